@@ -0,0 +1,45 @@
+// one sphere at a time against 8 at a time, on the small spheres of `random_scene`
+// run with `cargo bench --bench packet`
+
+use std::rc::Rc;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use ray_tracing_rust::camera::Camera;
+use ray_tracing_rust::hittable::Hittable;
+use ray_tracing_rust::hittable_list::HittableList;
+use ray_tracing_rust::ray::Ray;
+use ray_tracing_rust::scene;
+use ray_tracing_rust::vec3::{Point3, Vec3};
+use ray_tracing_rust::SphereSet;
+
+// the camera rays of a 64 x 40 image with the framing of `main.rs`, row by row
+fn camera_rays() -> Vec<Ray> {
+    let camera = Camera::new(Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 20.0, 1.5, 0.1, 10.0);
+    let (width, height) = (64, 40);
+    (0..height).flat_map(|j| (0..width).map(move |i| (i, j)))
+        .map(|(i, j)| camera.get_ray(i as f32 / (width - 1) as f32, j as f32 / (height - 1) as f32))
+        .collect()
+}
+
+fn bench_spheres(c: &mut Criterion) {
+    let spheres = scene::random_spheres();
+    let mut list = HittableList::new();
+    for sphere in spheres.iter() {
+        list.add(Rc::new(sphere.clone()));
+    }
+    let set = SphereSet::new(spheres);
+    let rays = camera_rays();
+
+    let mut group = c.benchmark_group("random_scene spheres");
+    group.bench_function("list", |b| b.iter(|| {
+        rays.iter().filter(|r| list.hit(black_box(r), 0.001, f32::MAX).is_some()).count()
+    }));
+    group.bench_function("set", |b| b.iter(|| {
+        rays.iter().filter(|r| set.hit(black_box(r), 0.001, f32::MAX).is_some()).count()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, bench_spheres);
+criterion_main!(benches);
@@ -0,0 +1,100 @@
+// the hot paths of the renderer, from vector math up to a whole (small) image
+// run with `cargo bench --bench renderer`, criterion compares each run to the previous one
+// everything random is seeded, so the scenes and the rays are the same from run to run
+
+use std::rc::Rc;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use ray_tracing_rust::camera::Camera;
+use ray_tracing_rust::framebuffer::Framebuffer;
+use ray_tracing_rust::hittable::{HitRecord, Hittable};
+use ray_tracing_rust::material::{Dielectric, Lambertian, Material, Metal, SurfaceMapped};
+use ray_tracing_rust::ray::Ray;
+use ray_tracing_rust::render::{self, RenderSettings};
+use ray_tracing_rust::scene;
+use ray_tracing_rust::sphere::Sphere;
+use ray_tracing_rust::spectrum::Dispersion;
+use ray_tracing_rust::texture::{ImageTexture, SurfaceMap};
+use ray_tracing_rust::vec3::{Color, Point3, Vec3};
+
+fn camera() -> Camera {
+    Camera::new(Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 20.0, 1.5, 0.1, 10.0)
+}
+
+// a ray from the camera of `main.rs` towards the big spheres
+fn ray() -> Ray {
+    Ray { origin: Point3::new(13.0, 2.0, 3.0), direction: Vec3::new(-13.0, -1.0, -3.0) }
+}
+
+fn bench_vec3(c: &mut Criterion) {
+    let a = Vec3::new(1.0, 2.0, 3.0);
+    let b = Vec3::new(-0.5, 0.25, 4.0);
+    let mut group = c.benchmark_group("vec3");
+    group.bench_function("add", |bench| bench.iter(|| black_box(a) + black_box(b)));
+    group.bench_function("dot", |bench| bench.iter(|| black_box(a).dot(black_box(b))));
+    group.bench_function("cross", |bench| bench.iter(|| black_box(a).cross(black_box(b))));
+    group.bench_function("unit_vector", |bench| bench.iter(|| black_box(a).unit_vector()));
+    group.bench_function("random_unit_vector", |bench| bench.iter(Vec3::random_unit_vector));
+    group.finish();
+}
+
+fn bench_hit(c: &mut Criterion) {
+    let material = Rc::new(Lambertian { albedo: Color::new(0.5, 0.5, 0.5) });
+    let sphere = Sphere { center: Point3::new(0.0, 1.0, 0.0), radius: 1.0, material };
+    ray_tracing_rust::seed(1);
+    let world = scene::random_scene();
+    let r = ray();
+
+    let mut group = c.benchmark_group("hit");
+    group.bench_function("sphere", |bench| bench.iter(|| sphere.hit(black_box(&r), 0.001, f32::MAX)));
+    group.bench_function("random_scene", |bench| bench.iter(|| world.hit(black_box(&r), 0.001, f32::MAX)));
+    group.finish();
+}
+
+fn bench_scatter(c: &mut Criterion) {
+    let lambertian: Rc<dyn Material> = Rc::new(Lambertian { albedo: Color::new(0.5, 0.5, 0.5) });
+    let metal: Rc<dyn Material> = Rc::new(Metal { albedo: Color::new(0.7, 0.6, 0.5), fuzz: 0.3 });
+    let glass: Rc<dyn Material> = Rc::new(Dielectric { ir: 1.5, dispersion: Some(Dispersion::bk7()) });
+    let mut bumps = Framebuffer::new(16, 16);
+    for (i, pixel) in bumps.pixels.iter_mut().enumerate() {
+        let h = ((i * 7) % 16) as f32 / 16.0;
+        *pixel = Color::new(h, h, h);
+    }
+    let bumpy: Rc<dyn Material> = Rc::new(SurfaceMapped {
+        material: lambertian.clone(),
+        map: SurfaceMap::Bump { texture: ImageTexture::new(bumps), scale: 0.02 }
+    });
+
+    let mut group = c.benchmark_group("scatter");
+    for (name, material) in [("lambertian", lambertian), ("metal", metal), ("dielectric", glass), ("surface_mapped", bumpy)] {
+        let r = ray();
+        let sphere = Sphere { center: Point3::new(0.0, 1.0, 0.0), radius: 1.0, material: material.clone() };
+        let rec: HitRecord = sphere.hit(&r, 0.001, f32::MAX).unwrap();
+        group.bench_function(name, |bench| bench.iter(|| material.scatter(black_box(&r), black_box(&rec))));
+    }
+    group.finish();
+}
+
+fn bench_render(c: &mut Criterion) {
+    let settings = RenderSettings {
+        image_width: 60,
+        image_height: 40,
+        samples_per_pixel: 4,
+        max_depth: 10,
+        ..Default::default()
+    };
+    let camera = camera();
+
+    let mut group = c.benchmark_group("render");
+    group.sample_size(10);
+    group.bench_function("random_scene", |bench| bench.iter(|| {
+        ray_tracing_rust::seed(1);
+        let world = scene::random_scene();
+        render::render(&camera, &world, &settings)
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, bench_vec3, bench_hit, bench_scatter, bench_render);
+criterion_main!(benches);
@@ -0,0 +1,189 @@
+use std::f32::consts::PI;
+
+use crate::vec3::{Point3, Vec3};
+
+// the animatable camera parameters at one frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    pub frame: f32,
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vfov: f32,
+    pub focus_dist: f32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // Catmull-Rom spline through the keyframes, eases the motion around each keyframe
+    Smooth
+}
+
+// keyframes sorted by frame, frames before the first or after the last keyframe hold still
+pub struct CameraAnimation {
+    pub keyframes: Vec<CameraKeyframe>,
+    pub interpolation: Interpolation
+}
+
+impl CameraAnimation {
+    pub fn new(mut keyframes: Vec<CameraKeyframe>, interpolation: Interpolation) -> CameraAnimation {
+        keyframes.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+        CameraAnimation { keyframes, interpolation }
+    }
+
+    // one keyframe per line: `frame from_x from_y from_z at_x at_y at_z vfov focus_dist`
+    // empty lines and everything after a '#' are ignored
+    pub fn parse(text: &str, interpolation: Interpolation) -> Result<CameraAnimation, String> {
+        let mut keyframes = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values: Result<Vec<f32>, _> = line.split_whitespace().map(|v| v.parse::<f32>()).collect();
+            let values = values.map_err(|_| format!("line {}: expected numbers", number + 1))?;
+            if values.len() != 9 {
+                return Err(format!("line {}: expected 9 values, got {}", number + 1, values.len()));
+            }
+            keyframes.push(CameraKeyframe {
+                frame: values[0],
+                look_from: Point3::new(values[1], values[2], values[3]),
+                look_at: Point3::new(values[4], values[5], values[6]),
+                vfov: values[7],
+                focus_dist: values[8]
+            });
+        }
+        if keyframes.is_empty() {
+            return Err("no keyframes".to_string());
+        }
+        Ok(CameraAnimation::new(keyframes, interpolation))
+    }
+
+    // a full circle around look_at over `frames` frames, keeping the height and the distance of look_from
+    // the last keyframe is the first one again, so frames start..start + frames loop seamlessly
+    pub fn turntable(start: &CameraKeyframe, frames: u32) -> CameraAnimation {
+        let offset = start.look_from - start.look_at;
+        let keyframes = (0..=frames).map(|i| {
+            let angle = 2.0 * PI * i as f32 / frames as f32;
+            let (sin, cos) = angle.sin_cos();
+            let rotated = Vec3::new(offset.x * cos + offset.z * sin, offset.y, -offset.x * sin + offset.z * cos);
+            CameraKeyframe { frame: start.frame + i as f32, look_from: start.look_at + rotated, ..*start }
+        }).collect();
+        CameraAnimation::new(keyframes, Interpolation::Linear)
+    }
+
+    pub fn first_frame(&self) -> f32 {
+        self.keyframes[0].frame
+    }
+
+    pub fn last_frame(&self) -> f32 {
+        self.keyframes[self.keyframes.len() - 1].frame
+    }
+
+    pub fn evaluate(&self, frame: f32) -> CameraKeyframe {
+        let keys = &self.keyframes;
+        if frame <= self.first_frame() {
+            return CameraKeyframe { frame, ..keys[0] };
+        }
+        if frame >= self.last_frame() {
+            return CameraKeyframe { frame, ..keys[keys.len() - 1] };
+        }
+
+        // keys[i] <= frame < keys[i + 1]
+        let i = keys.partition_point(|k| k.frame <= frame) - 1;
+        let (k1, k2) = (&keys[i], &keys[i + 1]);
+        let t = (frame - k1.frame) / (k2.frame - k1.frame);
+
+        match self.interpolation {
+            Interpolation::Linear => CameraKeyframe {
+                frame,
+                look_from: lerp(k1.look_from, k2.look_from, t),
+                look_at: lerp(k1.look_at, k2.look_at, t),
+                vfov: k1.vfov + (k2.vfov - k1.vfov) * t,
+                focus_dist: k1.focus_dist + (k2.focus_dist - k1.focus_dist) * t
+            },
+            Interpolation::Smooth => {
+                // the end points are repeated, so the spline starts and stops at the first and last keyframe
+                let k0 = &keys[i.saturating_sub(1)];
+                let k3 = &keys[(i + 2).min(keys.len() - 1)];
+                // vfov and focus distance ride along in one vector
+                let scalars = |k: &CameraKeyframe| Vec3::new(k.vfov, k.focus_dist, 0.0);
+                let lens = catmull_rom(scalars(k0), scalars(k1), scalars(k2), scalars(k3), t);
+                CameraKeyframe {
+                    frame,
+                    look_from: catmull_rom(k0.look_from, k1.look_from, k2.look_from, k3.look_from, t),
+                    look_at: catmull_rom(k0.look_at, k1.look_at, k2.look_at, k3.look_at, t),
+                    vfov: lens.x,
+                    focus_dist: lens.y
+                }
+            }
+        }
+    }
+}
+
+fn lerp(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    (1.0 - t) * a + t * b
+}
+
+// uniform Catmull-Rom spline between p1 and p2
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1 + t * (p2 - p0) + t2 * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) + t3 * (3.0 * p1 - p0 - 3.0 * p2 + p3))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::animation::*;
+
+    const KEYS: &str = "
+        # frame  look_from  look_at  vfov  focus
+        1   0 0 0   0 0 -1   20  10
+        11  10 0 0  0 0 -1   40  20
+        21  10 10 0 0 0 -1   40  20
+    ";
+
+    #[test]
+    fn test_parse_and_hold_ends() {
+        let animation = CameraAnimation::parse(KEYS, Interpolation::Linear).unwrap();
+        assert_eq!(animation.keyframes.len(), 3);
+        assert_eq!(animation.first_frame(), 1.0);
+        assert_eq!(animation.last_frame(), 21.0);
+        assert_eq!(animation.evaluate(-5.0).look_from, Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(animation.evaluate(30.0).look_from, Point3::new(10.0, 10.0, 0.0));
+        assert!(CameraAnimation::parse("1 2 3", Interpolation::Linear).is_err());
+    }
+
+    #[test]
+    fn test_interpolation_passes_through_keyframes() {
+        for interpolation in [Interpolation::Linear, Interpolation::Smooth] {
+            let animation = CameraAnimation::parse(KEYS, interpolation).unwrap();
+            for key in animation.keyframes.iter() {
+                let evaluated = animation.evaluate(key.frame);
+                assert!((evaluated.look_from - key.look_from).length() < 1e-4);
+                assert!((evaluated.vfov - key.vfov).abs() < 1e-4);
+            }
+        }
+        let linear = CameraAnimation::parse(KEYS, Interpolation::Linear).unwrap().evaluate(6.0);
+        assert_eq!(linear.look_from, Point3::new(5.0, 0.0, 0.0));
+        assert_eq!(linear.vfov, 30.0);
+        assert_eq!(linear.focus_dist, 15.0);
+    }
+
+    #[test]
+    fn test_turntable_keeps_distance() {
+        let start = CameraKeyframe { frame: 1.0, look_from: Point3::new(13.0, 2.0, 3.0), look_at: Point3::new(0.0, 0.0, 0.0),
+            vfov: 20.0, focus_dist: 10.0 };
+        let animation = CameraAnimation::turntable(&start, 36);
+        assert_eq!(animation.first_frame(), 1.0);
+        assert_eq!(animation.last_frame(), 37.0);
+        for frame in 1..=37 {
+            let key = animation.evaluate(frame as f32);
+            assert!(((key.look_from - key.look_at).length() - (start.look_from - start.look_at).length()).abs() < 1e-3);
+            assert_eq!(key.look_from.y, 2.0);
+        }
+        assert!((animation.evaluate(37.0).look_from - start.look_from).length() < 1e-3);
+    }
+}
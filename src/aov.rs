@@ -0,0 +1,221 @@
+use std::io;
+
+use crate::camera::CameraModel;
+use crate::framebuffer::{Framebuffer, Region};
+use crate::hittable::Hittable;
+use crate::image_io::{self, ExrChannel};
+use crate::ray::Ray;
+use crate::stats;
+use crate::render::{background, hit_color, ray_color};
+use crate::vec3::{Color, Vec3};
+
+// how the auxiliary buffers (render passes) should be written out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AovOutput {
+    // one pfm file per pass next to the beauty image
+    Files,
+    // a single multi-layer exr holding the beauty image and every pass
+    Exr
+}
+
+// what a single camera ray contributes to each pass
+pub struct AovSample {
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub depth: f32,
+    // 0 is the background, objects start from 1
+    pub object_id: f32,
+    // light that reached the camera after at most one bounce
+    pub direct: Color,
+    // light that bounced around more than once, direct + indirect is the beauty color
+    pub indirect: Color
+}
+
+#[derive(Clone)]
+pub struct AovBuffers {
+    pub albedo: Framebuffer,
+    pub normal: Framebuffer,
+    // depth and object id are single channel, only `x` is used
+    pub depth: Framebuffer,
+    pub object_id: Framebuffer,
+    pub direct: Framebuffer,
+    pub indirect: Framebuffer
+}
+
+// same as `ray_color`, but also keeps what the first hit looked like
+// and splits the light into the direct and the indirect part
+pub fn trace(r: &Ray, world: &impl Hittable, depth: i32, camera: &dyn CameraModel) -> AovSample {
+    let mut sample = AovSample {
+        albedo: Color::white(),
+        normal: Vec3::new_empty(),
+        depth: f32::INFINITY,
+        object_id: 0.0,
+        direct: Color::white(),
+        indirect: Color::white()
+    };
+
+    if depth <= 0 {
+        sample.direct = ray_color(r, world, depth);
+        return sample;
+    }
+
+    stats::count(|c| c.rays += 1);
+    match world.hit(r, 0.001, f32::MAX) {
+        Some(rec) => {
+            sample.albedo = rec.material.albedo();
+            sample.normal = rec.normal;
+            sample.depth = camera.depth(&rec.p);
+            sample.object_id = rec.object_id as f32 + 1.0;
+
+            if let Some((attenuation, scattered)) = rec.material.scatter(r, &rec) {
+                if depth > 1 {
+                    stats::count(|c| c.shadow_rays += 1);
+                    match world.hit(&scattered, 0.001, f32::MAX) {
+                        // the scattered ray goes straight to the sky, so this is direct lighting
+                        None => sample.direct = attenuation * background(&scattered),
+                        // no need to trace it again to follow the light further
+                        Some(hit) => sample.indirect = attenuation * hit_color(&scattered, &hit, world, depth - 1)
+                    }
+                } else {
+                    // the last bounce, no more light is gathered
+                    sample.indirect = Color::black();
+                }
+            }
+        },
+        None => sample.direct = background(r)
+    }
+
+    sample
+}
+
+impl AovBuffers {
+    pub fn new(width: usize, height: usize) -> AovBuffers {
+        AovBuffers {
+            albedo: Framebuffer::new(width, height),
+            normal: Framebuffer::new(width, height),
+            depth: Framebuffer::new(width, height),
+            object_id: Framebuffer::new(width, height),
+            direct: Framebuffer::new(width, height),
+            indirect: Framebuffer::new(width, height)
+        }
+    }
+
+    // sum up the sample into pixel (x, y)
+    // depth and object id can't be averaged, so they're taken from the first sample only
+    pub fn accumulate(&mut self, x: usize, y: usize, sample: &AovSample, first_sample: bool) {
+        let i = y * self.albedo.width + x;
+        self.albedo.pixels[i] += sample.albedo;
+        self.normal.pixels[i] += sample.normal;
+        self.direct.pixels[i] += sample.direct;
+        self.indirect.pixels[i] += sample.indirect;
+        if first_sample {
+            self.depth.pixels[i] = Vec3::new(sample.depth, sample.depth, sample.depth);
+            self.object_id.pixels[i] = Vec3::new(sample.object_id, sample.object_id, sample.object_id);
+        }
+    }
+
+    // turn the accumulated sums into averages, `row_samples` has the number of samples of every row
+    pub fn resolve(&mut self, row_samples: &[i32]) {
+        let width = self.albedo.width;
+        for buffer in [&mut self.albedo, &mut self.normal, &mut self.direct, &mut self.indirect] {
+            for (row, samples) in buffer.pixels.chunks_mut(width).zip(row_samples) {
+                let scale = 1.0 / (*samples).max(1) as f32;
+                row.iter_mut().for_each(|pixel| *pixel *= scale);
+            }
+        }
+        for pixel in self.normal.pixels.iter_mut() {
+            if !pixel.near_zero() {
+                *pixel = pixel.unit_vector();
+            }
+        }
+    }
+
+    // the passes of a region put back into the whole image, see `Framebuffer::pad`
+    pub fn pad(&self, region: &Region, width: usize, height: usize) -> AovBuffers {
+        AovBuffers {
+            albedo: self.albedo.pad(region, width, height),
+            normal: self.normal.pad(region, width, height),
+            depth: self.depth.pad(region, width, height),
+            object_id: self.object_id.pad(region, width, height),
+            direct: self.direct.pad(region, width, height),
+            indirect: self.indirect.pad(region, width, height)
+        }
+    }
+
+    // write every pass to `<stem>.<pass>.pfm`
+    pub fn write_files(&self, stem: &str) -> io::Result<()> {
+        image_io::write_pfm(&format!("{}.albedo.pfm", stem), &self.albedo)?;
+        image_io::write_pfm(&format!("{}.normal.pfm", stem), &self.normal)?;
+        image_io::write_pfm_mono(&format!("{}.depth.pfm", stem), &self.depth)?;
+        image_io::write_pfm_mono(&format!("{}.object_id.pfm", stem), &self.object_id)?;
+        image_io::write_pfm(&format!("{}.direct.pfm", stem), &self.direct)?;
+        image_io::write_pfm(&format!("{}.indirect.pfm", stem), &self.indirect)
+    }
+
+    // write the beauty image and every pass as layers of one exr file
+    pub fn write_exr(&self, path: &str, beauty: &Framebuffer) -> io::Result<()> {
+        let mut channels = Vec::new();
+        channels.extend(ExrChannel::rgb("", beauty));
+        channels.extend(ExrChannel::rgb("albedo.", &self.albedo));
+        channels.extend(ExrChannel::xyz("normal.", &self.normal));
+        channels.push(ExrChannel::mono("Z", &self.depth));
+        channels.push(ExrChannel::mono("object_id", &self.object_id));
+        channels.extend(ExrChannel::rgb("direct.", &self.direct));
+        channels.extend(ExrChannel::rgb("indirect.", &self.indirect));
+        image_io::write_exr(path, beauty.width, beauty.height, channels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::rc::Rc;
+
+    use crate::aov::*;
+    use crate::camera::Camera;
+    use crate::hittable_list::HittableList;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::Point3;
+
+    fn sample(albedo: Color, normal: Vec3, depth: f32) -> AovSample {
+        AovSample { albedo, normal, depth, object_id: 3.0, direct: Color::new(0.5, 0.5, 0.5), indirect: Color::new_empty() }
+    }
+
+    #[test]
+    fn test_accumulate_and_resolve() {
+        let mut buffers = AovBuffers::new(2, 1);
+        buffers.accumulate(1, 0, &sample(Color::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 2.0), true);
+        buffers.accumulate(1, 0, &sample(Color::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0), 5.0), false);
+        buffers.resolve(&[2]);
+
+        assert_eq!(buffers.albedo.get(1, 0), Color::new(0.5, 0.0, 0.5));
+        assert_eq!(buffers.direct.get(1, 0), Color::new(0.5, 0.5, 0.5));
+        // the averaged normal is a unit vector again
+        assert!((buffers.normal.get(1, 0).length() - 1.0).abs() < 1e-6);
+        assert!((buffers.normal.get(1, 0).x - buffers.normal.get(1, 0).z).abs() < 1e-6);
+        // from the first sample only
+        assert_eq!(buffers.depth.get(1, 0).x, 2.0);
+        assert_eq!(buffers.object_id.get(1, 0).x, 3.0);
+        // nothing hit, nothing to normalize
+        assert_eq!(buffers.normal.get(0, 0), Vec3::new_empty());
+    }
+
+    #[test]
+    fn test_shadow_rays() {
+        let mut world = HittableList { objects: Vec::new() };
+        world.add(Rc::new(Sphere { center: Point3::new(0.0, -100.5, -1.0), radius: 100.0,
+            material: Rc::new(Lambertian { albedo: Color::new(0.5, 0.5, 0.5) }) }));
+        let camera = Camera::new(Point3::new(0.0, 0.0, 1.0), Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0), 60.0, 1.0, 0.0, 2.0);
+        let down = Ray {origin: Point3::new(0.0, 0.0, 0.0), direction: Vec3::new(0.0, -1.0, 0.0)};
+
+        // on the last bounce there's no shadow ray to trace
+        stats::take();
+        trace(&down, &world, 1, &camera);
+        let counters = stats::take();
+        assert_eq!((counters.rays, counters.shadow_rays), (1, 0));
+        trace(&down, &world, 2, &camera);
+        assert_eq!(stats::take().shadow_rays, 1);
+    }
+}
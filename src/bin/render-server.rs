@@ -0,0 +1,12 @@
+use std::io;
+use std::net::TcpListener;
+
+use ray_tracing_rust::serve_http;
+
+// `render-server [address]`, the http api is described in `src/server.rs`
+fn main() -> io::Result<()> {
+    let address = std::env::args().nth(1).unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let listener = TcpListener::bind(&address)?;
+    eprintln!("render server listening on http://{}", listener.local_addr()?);
+    serve_http(listener)
+}
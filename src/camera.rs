@@ -1,6 +1,21 @@
+use std::f32::consts::PI;
+
+use crate::framebuffer::Framebuffer;
+use crate::hittable::Hittable;
 use crate::vec3::*;
 use crate::ray::Ray;
+use crate::rng;
+
+// a projection from the image plane into the scene
+// u and v go from 0 to 1, (0, 0) is the lower left corner of the image
+pub trait CameraModel {
+    fn get_ray(&self, u: f32, v: f32) -> Ray;
+
+    // how far p is from the camera, used for the depth pass
+    fn depth(&self, p: &Point3) -> f32;
+}
 
+// the usual pinhole / thin lens perspective camera
 pub struct Camera {
     origin: Point3,
     lower_left_corner: Point3,
@@ -9,7 +24,76 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     w: Vec3,
-    lens_radius: f32
+    lens_radius: f32,
+    aperture_shape: ApertureShape
+}
+
+// the shape of the lens opening, this is what out of focus highlights (bokeh) look like
+#[derive(Clone, Default)]
+pub enum ApertureShape {
+    #[default]
+    Circle,
+    // a regular polygon made by the aperture blades, rotation is in degrees
+    Polygon { blades: u32, rotation: f32 },
+    // any shape, brighter pixels of the mask let more light through
+    Image(ApertureMask)
+}
+
+// a grayscale aperture image turned into a distribution we can pick lens positions from
+#[derive(Clone)]
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    // running sum of the pixel brightness, for picking a pixel with a binary search
+    cdf: Vec<f32>
+}
+
+// lens and exposure settings of a real camera, lengths are in millimeters
+// and the scene is assumed to be in meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhysicalLens {
+    pub focal_length: f32,
+    pub sensor_width: f32,
+    pub sensor_height: f32,
+    pub f_stop: f32,
+    // in seconds
+    pub shutter: f32,
+    pub iso: f32
+}
+
+// parallel rays, no perspective, for architectural elevations and plans
+pub struct OrthographicCamera {
+    lower_left_corner: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    w: Vec3
+}
+
+// 360 x 180 degree latitude-longitude panorama, use a 2:1 image for VR viewers
+pub struct EquirectangularCamera {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3
+}
+
+// equidistant fisheye, the angle from the view direction grows linearly with the distance from the image center
+pub struct FisheyeCamera {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    // half of the field of view, in radians, reached at the top and bottom edge of the image
+    half_fov: f32,
+    aspect_ratio: f32
+}
+
+// w points backwards (away from look_at), u to the right and v up
+fn orthonormal_basis(look_from: Point3, look_at: Point3, vup: Vec3) -> (Vec3, Vec3, Vec3) {
+    let w = (look_from - look_at).unit_vector();
+    let u = vup.cross(w).unit_vector();
+    let v = w.cross(u);
+    (u, v, w)
 }
 
 impl Camera {
@@ -29,10 +113,8 @@ impl Camera {
         let viewport_height: f32 = 2.0 * h;
         let viewport_width: f32 = aspect_ratio * viewport_height;
 
-        let w = (look_from - look_at).unit_vector();
-        let u = vup.cross(w).unit_vector();
-        let v = w.cross(u);
-    
+        let (u, v, w) = orthonormal_basis(look_from, look_at, vup);
+
         let origin: Point3 = look_from;
         let horizontal: Vec3 = focus_dist * viewport_width * u;
         let vertical: Vec3 = focus_dist * viewport_height * v;
@@ -40,14 +122,292 @@ impl Camera {
 
         // larget the lens_radius is, the more blur out of focus
         let lens_radius = aperture / 2.0;
-        Camera { origin: origin, lower_left_corner: lower_left_corner, horizontal: horizontal,
-            vertical: vertical, u: u, v: v, w: w, lens_radius: lens_radius }
+        Camera { origin, lower_left_corner, horizontal,
+            vertical, u, v, w, lens_radius, aperture_shape: ApertureShape::Circle }
+    }
+
+    // a camera set up from the lens, the field of view and aperture follow from the focal length and f-stop
+    pub fn from_physical(
+        look_from: Point3,
+        look_at: Point3,
+        vup: Vec3,
+        lens: &PhysicalLens,
+        aspect_ratio: f32,
+        focus_dist: f32
+    ) -> Camera {
+        Camera::new(look_from, look_at, vup, lens.vfov(aspect_ratio), aspect_ratio, lens.aperture(), focus_dist)
     }
 
+    pub fn set_aperture_shape(&mut self, aperture_shape: ApertureShape) {
+        self.aperture_shape = aperture_shape;
+    }
+
+    // these were methods of `Camera` before there was `CameraModel`, so they work without the trait in scope too
     pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+        let rd = self.lens_radius * self.aperture_shape.sample();
         let offset = self.u * rd.x + self.v * rd.y;
         Ray {origin: self.origin + offset,
             direction: self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin - offset}
     }
-}
\ No newline at end of file
+
+    // distance from the camera to p along the viewing direction (z-depth)
+    pub fn depth(&self, p: &Point3) -> f32 {
+        (*p - self.origin).dot(-&self.w)
+    }
+}
+
+impl CameraModel for Camera {
+    fn get_ray(&self, u: f32, v: f32) -> Ray {
+        Camera::get_ray(self, u, v)
+    }
+
+    fn depth(&self, p: &Point3) -> f32 {
+        Camera::depth(self, p)
+    }
+}
+
+impl ApertureShape {
+    // a random point on the lens, within [-1, 1] on x and y (z is always 0)
+    pub fn sample(&self) -> Vec3 {
+        match self {
+            ApertureShape::Circle => Vec3::random_in_unit_disk(),
+            ApertureShape::Polygon { blades, rotation } => sample_polygon(*blades, rotation.to_radians()),
+            ApertureShape::Image(mask) => mask.sample()
+        }
+    }
+}
+
+// the polygon is inscribed in the unit circle and split into one triangle per blade,
+// they all have the same area so we can pick one and then a point inside it uniformly
+fn sample_polygon(blades: u32, rotation: f32) -> Vec3 {
+    let blades = blades.max(3);
+    let step = 2.0 * PI / blades as f32;
+    let i = rng::gen_range(0..blades) as f32;
+    let a = Vec3::new((rotation + i * step).cos(), (rotation + i * step).sin(), 0.0);
+    let b = Vec3::new((rotation + (i + 1.0) * step).cos(), (rotation + (i + 1.0) * step).sin(), 0.0);
+
+    // uniform point in the triangle (center, a, b)
+    let (mut s, mut t) = (rng::random::<f32>(), rng::random::<f32>());
+    if s + t > 1.0 {
+        s = 1.0 - s;
+        t = 1.0 - t;
+    }
+    s * a + t * b
+}
+
+impl ApertureMask {
+    // returns None when the image is completely black and no light could get through
+    pub fn new(image: &Framebuffer) -> Option<ApertureMask> {
+        let mut cdf = Vec::with_capacity(image.pixels.len());
+        let mut sum = 0.0;
+        for pixel in image.pixels.iter() {
+            sum += ((pixel.x + pixel.y + pixel.z) / 3.0).max(0.0);
+            cdf.push(sum);
+        }
+        if sum <= 0.0 {
+            return None;
+        }
+        Some(ApertureMask { width: image.width, height: image.height, cdf })
+    }
+
+    // the image is stretched over the square around the unit disk
+    fn sample(&self) -> Vec3 {
+        let total = self.cdf[self.cdf.len() - 1];
+        let target = rng::random::<f32>() * total;
+        let index = self.cdf.partition_point(|sum| *sum <= target).min(self.cdf.len() - 1);
+        let x = (index % self.width) as f32 + rng::random::<f32>();
+        let y = (index / self.width) as f32 + rng::random::<f32>();
+        // image rows go from the top, the lens v axis goes up
+        Vec3::new(2.0 * x / self.width as f32 - 1.0, 1.0 - 2.0 * y / self.height as f32, 0.0)
+    }
+}
+
+impl Default for PhysicalLens {
+    // a 50mm lens on a full frame sensor, exposed for a sunny day
+    fn default() -> Self {
+        PhysicalLens { focal_length: 50.0, sensor_width: 36.0, sensor_height: 24.0, f_stop: 16.0, shutter: 0.01, iso: 100.0 }
+    }
+}
+
+impl PhysicalLens {
+    // vertical field of view in degrees, the sensor is fit into the image
+    pub fn vfov(&self, aspect_ratio: f32) -> f32 {
+        let height = self.sensor_height.min(self.sensor_width / aspect_ratio);
+        2.0 * (height / (2.0 * self.focal_length)).atan().to_degrees()
+    }
+
+    // diameter of the lens opening in scene units (meters)
+    pub fn aperture(&self) -> f32 {
+        self.focal_length / self.f_stop / 1000.0
+    }
+
+    // exposure adjustment in stops, relative to the "sunny 16" rule (f/16, 1/100s at ISO 100)
+    // which is about right for the sky brightness of our scenes
+    pub fn exposure(&self) -> f32 {
+        let sunny_16 = 0.01 / (16.0 * 16.0);
+        (self.shutter * self.iso / 100.0 / (self.f_stop * self.f_stop) / sunny_16).log2()
+    }
+}
+
+// distance along the view direction to whatever is in the center of the image,
+// None when the center of the image only sees the sky
+pub fn autofocus(look_from: Point3, look_at: Point3, world: &impl Hittable) -> Option<f32> {
+    let direction = (look_at - look_from).unit_vector();
+    world.hit(&Ray {origin: look_from, direction}, 0.001, f32::MAX).map(|rec| rec.t)
+}
+
+impl OrthographicCamera {
+    // view_height is the height of the visible area in world units
+    pub fn new(look_from: Point3, look_at: Point3, vup: Vec3, view_height: f32, aspect_ratio: f32) -> OrthographicCamera {
+        let (u, v, w) = orthonormal_basis(look_from, look_at, vup);
+        let horizontal = aspect_ratio * view_height * u;
+        let vertical = view_height * v;
+        let lower_left_corner = look_from - horizontal / 2.0 - vertical / 2.0;
+        OrthographicCamera { lower_left_corner, horizontal, vertical, w }
+    }
+}
+
+impl CameraModel for OrthographicCamera {
+    // every ray starts on the image plane and goes straight ahead
+    fn get_ray(&self, u: f32, v: f32) -> Ray {
+        Ray {origin: self.lower_left_corner + u * self.horizontal + v * self.vertical, direction: -&self.w}
+    }
+
+    fn depth(&self, p: &Point3) -> f32 {
+        (*p - self.lower_left_corner).dot(-&self.w)
+    }
+}
+
+impl EquirectangularCamera {
+    // look_at ends up in the center of the image
+    pub fn new(look_from: Point3, look_at: Point3, vup: Vec3) -> EquirectangularCamera {
+        let (u, v, w) = orthonormal_basis(look_from, look_at, vup);
+        EquirectangularCamera { origin: look_from, u, v, w }
+    }
+}
+
+impl CameraModel for EquirectangularCamera {
+    // u is the longitude from -180 to 180 degrees, v the latitude from -90 to 90 degrees
+    fn get_ray(&self, u: f32, v: f32) -> Ray {
+        let phi = (u - 0.5) * 2.0 * PI;
+        let theta = (v - 0.5) * PI;
+        let direction = theta.cos() * phi.sin() * self.u + theta.sin() * self.v - theta.cos() * phi.cos() * self.w;
+        Ray {origin: self.origin, direction}
+    }
+
+    // there's no single view direction, so this is the distance from the camera
+    fn depth(&self, p: &Point3) -> f32 {
+        (*p - self.origin).length()
+    }
+}
+
+impl FisheyeCamera {
+    // fov is in degrees and covers the image height, 180 gives a full hemisphere
+    pub fn new(look_from: Point3, look_at: Point3, vup: Vec3, fov: f32, aspect_ratio: f32) -> FisheyeCamera {
+        let (u, v, w) = orthonormal_basis(look_from, look_at, vup);
+        FisheyeCamera { origin: look_from, u, v, w, half_fov: fov.to_radians() / 2.0, aspect_ratio }
+    }
+}
+
+impl CameraModel for FisheyeCamera {
+    fn get_ray(&self, u: f32, v: f32) -> Ray {
+        // position on the image relative to the center, the top and bottom edges are at distance 1
+        let x = (2.0 * u - 1.0) * self.aspect_ratio;
+        let y = 2.0 * v - 1.0;
+        let r = (x * x + y * y).sqrt();
+        // past the image circle the projection keeps going until it looks straight back
+        let theta = (r * self.half_fov).min(PI);
+        let alpha = y.atan2(x);
+        let direction = theta.sin() * alpha.cos() * self.u + theta.sin() * alpha.sin() * self.v - theta.cos() * self.w;
+        Ray {origin: self.origin, direction}
+    }
+
+    fn depth(&self, p: &Point3) -> f32 {
+        (*p - self.origin).length()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::camera::*;
+
+    fn look_from() -> Point3 {
+        Point3::new(0.0, 0.0, 0.0)
+    }
+
+    fn look_at() -> Point3 {
+        Point3::new(0.0, 0.0, -1.0)
+    }
+
+    fn up() -> Vec3 {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_physical_lens() {
+        let lens = PhysicalLens::default();
+        assert!(lens.exposure().abs() < 1e-5);
+        assert!((lens.vfov(1.5) - 26.99).abs() < 0.01);
+        assert!((lens.aperture() - 0.003_125).abs() < 1e-6);
+
+        let brighter = PhysicalLens { f_stop: 8.0, ..lens };
+        assert!((brighter.exposure() - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_polygon_aperture_stays_in_unit_disk() {
+        let shape = ApertureShape::Polygon { blades: 6, rotation: 15.0 };
+        for _ in 0..1000 {
+            let p = shape.sample();
+            assert!(p.length() <= 1.0 + 1e-5);
+            assert_eq!(p.z, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_aperture_mask_only_samples_bright_pixels() {
+        let mut image = Framebuffer::new(4, 4);
+        // only the top right pixel is open
+        image.set(3, 0, Vec3::new(1.0, 1.0, 1.0));
+        let shape = ApertureShape::Image(ApertureMask::new(&image).unwrap());
+        for _ in 0..100 {
+            let p = shape.sample();
+            assert!(p.x >= 0.5 && p.y >= 0.5, "{:?}", p);
+        }
+        assert!(ApertureMask::new(&Framebuffer::new(2, 2)).is_none());
+    }
+
+    #[test]
+    fn test_orthographic_rays_are_parallel() {
+        let camera = OrthographicCamera::new(look_from(), look_at(), up(), 2.0, 2.0);
+        let corner = camera.get_ray(0.0, 0.0);
+        let center = camera.get_ray(0.5, 0.5);
+        assert_near(corner.direction, Vec3::new(0.0, 0.0, -1.0));
+        assert_near(center.direction, Vec3::new(0.0, 0.0, -1.0));
+        assert_near(corner.origin, Vec3::new(-2.0, -1.0, 0.0));
+        assert_near(center.origin, look_from());
+    }
+
+    #[test]
+    fn test_equirectangular_covers_the_sphere() {
+        let camera = EquirectangularCamera::new(look_from(), look_at(), up());
+        assert_near(camera.get_ray(0.5, 0.5).direction, Vec3::new(0.0, 0.0, -1.0));
+        assert_near(camera.get_ray(0.75, 0.5).direction, Vec3::new(1.0, 0.0, 0.0));
+        assert_near(camera.get_ray(0.0, 0.5).direction, Vec3::new(0.0, 0.0, 1.0));
+        assert_near(camera.get_ray(0.3, 1.0).direction, Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_fisheye_is_equidistant() {
+        let camera = FisheyeCamera::new(look_from(), look_at(), up(), 180.0, 1.0);
+        assert_near(camera.get_ray(0.5, 0.5).direction, Vec3::new(0.0, 0.0, -1.0));
+        assert_near(camera.get_ray(0.5, 1.0).direction, Vec3::new(0.0, 1.0, 0.0));
+        let halfway = camera.get_ray(0.75, 0.5).direction;
+        assert_near(halfway, Vec3::new((PI / 4.0).sin(), 0.0, -(PI / 4.0).cos()));
+    }
+}
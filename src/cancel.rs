@@ -0,0 +1,55 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+// stops a render early, the render loop looks at it before every scanline
+// clones share the flag: hand one to the render and cancel it with another one from a different thread
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    // counts as cancelled from then on
+    deadline: Option<Instant>
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    // the same flag, that also runs out at `deadline` (or the earlier deadline it already had)
+    pub fn with_deadline(&self, deadline: Instant) -> CancellationToken {
+        CancellationToken {
+            cancelled: self.cancelled.clone(),
+            deadline: Some(self.deadline.map_or(deadline, |earlier| earlier.min(deadline)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::cancel::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_cancel() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        let timed = token.with_deadline(Instant::now() + Duration::from_secs(3600));
+        assert!(!clone.is_cancelled() && !timed.is_cancelled());
+        std::thread::spawn(move || clone.cancel()).join().unwrap();
+        assert!(token.is_cancelled() && timed.is_cancelled());
+
+        let expired = CancellationToken::new().with_deadline(Instant::now());
+        assert!(expired.is_cancelled());
+        assert!(!CancellationToken::new().is_cancelled());
+    }
+}
@@ -1,21 +1,197 @@
 use crate::vec3::Color;
 
+// how the linear (scene referred) colors of the render are turned into display values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputTransform {
+    // in stops, every +1 doubles the brightness
+    pub exposure: f32,
+    pub tone_map: ToneMap,
+    pub color_space: ColorSpace
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    // cut everything above 1, highlights clip
+    Clamp,
+    // c / (1 + c), never reaches 1 but flattens the image
+    Reinhard,
+    // Stephen Hill's fit of the ACES RRT + sRGB ODT
+    AcesFilmic,
+    // Troy Sobotka's AgX with the default contrast look
+    Agx
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorSpace {
+    // Rec.709 primaries with the sRGB transfer function
+    Srgb,
+    // Rec.709 primaries with the BT.709 transfer function, for video
+    Rec709,
+    // P3 primaries with a D65 white point and the sRGB transfer function
+    DisplayP3
+}
+
+impl Default for OutputTransform {
+    fn default() -> Self {
+        OutputTransform { exposure: 0.0, tone_map: ToneMap::Clamp, color_space: ColorSpace::Srgb }
+    }
+}
+
+// row major 3x3 matrices
+type Mat3 = [[f32; 3]; 3];
+
+const REC709_TO_DISPLAY_P3: Mat3 = [
+    [0.822_462_1, 0.177_538, 0.0],
+    [0.033_194_1, 0.966_805_8, 0.0],
+    [0.017_082_7, 0.072_397_4, 0.910_519_9]
+];
+
+// sRGB => ACES AP1 (with the RRT saturation baked in) and back
+const ACES_INPUT: Mat3 = [
+    [0.597_19, 0.354_58, 0.048_23],
+    [0.076_00, 0.908_34, 0.015_66],
+    [0.028_40, 0.133_83, 0.837_77]
+];
+
+const ACES_OUTPUT: Mat3 = [
+    [1.604_75, -0.531_08, -0.073_67],
+    [-0.102_08, 1.108_13, -0.006_05],
+    [-0.003_27, -0.072_76, 1.076_02]
+];
+
+// the AgX inset and outset matrices, they pull the primaries in a bit before the curve
+// so very saturated colors desaturate towards white instead of skewing in hue
+const AGX_INSET: Mat3 = [
+    [0.842_479_06, 0.078_433_6, 0.079_223_745],
+    [0.042_328_242, 0.878_468_6, 0.079_166_13],
+    [0.042_375_655, 0.078_433_6, 0.879_143]
+];
+
+const AGX_OUTSET: Mat3 = [
+    [1.196_879, -0.098_020_88, -0.099_029_74],
+    [-0.052_896_85, 1.151_903_1, -0.098_961_18],
+    [-0.052_971_635, -0.098_043_45, 1.151_073_7]
+];
+
+impl OutputTransform {
+    // linear Rec.709 color in, encoded display values in [0, 1] out
+    pub fn apply(&self, color: &Color) -> Color {
+        let exposed = *color * 2.0_f32.powf(self.exposure);
+        let tone_mapped = match self.tone_map {
+            ToneMap::Clamp => exposed,
+            ToneMap::Reinhard => map_channels(exposed, |c| c / (1.0 + c)),
+            ToneMap::AcesFilmic => aces_filmic(exposed),
+            ToneMap::Agx => agx(exposed)
+        };
+
+        let display = match self.color_space {
+            ColorSpace::DisplayP3 => mul(&REC709_TO_DISPLAY_P3, tone_mapped),
+            ColorSpace::Srgb | ColorSpace::Rec709 => tone_mapped
+        };
+
+        map_channels(display, |c| {
+            let c = c.clamp(0.0, 1.0);
+            match self.color_space {
+                ColorSpace::Rec709 => rec709_oetf(c),
+                ColorSpace::Srgb | ColorSpace::DisplayP3 => srgb_oetf(c)
+            }
+        })
+    }
+}
+
 // color is the sum of all samples
 // samples_per_pixel is the number of samples
 // the final color to write is the avg of all samples
-pub fn write_color(buffer: &mut String, color: &Color, samples_per_pixel: i32) {
-    let mut ir: f32 = color.x / samples_per_pixel as f32;
-    let mut ig: f32 = color.y / samples_per_pixel as f32;
-    let mut ib: f32 = color.z / samples_per_pixel as f32;
-
-    // divide the color by the number of samples and gamma-correct for gamma=2.0 (sqrt)
-    ir = ir.sqrt();
-    ig = ig.sqrt();
-    ib = ib.sqrt();
-
-    buffer.push_str(
-        &format!("{} {} {}\n",
-        (256.0 * ir.clamp(0.0, 0.999)) as i32,
-        (256.0 * ig.clamp(0.0, 0.999)) as i32,
-        (256.0 * ib.clamp(0.0, 0.999)) as i32));
+pub fn write_color(buffer: &mut String, color: &Color, samples_per_pixel: i32, transform: &OutputTransform) {
+    let [r, g, b] = to_rgb8(&(*color / samples_per_pixel as f32), transform);
+    buffer.push_str(&format!("{} {} {}\n", r, g, b));
+}
+
+pub fn to_rgb8(color: &Color, transform: &OutputTransform) -> [u8; 3] {
+    let encoded = transform.apply(color);
+    [
+        (256.0 * encoded.x.clamp(0.0, 0.999)) as u8,
+        (256.0 * encoded.y.clamp(0.0, 0.999)) as u8,
+        (256.0 * encoded.z.clamp(0.0, 0.999)) as u8
+    ]
+}
+
+pub fn srgb_oetf(c: f32) -> f32 {
+    if c <= 0.003_130_8 { 12.92 * c } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+pub fn rec709_oetf(c: f32) -> f32 {
+    if c < 0.018 { 4.5 * c } else { 1.099 * c.powf(0.45) - 0.099 }
+}
+
+fn aces_filmic(color: Color) -> Color {
+    let v = mul(&ACES_INPUT, color);
+    let fitted = map_channels(v, |c| {
+        let a = c * (c + 0.024_578_6) - 0.000_090_537;
+        let b = c * (0.983_729 * c + 0.432_951) + 0.238_081;
+        a / b
+    });
+    mul(&ACES_OUTPUT, fitted)
+}
+
+fn agx(color: Color) -> Color {
+    const MIN_EV: f32 = -12.473_93;
+    const MAX_EV: f32 = 4.026_069;
+
+    let inset = mul(&AGX_INSET, color);
+    let curved = map_channels(inset, |c| {
+        // log encode, then a polynomial fit of the AgX sigmoid
+        let x = (c.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.002_32
+    });
+    // the curve outputs display encoded values, undo the 2.2 gamma so the oetf can be applied later
+    map_channels(mul(&AGX_OUTSET, curved), |c| c.max(0.0).powf(2.2))
+}
+
+fn mul(m: &Mat3, c: Color) -> Color {
+    Color::new(
+        m[0][0] * c.x + m[0][1] * c.y + m[0][2] * c.z,
+        m[1][0] * c.x + m[1][1] * c.y + m[1][2] * c.z,
+        m[2][0] * c.x + m[2][1] * c.y + m[2][2] * c.z
+    )
+}
+
+fn map_channels(c: Color, f: impl Fn(f32) -> f32) -> Color {
+    Color::new(f(c.x), f(c.y), f(c.z))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::color_utils::*;
+
+    #[test]
+    fn test_srgb_oetf() {
+        assert_eq!(srgb_oetf(0.0), 0.0);
+        assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_oetf(0.18) - 0.461_356).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_tone_maps_keep_highlights_in_range() {
+        for tone_map in [ToneMap::Clamp, ToneMap::Reinhard, ToneMap::AcesFilmic, ToneMap::Agx] {
+            let transform = OutputTransform { tone_map, ..OutputTransform::default() };
+            let mut previous = -1.0;
+            for i in 0..200 {
+                let value = transform.apply(&(Color::new(1.0, 1.0, 1.0) * (i as f32 * 0.1))).x;
+                assert!((0.0..=1.0).contains(&value), "{:?} gave {}", tone_map, value);
+                assert!(value >= previous - 1e-4, "{:?} is not monotonic", tone_map);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn test_display_p3_keeps_white() {
+        let transform = OutputTransform { color_space: ColorSpace::DisplayP3, ..OutputTransform::default() };
+        let white = transform.apply(&Color::new(1.0, 1.0, 1.0));
+        assert!((white - Color::new(1.0, 1.0, 1.0)).length() < 1e-3);
+    }
 }
@@ -0,0 +1,181 @@
+use std::rc::Rc;
+
+use crate::hittable::{HitRecord, Hittable, Interval};
+use crate::ray::Ray;
+
+// constructive solid geometry, combines two closed solids into a new one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CsgOperation {
+    // inside either of them
+    Union,
+    // inside both of them, e.g. two overlapping spheres make a lens
+    Intersection,
+    // inside the left one but not the right one, e.g. a hollowed sphere
+    Difference
+}
+
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Rc<dyn Hittable>,
+    pub right: Rc<dyn Hittable>
+}
+
+impl CsgOperation {
+    fn inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right
+        }
+    }
+}
+
+impl Csg {
+    pub fn union(left: Rc<dyn Hittable>, right: Rc<dyn Hittable>) -> Csg {
+        Csg { operation: CsgOperation::Union, left, right }
+    }
+
+    pub fn intersection(left: Rc<dyn Hittable>, right: Rc<dyn Hittable>) -> Csg {
+        Csg { operation: CsgOperation::Intersection, left, right }
+    }
+
+    pub fn difference(left: Rc<dyn Hittable>, right: Rc<dyn Hittable>) -> Csg {
+        Csg { operation: CsgOperation::Difference, left, right }
+    }
+}
+
+// one end of an interval of either child
+struct Crossing {
+    rec: HitRecord,
+    from_right: bool,
+    entering: bool
+}
+
+impl Hittable for Csg {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.intervals(r).into_iter()
+            .flat_map(|interval| [interval.enter, interval.exit])
+            .find(|rec| rec.t > t_min && rec.t < t_max)
+            .map(|mut rec| {
+                let outward_normal = rec.normal;
+                rec.set_face_normal(r, &outward_normal);
+                rec
+            })
+    }
+
+    // walk along the ends of both children's intervals in order and keep track of
+    // whether the ray is inside each of them, the result starts and ends wherever that changes
+    fn intervals(&self, r: &Ray) -> Vec<Interval> {
+        let mut crossings = Vec::new();
+        for (child, from_right) in [(&self.left, false), (&self.right, true)] {
+            for interval in child.intervals(r) {
+                crossings.push(Crossing { rec: interval.enter, from_right, entering: true });
+                crossings.push(Crossing { rec: interval.exit, from_right, entering: false });
+            }
+        }
+        crossings.sort_by(|a, b| a.rec.t.total_cmp(&b.rec.t));
+
+        let mut intervals = Vec::new();
+        let (mut in_left, mut in_right) = (false, false);
+        let mut enter: Option<HitRecord> = None;
+        for crossing in crossings {
+            if crossing.from_right {
+                in_right = crossing.entering;
+            } else {
+                in_left = crossing.entering;
+            }
+
+            let mut rec = crossing.rec;
+            // the surface of a subtracted solid faces the other way
+            if crossing.from_right && self.operation == CsgOperation::Difference {
+                let inward_normal = -&rec.normal;
+                rec.set_outward_normal(&inward_normal);
+            }
+
+            let inside = self.operation.inside(in_left, in_right);
+            match enter.take() {
+                None if inside => {
+                    rec.front_face = true;
+                    enter = Some(rec);
+                },
+                Some(enter_rec) if !inside => {
+                    rec.front_face = false;
+                    intervals.push(Interval { enter: enter_rec, exit: rec });
+                },
+                still_inside => enter = still_inside
+            }
+        }
+        intervals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::csg::*;
+    use crate::hittable_list::HittableList;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::{Color, Point3, Vec3};
+
+    fn sphere(x: f32, radius: f32) -> Rc<dyn Hittable> {
+        Rc::new(Sphere { center: Point3::new(x, 0.0, 0.0), radius, material: Rc::new(Lambertian { albedo: Color::new(0.5, 0.5, 0.5) }) })
+    }
+
+    // a ray along the x axis, coming from the left
+    fn ray() -> Ray {
+        Ray { origin: Point3::new(-10.0, 0.0, 0.0), direction: Vec3::new(1.0, 0.0, 0.0) }
+    }
+
+    fn ends(object: &dyn Hittable) -> Vec<(f32, f32)> {
+        object.intervals(&ray()).iter().map(|i| (i.enter.p.x, i.exit.p.x)).collect()
+    }
+
+    fn assert_ends(actual: Vec<(f32, f32)>, expected: &[(f32, f32)]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a.0 - e.0).abs() < 1e-3 && (a.1 - e.1).abs() < 1e-3, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_operations() {
+        // spheres covering [-2, 2] and [1, 3] on the x axis
+        assert_ends(ends(&Csg::union(sphere(0.0, 2.0), sphere(2.0, 1.0))), &[(-2.0, 3.0)]);
+        assert_ends(ends(&Csg::intersection(sphere(0.0, 2.0), sphere(2.0, 1.0))), &[(1.0, 2.0)]);
+        assert_ends(ends(&Csg::difference(sphere(0.0, 2.0), sphere(2.0, 1.0))), &[(-2.0, 1.0)]);
+        // a hollow sphere is crossed twice
+        assert_ends(ends(&Csg::difference(sphere(0.0, 2.0), sphere(0.0, 1.0))), &[(-2.0, -1.0), (1.0, 2.0)]);
+    }
+
+    #[test]
+    fn test_hit_normals() {
+        let hollow = Csg::difference(sphere(0.0, 2.0), sphere(0.0, 1.0));
+        let r = ray();
+        let outer = hollow.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((outer.p.x + 2.0).abs() < 1e-3 && outer.front_face);
+        assert!((outer.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-3);
+
+        // the inner wall of the shell faces the cavity, so the ray leaving the shell sees its back
+        let inner = hollow.hit(&r, outer.t + 0.001, f32::MAX).unwrap();
+        assert!((inner.p.x + 1.0).abs() < 1e-3 && !inner.front_face);
+        assert!((inner.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-3);
+
+        // csg nodes nest
+        let lens = Csg::intersection(Rc::new(hollow), sphere(-1.5, 1.0));
+        assert_ends(ends(&lens), &[(-2.0, -1.0)]);
+
+        // a list only implements `hit`, so its intervals come from walking along the ray
+        let mut list = HittableList::new();
+        list.add(sphere(0.0, 2.0));
+        assert_ends(ends(&Csg::difference(Rc::new(list), sphere(2.0, 1.0))), &[(-2.0, 1.0)]);
+
+        // overlapping objects of a list are one stretch, a separate one is another
+        let mut list = HittableList::new();
+        list.add(sphere(0.0, 2.0));
+        list.add(sphere(2.0, 1.0));
+        list.add(sphere(0.3, 0.5));
+        list.add(sphere(6.0, 1.0));
+        assert_ends(ends(&list), &[(-2.0, 3.0), (5.0, 7.0)]);
+    }
+}
@@ -0,0 +1,110 @@
+use std::rc::Rc;
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::rng;
+use crate::texture::ImageTexture;
+
+// how the opacity of a hit decides whether the ray stops there
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    // hits with an opacity below the threshold are holes, gives hard edges
+    Threshold(f32),
+    // the ray stops with a probability equal to the opacity, semi transparent areas average out over the samples
+    Stochastic
+}
+
+// wraps another hittable and cuts holes into it where its opacity texture is dark (leaves, fences, decals)
+// the texture is looked up by the (u, v) of the hit, the average of the channels is the opacity
+// since the holes are skipped inside `hit`, lists, csg and the direct light test of the aov pass all see them
+pub struct Cutout {
+    pub object: Rc<dyn Hittable>,
+    pub opacity: ImageTexture,
+    pub mode: AlphaMode
+}
+
+// a ray crossing more holes than this gets through
+const MAX_PASSES: usize = 64;
+
+impl Cutout {
+    pub fn new(object: Rc<dyn Hittable>, opacity: ImageTexture, mode: AlphaMode) -> Cutout {
+        Cutout { object, opacity, mode }
+    }
+
+    fn is_opaque(&self, rec: &HitRecord) -> bool {
+        let alpha = self.opacity.sample_mono(rec.u, rec.v);
+        match self.mode {
+            AlphaMode::Threshold(threshold) => alpha >= threshold,
+            AlphaMode::Stochastic => alpha > rng::random::<f32>()
+        }
+    }
+}
+
+impl Hittable for Cutout {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let mut t = t_min;
+        for _ in 0..MAX_PASSES {
+            let rec = self.object.hit(r, t, t_max)?;
+            if self.is_opaque(&rec) {
+                return Some(rec);
+            }
+            // go on behind the hole
+            t = rec.t + 1e-4 * rec.t.abs().max(1.0);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::cutout::*;
+    use crate::framebuffer::Framebuffer;
+    use crate::hittable_list::HittableList;
+    use crate::material::Lambertian;
+    use crate::shapes::Plane;
+    use crate::sphere::Sphere;
+    use crate::vec3::{Color, Point3, Vec3};
+
+    // the left half of the mask is opaque, the right half is a hole
+    fn half_mask(right: f32) -> ImageTexture {
+        let mut image = Framebuffer::new(2, 1);
+        image.set(0, 0, Color::new(1.0, 1.0, 1.0));
+        image.set(1, 0, Color::new(right, right, right));
+        ImageTexture::new(image)
+    }
+
+    fn scene(mode: AlphaMode, right: f32) -> HittableList {
+        let material = Rc::new(Lambertian { albedo: Color::new(0.5, 0.5, 0.5) });
+        // a sphere, and in front of it a card facing +z, for which the plane's u runs along y
+        let mut world = HittableList::new();
+        world.add(Rc::new(Sphere { center: Point3::new(0.0, 0.0, -5.0), radius: 10.0, material: material.clone() }));
+        let card = Plane::new(Point3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 0.0, 1.0), material);
+        world.add(Rc::new(Cutout::new(Rc::new(card), half_mask(right), mode)));
+        world
+    }
+
+    #[test]
+    fn test_threshold() {
+        let world = scene(AlphaMode::Threshold(0.5), 0.0);
+        let hit_at = |y: f32| {
+            let r = Ray { origin: Point3::new(0.0, y, 2.0), direction: Vec3::new(0.0, 0.0, -1.0) };
+            world.hit(&r, 0.001, f32::MAX).unwrap().object_id
+        };
+        // u = y + 0.5, the opaque half is u in [0, 0.5)
+        assert_eq!(hit_at(-0.25), 1);
+        assert_eq!(hit_at(0.25), 0);
+        // the mask repeats
+        assert_eq!(hit_at(0.75), 1);
+    }
+
+    #[test]
+    fn test_stochastic() {
+        let world = scene(AlphaMode::Stochastic, 0.25);
+        let r = Ray { origin: Point3::new(0.0, 0.25, 2.0), direction: Vec3::new(0.0, 0.0, -1.0) };
+        let n = 4000;
+        let stopped = (0..n).filter(|_| world.hit(&r, 0.001, f32::MAX).unwrap().object_id == 1).count();
+        let fraction = stopped as f32 / n as f32;
+        assert!((fraction - 0.25).abs() < 0.05, "{} of the rays stopped at the card", fraction);
+    }
+}
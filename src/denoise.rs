@@ -0,0 +1,147 @@
+use crate::framebuffer::Framebuffer;
+use crate::vec3::Color;
+
+// edge-avoiding a-trous wavelet filter, see
+// "Edge-Avoiding A-Trous Wavelet Transform for fast Global Illumination Filtering" (Dammertz et al. 2010)
+// every iteration blurs with a 5x5 kernel whose taps are spread 2^i pixels apart,
+// so a few iterations cover a large area, while the albedo and normal passes stop the blur at edges
+pub struct DenoiseSettings {
+    pub iterations: usize,
+    // how much color difference between two pixels is still considered noise
+    pub sigma_color: f32,
+    pub sigma_normal: f32,
+    pub sigma_albedo: f32
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings { iterations: 2, sigma_color: 1.0, sigma_normal: 0.3, sigma_albedo: 0.1 }
+    }
+}
+
+// B3 spline, the 1d kernel used by the a-trous transform
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+pub fn denoise(beauty: &Framebuffer, albedo: &Framebuffer, normal: &Framebuffer, settings: &DenoiseSettings) -> Framebuffer {
+    // filter the lighting alone (the beauty divided by the albedo), so that texture and
+    // color changes in the albedo don't get blurred away, then multiply the albedo back
+    let mut current = beauty.clone();
+    for (pixel, albedo) in current.pixels.iter_mut().zip(albedo.pixels.iter()) {
+        let factor = demodulation_factor(*albedo);
+        *pixel = Color::new(pixel.x / factor.x, pixel.y / factor.y, pixel.z / factor.z);
+    }
+    let mut sigma_color = settings.sigma_color;
+
+    for iteration in 0..settings.iterations {
+        let step = 1_i64 << iteration;
+        let mut next = Framebuffer::new(beauty.width, beauty.height);
+
+        for y in 0..beauty.height {
+            for x in 0..beauty.width {
+                let color_p = current.get(x, y);
+                let normal_p = normal.get(x, y);
+                let albedo_p = albedo.get(x, y);
+
+                let mut sum = Color::new_empty();
+                let mut weight_sum = 0.0;
+                for (ky, hy) in KERNEL.iter().enumerate() {
+                    for (kx, hx) in KERNEL.iter().enumerate() {
+                        let qx = x as i64 + (kx as i64 - 2) * step;
+                        let qy = y as i64 + (ky as i64 - 2) * step;
+                        if qx < 0 || qy < 0 || qx >= beauty.width as i64 || qy >= beauty.height as i64 {
+                            continue;
+                        }
+                        let (qx, qy) = (qx as usize, qy as usize);
+
+                        let color_q = current.get(qx, qy);
+                        let weight = hx * hy
+                            * edge_weight(color_p, color_q, sigma_color)
+                            * edge_weight(normal_p, normal.get(qx, qy), settings.sigma_normal)
+                            * edge_weight(albedo_p, albedo.get(qx, qy), settings.sigma_albedo);
+                        sum += weight * color_q;
+                        weight_sum += weight;
+                    }
+                }
+
+                // the center tap always has a weight of at least (3/8)^2, so this never divides by 0
+                next.set(x, y, sum / weight_sum);
+            }
+        }
+
+        current = next;
+        // the image gets smoother every iteration, so the color edges get sharper
+        sigma_color /= 2.0;
+    }
+
+    for (pixel, albedo) in current.pixels.iter_mut().zip(albedo.pixels.iter()) {
+        *pixel = *pixel * demodulation_factor(*albedo);
+    }
+    current
+}
+
+// channels without an albedo (like the sky) are filtered as they are
+fn demodulation_factor(albedo: Color) -> Color {
+    let factor = |a: f32| if a < 0.01 { 1.0 } else { a };
+    Color::new(factor(albedo.x), factor(albedo.y), factor(albedo.z))
+}
+
+fn edge_weight(p: Color, q: Color, sigma: f32) -> f32 {
+    (-(p - q).length_squared() / (sigma * sigma)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::rc::Rc;
+
+    use crate::camera::Camera;
+    use crate::denoise::*;
+    use crate::hittable_list::HittableList;
+    use crate::material::Lambertian;
+    use crate::render::{self, RenderSettings};
+    use crate::sphere::Sphere;
+    use crate::vec3::{Point3, Vec3};
+
+    fn mean_squared_error(a: &Framebuffer, b: &Framebuffer) -> f32 {
+        let sum: f32 = a.pixels.iter().zip(b.pixels.iter()).map(|(p, q)| (*p - *q).length_squared()).sum();
+        sum / a.pixels.len() as f32
+    }
+
+    #[test]
+    fn test_denoise_is_closer_to_reference() {
+        let mut world = HittableList { objects: Vec::new() };
+        world.add(Rc::new(Sphere { center: Point3::new(0.0, -100.5, -1.0), radius: 100.0,
+            material: Rc::new(Lambertian { albedo: Color::new(0.8, 0.8, 0.0) }) }));
+        world.add(Rc::new(Sphere { center: Point3::new(0.0, 0.0, -1.0), radius: 0.5,
+            material: Rc::new(Lambertian { albedo: Color::new(0.1, 0.2, 0.5) }) }));
+        world.add(Rc::new(Sphere { center: Point3::new(1.0, 0.0, -1.0), radius: 0.5,
+            material: Rc::new(Lambertian { albedo: Color::new(0.8, 0.6, 0.2) }) }));
+
+        let camera = Camera::new(Point3::new(0.0, 0.0, 1.0), Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0), 60.0, 4.0 / 3.0, 0.0, 2.0);
+        let settings = |samples_per_pixel| RenderSettings {
+            image_width: 64, image_height: 48, samples_per_pixel, max_depth: 8, collect_aovs: true, ..Default::default()
+        };
+
+        let reference = render::render(&camera, &world, &settings(256)).beauty;
+        let noisy = render::render(&camera, &world, &settings(1));
+        let aovs = noisy.aovs.unwrap();
+        let denoised = denoise(&noisy.beauty, &aovs.albedo, &aovs.normal, &DenoiseSettings::default());
+
+        let noisy_error = mean_squared_error(&noisy.beauty, &reference);
+        let denoised_error = mean_squared_error(&denoised, &reference);
+        assert!(denoised_error < noisy_error * 0.8,
+            "denoised error {} should be well below noisy error {}", denoised_error, noisy_error);
+    }
+
+    #[test]
+    fn test_denoise_keeps_flat_image() {
+        let mut flat = Framebuffer::new(8, 8);
+        for pixel in flat.pixels.iter_mut() {
+            *pixel = Color::new(0.25, 0.5, 0.75);
+        }
+        let guide = Framebuffer::new(8, 8);
+        let denoised = denoise(&flat, &guide, &guide, &DenoiseSettings::default());
+        assert!(mean_squared_error(&flat, &denoised) < 1e-10);
+    }
+}
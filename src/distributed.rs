@@ -0,0 +1,357 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::framebuffer::{Framebuffer, Region};
+use crate::image_io;
+use crate::job::RenderJob;
+use crate::progress::Progress;
+use crate::render;
+
+// rendering one image on several worker processes
+// the coordinator cuts the image into tiles and hands them out to the workers over tcp, a worker builds the scene
+// of the job itself (so nothing with an `Rc` in it has to cross a process) and sends every tile back as float pixels
+// the job is seeded, so a tile comes out the same whichever worker renders it, and a tile of a worker that went away
+// is simply rendered again by another one
+//
+// every message is a kind byte, a little endian u32 length and that many bytes:
+//   'J' job document (coordinator -> worker), once per connection
+//   'T' tile request: index, x, y, width, height as u32
+//   'R' rendered tile: the index as u32, then the tile as pfm
+//   'E' error text, the worker can't do the job
+
+// connection attempts per worker in a row before giving up on it
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// a worker that doesn't answer a tile request in this time is treated like one that hung up, its tile goes back to the queue
+const TILE_TIMEOUT: Duration = Duration::from_secs(300);
+// the job document and the tile requests a worker gets are small, the rendered tiles a coordinator gets are
+// limited by the size of the tile
+const MAX_REQUEST: usize = 1 << 20;
+
+fn write_message(stream: &mut TcpStream, kind: u8, payload: &[u8]) -> io::Result<()> {
+    let mut message = Vec::with_capacity(5 + payload.len());
+    message.push(kind);
+    message.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    message.extend_from_slice(payload);
+    stream.write_all(&message)
+}
+
+// `None` when the other side closed the connection between messages
+// a payload longer than `max_length` is an error, before anything is allocated for it
+fn read_message(stream: &mut TcpStream, max_length: usize) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut header = [0; 5];
+    match stream.read_exact(&mut header) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?
+    }
+    let length = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if length > max_length {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("a message of {} bytes, expected at most {}", length, max_length)));
+    }
+    let mut payload = vec![0; length];
+    stream.read_exact(&mut payload)?;
+    Ok(Some((header[0], payload)))
+}
+
+fn read_u32s(bytes: &[u8], count: usize) -> io::Result<Vec<u32>> {
+    if bytes.len() < count * 4 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "message too short"));
+    }
+    Ok(bytes.chunks_exact(4).take(count).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+}
+
+// run a worker: serve the coordinators that connect, one at a time, until the listener fails
+pub fn serve(listener: TcpListener) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let peer = stream.peer_addr().map_or("?".to_string(), |a| a.to_string());
+        // a coordinator going away is its problem, the worker waits for the next one
+        if let Err(e) = serve_connection(stream) {
+            eprintln!("worker: connection from {} failed: {}", peer, e);
+        }
+    }
+    Ok(())
+}
+
+// answer the tile requests of one coordinator until it hangs up
+pub fn serve_connection(mut stream: TcpStream) -> io::Result<()> {
+    let mut current = None;
+    while let Some((kind, payload)) = read_message(&mut stream, MAX_REQUEST)? {
+        match kind {
+            b'J' => match RenderJob::parse(&String::from_utf8_lossy(&payload)) {
+                Ok(job) => current = Some((job, job.build_world(), job.camera())),
+                Err(e) => return write_message(&mut stream, b'E', format!("invalid job: {}", e).as_bytes())
+            },
+            b'T' => {
+                let Some((job, world, camera)) = current.as_ref() else {
+                    return write_message(&mut stream, b'E', b"tile request before the job");
+                };
+                let values = read_u32s(&payload, 5)?;
+                let region = Region { x: values[1] as usize, y: values[2] as usize, width: values[3] as usize, height: values[4] as usize };
+                let settings = job.settings();
+                if region.width == 0 || region.x + region.width > settings.image_width || region.y + region.height > settings.image_height {
+                    return write_message(&mut stream, b'E', format!("tile {:?} is outside the image", region).as_bytes());
+                }
+                let output = render::render(camera, world, &render::RenderSettings { region: Some(region), ..settings });
+                let mut reply = values[0].to_le_bytes().to_vec();
+                reply.extend_from_slice(&image_io::encode_pfm(&output.beauty, 3));
+                write_message(&mut stream, b'R', &reply)?;
+            },
+            other => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown message '{}'", other as char)))
+        }
+    }
+    Ok(())
+}
+
+// the tiles still to do and the ones that are done, shared by the threads talking to the workers
+struct Tiles {
+    image_width: usize,
+    image_height: usize,
+    columns: usize,
+    rows: usize,
+    timeout: Duration,
+    queue: Mutex<VecDeque<usize>>,
+    done: Mutex<Vec<Option<Framebuffer>>>,
+    remaining: AtomicUsize
+}
+
+// render `job` on the workers at `addresses`, cut into a `columns` x `rows` grid of tiles
+// `progress` advances by one for every finished tile, starting and finishing it is up to the caller
+// fails only when every worker is gone with tiles left to do
+pub fn render_distributed(job: &RenderJob, addresses: &[String], columns: usize, rows: usize, progress: &dyn Progress) -> io::Result<Framebuffer> {
+    render_tiles(job, addresses, columns, rows, progress, TILE_TIMEOUT)
+}
+
+fn render_tiles(job: &RenderJob, addresses: &[String], columns: usize, rows: usize, progress: &dyn Progress, timeout: Duration) -> io::Result<Framebuffer> {
+    Region::check_grid(job.settings.image_width, job.settings.image_height, columns, rows)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let count = columns * rows;
+    let tiles = Tiles {
+        image_width: job.settings.image_width,
+        image_height: job.settings.image_height,
+        columns,
+        rows,
+        timeout,
+        queue: Mutex::new((0..count).collect()),
+        done: Mutex::new(vec![None; count]),
+        remaining: AtomicUsize::new(count)
+    };
+    let document = job.to_text();
+    thread::scope(|scope| {
+        for address in addresses {
+            let (tiles, document) = (&tiles, &document);
+            scope.spawn(move || {
+                let mut attempts = 0;
+                while tiles.remaining.load(Ordering::SeqCst) > 0 {
+                    match drive_worker(address, document, tiles, progress, &mut attempts) {
+                        Ok(()) => break,
+                        Err(e) => {
+                            attempts += 1;
+                            if attempts >= MAX_ATTEMPTS {
+                                eprintln!("worker {}: {}, giving up on it", address, e);
+                                break;
+                            }
+                            eprintln!("worker {}: {}, retrying", address, e);
+                            thread::sleep(RETRY_DELAY * attempts);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let done = tiles.done.into_inner().unwrap();
+    let missing = done.iter().filter(|tile| tile.is_none()).count();
+    if missing > 0 {
+        return Err(io::Error::other(format!("no worker left, {} of {} tiles are missing", missing, count)));
+    }
+    let done: Vec<Framebuffer> = done.into_iter().flatten().collect();
+    Framebuffer::stitch(&done, columns, rows).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// one connection to a worker, rendering tiles until there are none left
+// a tile that was handed out when the connection broke goes back to the queue
+fn drive_worker(address: &str, document: &str, tiles: &Tiles, progress: &dyn Progress, attempts: &mut u32) -> io::Result<()> {
+    let socket_address = address.to_socket_addrs()?.next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?;
+    let mut stream = TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(tiles.timeout))?;
+    write_message(&mut stream, b'J', document.as_bytes())?;
+
+    loop {
+        let next = tiles.queue.lock().unwrap().pop_front();
+        let Some(index) = next else {
+            if tiles.remaining.load(Ordering::SeqCst) == 0 {
+                return Ok(());
+            }
+            // the last tiles are out, wait in case one of them comes back
+            thread::sleep(Duration::from_millis(20));
+            continue;
+        };
+
+        let region = Region::tile(tiles.image_width, tiles.image_height, tiles.columns, tiles.rows, index);
+        match render_tile(&mut stream, index, &region) {
+            Ok(tile) => {
+                tiles.done.lock().unwrap()[index] = Some(tile);
+                tiles.remaining.fetch_sub(1, Ordering::SeqCst);
+                progress.advance(1);
+                // the worker is doing fine again
+                *attempts = 0;
+            },
+            Err(e) => {
+                tiles.queue.lock().unwrap().push_front(index);
+                return Err(e);
+            }
+        }
+    }
+}
+
+fn render_tile(stream: &mut TcpStream, index: usize, region: &Region) -> io::Result<Framebuffer> {
+    let request: Vec<u8> = [index, region.x, region.y, region.width, region.height].iter()
+        .flat_map(|v| (*v as u32).to_le_bytes()).collect();
+    write_message(stream, b'T', &request)?;
+
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    // the index, the pfm header and 3 floats per pixel, or an error message
+    let max_length = (4 + 64 + region.width * region.height * 12).max(MAX_REQUEST);
+    let message = read_message(stream, max_length).map_err(|e| match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::Error::new(io::ErrorKind::TimedOut, "the worker didn't answer in time"),
+        _ => e
+    })?;
+    match message {
+        Some((b'R', payload)) => {
+            if read_u32s(&payload, 1)?[0] as usize != index {
+                return Err(invalid(format!("expected tile {}", index)));
+            }
+            // the size from the header, before anything is allocated for the pixels
+            if image_io::pfm_size(&payload[4..]).map_err(invalid)? != (region.width, region.height) {
+                return Err(invalid(format!("tile {} has the wrong size", index)));
+            }
+            image_io::decode_pfm(&payload[4..]).map_err(invalid)
+        },
+        Some((b'E', payload)) => Err(io::Error::other(String::from_utf8_lossy(&payload).to_string())),
+        Some((other, _)) => Err(invalid(format!("unknown message '{}'", other as char))),
+        None => Err(io::Error::new(io::ErrorKind::ConnectionAborted, "the worker hung up"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::distributed::*;
+    use crate::progress::Quiet;
+    use crate::scene::SceneKind;
+
+    fn start_worker() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener));
+        address
+    }
+
+    #[test]
+    fn test_matches_local_render() {
+        let job = RenderJob::parse("scene landscape\nseed 3\nsize 24 16\nsamples 2\nmax_depth 4\nfilter tent").unwrap();
+        let local = render::render(&job.camera(), &job.build_world(), &job.settings());
+
+        // a worker that hangs up on the first tile it gets, then isn't there anymore
+        let flaky = TcpListener::bind("127.0.0.1:0").unwrap();
+        let flaky_address = flaky.local_addr().unwrap().to_string();
+        let flaky_thread = thread::spawn(move || {
+            let (mut stream, _) = flaky.accept().unwrap();
+            read_message(&mut stream, MAX_REQUEST).unwrap();
+            read_message(&mut stream, MAX_REQUEST).unwrap();
+        });
+
+        let addresses = vec![flaky_address, start_worker(), start_worker()];
+        let image = render_distributed(&job, &addresses, 3, 2, &Quiet).unwrap();
+        flaky_thread.join().unwrap();
+        assert_eq!(image.pixels, local.beauty.pixels);
+
+        // nobody there
+        let job = RenderJob { scene: SceneKind::Random, ..job };
+        let gone = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        assert!(render_distributed(&job, &[gone], 2, 2, &Quiet).is_err());
+
+        // more columns than pixels
+        let error = render_distributed(&job, &addresses[1..], 25, 1, &Quiet).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(render_distributed(&job, &addresses[1..], 100000, 100000, &Quiet).is_err());
+    }
+
+    #[test]
+    fn test_silent_worker() {
+        let job = RenderJob::parse("scene landscape\nseed 3\nsize 24 16\nsamples 2\nmax_depth 4").unwrap();
+        let local = render::render(&job.camera(), &job.build_world(), &job.settings());
+
+        // a worker that takes every job and never answers
+        let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+        let silent_address = silent.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let mut streams = Vec::new();
+            for stream in silent.incoming() {
+                streams.push(stream.unwrap());
+            }
+        });
+
+        let addresses = vec![silent_address, start_worker()];
+        let image = render_tiles(&job, &addresses, 2, 2, &Quiet, Duration::from_millis(200)).unwrap();
+        assert_eq!(image.pixels, local.beauty.pixels);
+    }
+
+    #[test]
+    fn test_broken_worker() {
+        let job = RenderJob::parse("scene landscape\nseed 3\nsize 24 16\nsamples 2\nmax_depth 4").unwrap();
+        let local = render::render(&job.camera(), &job.build_world(), &job.settings());
+
+        // a worker that answers every tile with an image that's far too big to exist
+        let broken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let broken_address = broken.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for stream in broken.incoming() {
+                let mut stream = stream.unwrap();
+                while let Ok(Some((kind, payload))) = read_message(&mut stream, MAX_REQUEST) {
+                    if kind == b'T' {
+                        let mut reply = payload[0..4].to_vec();
+                        reply.extend_from_slice(b"PF\n4611686018427387904 4\n-1.0\n");
+                        write_message(&mut stream, b'R', &reply).unwrap();
+                    }
+                }
+            }
+        });
+
+        let addresses = vec![broken_address, start_worker()];
+        let image = render_distributed(&job, &addresses, 2, 2, &Quiet).unwrap();
+        assert_eq!(image.pixels, local.beauty.pixels);
+    }
+
+    #[test]
+    fn test_worker_error() {
+        let mut stream = TcpStream::connect(start_worker()).unwrap();
+        write_message(&mut stream, b'J', b"size 24 16").unwrap();
+        // the error text is longer than the pixels of an empty tile would be
+        let error = render_tile(&mut stream, 0, &Region { x: 0, y: 0, width: 0, height: 1 }).err().unwrap();
+        assert_eq!(error.to_string(), "tile Region { x: 0, y: 0, width: 0, height: 1 } is outside the image");
+    }
+
+    #[test]
+    fn test_message_too_long() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        // claims 4 GB, only the header is ever sent
+        client.write_all(&[b'J', 0xff, 0xff, 0xff, 0xff]).unwrap();
+        let error = read_message(&mut server, MAX_REQUEST).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        // a worker hangs up on it
+        client.write_all(&[b'J', 0xff, 0xff, 0xff, 0xff]).unwrap();
+        assert!(serve_connection(server).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+// pixel reconstruction filters
+// every sample is splatted into all the pixels within `radius` of it, weighted by the filter
+// the final pixel is the weighted average, so a wider filter gives softer but less aliased edges
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    // in pixels, along each axis
+    pub radius: f32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    // Mitchell-Netravali with B = C = 1/3
+    Mitchell,
+    // windowed sinc, with as many lobes as the radius
+    Lanczos
+}
+
+impl FilterKind {
+    pub fn default_radius(&self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0
+        }
+    }
+
+    // the names used on the command line and in job files
+    pub fn from_name(name: &str) -> Option<FilterKind> {
+        match name {
+            "box" => Some(FilterKind::Box),
+            "tent" => Some(FilterKind::Tent),
+            "gaussian" => Some(FilterKind::Gaussian),
+            "mitchell" => Some(FilterKind::Mitchell),
+            "lanczos" => Some(FilterKind::Lanczos),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos"
+        }
+    }
+}
+
+// a box filter that only covers its own pixel, same as averaging the samples
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::new(FilterKind::Box)
+    }
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Filter {
+        Filter { kind, radius: kind.default_radius() }
+    }
+
+    // the filters are separable, so the 2d weight is just the product of both axes
+    // dx and dy are the offsets from the sample to the pixel center
+    pub fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        let r = self.radius;
+        match self.kind {
+            // half open, so a sample on the border of two pixels only lands in the pixel it was taken for
+            FilterKind::Box => if x > -r && x <= r { 1.0 } else { 0.0 },
+            FilterKind::Tent => (r - x.abs()).max(0.0),
+            FilterKind::Gaussian => {
+                // the curve is shifted down so it reaches 0 right at the radius
+                let sigma = r / 3.0;
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(r)).max(0.0)
+            },
+            FilterKind::Mitchell => mitchell(2.0 * x / r),
+            FilterKind::Lanczos => if x.abs() < r { sinc(x) * sinc(x / r) } else { 0.0 }
+        }
+    }
+}
+
+// the Mitchell-Netravali cubic is defined on [-2, 2]
+fn mitchell(x: f32) -> f32 {
+    const B: f32 = 1.0 / 3.0;
+    const C: f32 = 1.0 / 3.0;
+    let x = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * B - 6.0 * C) * x * x * x + (-18.0 + 12.0 * B + 6.0 * C) * x * x + (6.0 - 2.0 * B)) / 6.0
+    } else if x < 2.0 {
+        ((-B - 6.0 * C) * x * x * x + (6.0 * B + 30.0 * C) * x * x + (-12.0 * B - 48.0 * C) * x + (8.0 * B + 24.0 * C)) / 6.0
+    } else {
+        0.0
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    let px = std::f32::consts::PI * x;
+    px.sin() / px
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::filter::*;
+
+    #[test]
+    fn test_box_filter_covers_one_pixel() {
+        let filter = Filter::default();
+        // a sample on the left border of a pixel is 0.5 to the left of its center
+        assert_eq!(filter.evaluate(0.5, 0.0), 1.0);
+        assert_eq!(filter.evaluate(-0.5, 0.0), 0.0);
+        assert_eq!(filter.evaluate(0.2, -0.3), 1.0);
+    }
+
+    #[test]
+    fn test_filters_vanish_outside_radius() {
+        for kind in [FilterKind::Box, FilterKind::Tent, FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::Lanczos] {
+            let filter = Filter::new(kind);
+            assert!(filter.evaluate(0.0, 0.0) > 0.0, "{:?} should peak at the center", kind);
+            assert_eq!(filter.evaluate(filter.radius + 0.01, 0.0), 0.0, "{:?} should be 0 past the radius", kind);
+            assert_eq!(filter.evaluate(0.0, -filter.radius - 0.01), 0.0, "{:?} should be 0 past the radius", kind);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use crate::vec3::Color;
+
+// a float image, stored row by row starting from the top scanline
+// so it can be written out in the same order as the ppm file expects
+#[derive(Clone)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer { width, height, pixels: vec![Color::new_empty(); width * height] }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    // a copy of the pixels inside the region
+    pub fn crop(&self, region: &Region) -> Framebuffer {
+        let mut cropped = Framebuffer::new(region.width, region.height);
+        for y in 0..region.height {
+            for x in 0..region.width {
+                cropped.set(x, y, self.get(region.x + x, region.y + y));
+            }
+        }
+        cropped
+    }
+
+    // copy `image` in with its top left corner at (x, y), whatever sticks out is left out
+    pub fn paste(&mut self, image: &Framebuffer, x: usize, y: usize) {
+        for j in 0..image.height.min(self.height.saturating_sub(y)) {
+            for i in 0..image.width.min(self.width.saturating_sub(x)) {
+                self.set(x + i, y + j, image.get(i, j));
+            }
+        }
+    }
+
+    // the other way around from `crop`: a `width` x `height` image with these pixels at the region, black around it
+    pub fn pad(&self, region: &Region, width: usize, height: usize) -> Framebuffer {
+        let mut padded = Framebuffer::new(width, height);
+        padded.paste(self, region.x, region.y);
+        padded
+    }
+
+    // put the tiles of a `columns` x `rows` grid back together, in the order of `Region::tile`
+    // the image size is taken from the tiles along the top and the left edge
+    pub fn stitch(tiles: &[Framebuffer], columns: usize, rows: usize) -> Result<Framebuffer, String> {
+        if tiles.len() != columns * rows {
+            return Err(format!("expected {} tiles, got {}", columns * rows, tiles.len()));
+        }
+        let width = tiles[..columns].iter().map(|tile| tile.width).sum();
+        let height = tiles.iter().step_by(columns).map(|tile| tile.height).sum();
+        let mut image = Framebuffer::new(width, height);
+        for (index, tile) in tiles.iter().enumerate() {
+            let region = Region::tile(width, height, columns, rows, index);
+            if (tile.width, tile.height) != (region.width, region.height) {
+                return Err(format!("tile {} is {}x{}, expected {}x{}", index, tile.width, tile.height, region.width, region.height));
+            }
+            image.paste(tile, region.x, region.y);
+        }
+        Ok(image)
+    }
+}
+
+// a rectangle of pixels, x and y of the top left corner are counted from the top left of the image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+impl Region {
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    // tile `index` of a grid of `columns` x `rows` tiles covering the image, counted row by row from the top left
+    // the tiles differ by at most a pixel in size and always cover the whole image
+    pub fn tile(image_width: usize, image_height: usize, columns: usize, rows: usize, index: usize) -> Region {
+        let (column, row) = (index % columns, index / columns);
+        let (x0, x1) = (column * image_width / columns, (column + 1) * image_width / columns);
+        let (y0, y1) = (row * image_height / rows, (row + 1) * image_height / rows);
+        Region { x: x0, y: y0, width: x1 - x0, height: y1 - y0 }
+    }
+
+    // every tile of the grid needs at least one pixel
+    pub fn check_grid(image_width: usize, image_height: usize, columns: usize, rows: usize) -> Result<(), String> {
+        if columns == 0 || rows == 0 || columns > image_width || rows > image_height {
+            return Err(format!("a {}x{} grid of tiles doesn't fit the {}x{} image", columns, rows, image_width, image_height));
+        }
+        Ok(())
+    }
+}
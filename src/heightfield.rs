@@ -0,0 +1,312 @@
+use std::rc::Rc;
+
+use crate::framebuffer::Framebuffer;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+// terrain from a grid of heights, intersected cell by cell along the ray (no triangles)
+// the surface inside a cell is the bilinear blend of its 4 corners, shading normals are
+// interpolated from per sample normals so the terrain looks smooth
+pub struct Heightfield {
+    // columns along x, rows along z, each between 0 and 1
+    // the samples and the size are fixed once it's built, the normals and the bounding box are worked out from them
+    heights: Vec<f32>,
+    columns: usize,
+    rows: usize,
+    // the corner with the smallest x and z, at height 0
+    pub origin: Point3,
+    // extent along x and z, and the height of a sample of 1
+    size: Vec3,
+    pub material: Rc<dyn Material>,
+    normals: Vec<Vec3>,
+    // the highest sample, for the bounding box
+    max_height: f32
+}
+
+impl Heightfield {
+    pub fn new(heights: Vec<f32>, columns: usize, rows: usize, origin: Point3, size: Vec3, material: Rc<dyn Material>) -> Result<Heightfield, String> {
+        if columns < 2 || rows < 2 {
+            return Err(format!("a heightfield needs at least 2x2 samples, not {}x{}", columns, rows));
+        }
+        if heights.len() != columns * rows {
+            return Err(format!("{} heights for {}x{} samples", heights.len(), columns, rows));
+        }
+        let max_height = heights.iter().fold(0.0_f32, |m, h| m.max(*h));
+        let mut heightfield = Heightfield { heights, columns, rows, origin, size, material, normals: Vec::new(), max_height };
+        heightfield.normals = (0..rows)
+            .flat_map(|z| (0..columns).map(move |x| (x, z)))
+            .map(|(x, z)| heightfield.sample_normal(x, z))
+            .collect();
+        Ok(heightfield)
+    }
+
+    // the brightness of each pixel is the height, image rows run along z
+    pub fn from_image(image: &Framebuffer, origin: Point3, size: Vec3, material: Rc<dyn Material>) -> Result<Heightfield, String> {
+        let heights = image.pixels.iter().map(|p| (p.x + p.y + p.z) / 3.0).collect();
+        Heightfield::new(heights, image.width, image.height, origin, size, material)
+    }
+
+    // rolling hills from fractal value noise, the same seed always gives the same terrain
+    // at least 2x2 samples, fewer are rounded up
+    pub fn from_noise(columns: usize, rows: usize, seed: u32, origin: Point3, size: Vec3, material: Rc<dyn Material>) -> Heightfield {
+        let (columns, rows) = (columns.max(2), rows.max(2));
+        // about 4 hills across the terrain for the largest octave
+        let scale = 4.0 / columns.max(rows) as f32;
+        let heights = (0..rows)
+            .flat_map(|z| (0..columns).map(move |x| fractal_noise(x as f32 * scale, z as f32 * scale, 5, seed)))
+            .collect();
+        Heightfield::new(heights, columns, rows, origin, size, material).expect("the noise has a height for every sample")
+    }
+
+    pub fn heights(&self) -> &[f32] {
+        &self.heights
+    }
+
+    fn cell_size(&self) -> (f32, f32) {
+        (self.size.x / (self.columns - 1) as f32, self.size.z / (self.rows - 1) as f32)
+    }
+
+    // world space height of a sample
+    fn height(&self, x: usize, z: usize) -> f32 {
+        self.origin.y + self.heights[z * self.columns + x] * self.size.y
+    }
+
+    // normal from the central differences of the neighbouring samples
+    fn sample_normal(&self, x: usize, z: usize) -> Vec3 {
+        let (dx, dz) = self.cell_size();
+        let (x0, x1) = (x.saturating_sub(1), (x + 1).min(self.columns - 1));
+        let (z0, z1) = (z.saturating_sub(1), (z + 1).min(self.rows - 1));
+        let slope_x = (self.height(x1, z) - self.height(x0, z)) / ((x1 - x0) as f32 * dx);
+        let slope_z = (self.height(x, z1) - self.height(x, z0)) / ((z1 - z0) as f32 * dz);
+        Vec3::new(-slope_x, 1.0, -slope_z).unit_vector()
+    }
+
+    // the first hit with the bilinear patch of cell (x, z) between t0 and t1
+    // the ray is given in grid units along x and z, and world units along y
+    fn hit_cell(&self, origin: Vec3, direction: Vec3, x: usize, z: usize, t0: f32, t1: f32) -> Option<f32> {
+        let h00 = self.height(x, z);
+        let h10 = self.height(x + 1, z);
+        let h01 = self.height(x, z + 1);
+        let h11 = self.height(x + 1, z + 1);
+        let (ha, hb, hc) = (h10 - h00, h01 - h00, h00 - h10 - h01 + h11);
+
+        // u = au + bu * t and v = av + bv * t inside the cell,
+        // then ray height - surface height is a quadratic in t
+        let (au, bu) = (origin.x - x as f32, direction.x);
+        let (av, bv) = (origin.z - z as f32, direction.z);
+        let a = -hc * bu * bv;
+        let b = direction.y - ha * bu - hb * bv - hc * (au * bv + av * bu);
+        let c = origin.y - h00 - ha * au - hb * av - hc * au * av;
+
+        let in_cell = |t: f32| t >= t0 && t <= t1;
+        if a.abs() < 1e-9 {
+            if b == 0.0 {
+                return None;
+            }
+            return Some(-c / b).filter(|t| in_cell(*t));
+        }
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        // the numerically stable form of the two roots
+        let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+        let (r0, r1) = (q / a, if q != 0.0 { c / q } else { q / a });
+        [r0.min(r1), r0.max(r1)].into_iter().find(|t| in_cell(*t))
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let (dx, dz) = self.cell_size();
+
+        // clip the ray to the bounding box
+        let low = self.origin;
+        let high = self.origin + Vec3::new(self.size.x, self.max_height * self.size.y, self.size.z);
+        let (mut t_enter, mut t_exit) = (t_min, t_max);
+        for (o, d, lo, hi) in [
+            (r.origin.x, r.direction.x, low.x, high.x),
+            (r.origin.y, r.direction.y, low.y, high.y),
+            (r.origin.z, r.direction.z, low.z, high.z)
+        ] {
+            if d == 0.0 {
+                if o < lo || o > hi {
+                    return None;
+                }
+                continue;
+            }
+            let (a, b) = ((lo - o) / d, (hi - o) / d);
+            t_enter = t_enter.max(a.min(b));
+            t_exit = t_exit.min(a.max(b));
+        }
+        if t_enter > t_exit {
+            return None;
+        }
+
+        // the ray in grid units, t stays the same
+        let origin = Vec3::new((r.origin.x - self.origin.x) / dx, r.origin.y, (r.origin.z - self.origin.z) / dz);
+        let direction = Vec3::new(r.direction.x / dx, r.direction.y, r.direction.z / dz);
+
+        // walk through the cells the ray crosses, see
+        // "A Fast Voxel Traversal Algorithm for Ray Tracing" (Amanatides and Woo 1987)
+        let start = origin + t_enter * direction;
+        let mut cell_x = (start.x.floor().max(0.0) as usize).min(self.columns - 2);
+        let mut cell_z = (start.z.floor().max(0.0) as usize).min(self.rows - 2);
+        let axis = |o: f32, d: f32, cell: usize| -> (f32, f32) {
+            if d > 0.0 {
+                ((cell as f32 + 1.0 - o) / d, 1.0 / d)
+            } else if d < 0.0 {
+                ((cell as f32 - o) / d, -1.0 / d)
+            } else {
+                (f32::INFINITY, f32::INFINITY)
+            }
+        };
+        let (mut next_x, delta_x) = axis(origin.x, direction.x, cell_x);
+        let (mut next_z, delta_z) = axis(origin.z, direction.z, cell_z);
+
+        let mut t = t_enter;
+        let t_hit = loop {
+            let cell_exit = next_x.min(next_z).min(t_exit);
+            if let Some(t_hit) = self.hit_cell(origin, direction, cell_x, cell_z, t, cell_exit) {
+                break t_hit;
+            }
+            if cell_exit >= t_exit {
+                return None;
+            }
+            t = cell_exit;
+            if next_x < next_z {
+                if direction.x > 0.0 && cell_x + 2 < self.columns {
+                    cell_x += 1;
+                } else if direction.x < 0.0 && cell_x > 0 {
+                    cell_x -= 1;
+                } else {
+                    return None;
+                }
+                next_x += delta_x;
+            } else {
+                if direction.z > 0.0 && cell_z + 2 < self.rows {
+                    cell_z += 1;
+                } else if direction.z < 0.0 && cell_z > 0 {
+                    cell_z -= 1;
+                } else {
+                    return None;
+                }
+                next_z += delta_z;
+            }
+        };
+
+        let mut rec = HitRecord::new(&r.at(t_hit), t_hit, Rc::clone(&self.material));
+        let grid = origin + t_hit * direction;
+        let (u, v) = ((grid.x - cell_x as f32).clamp(0.0, 1.0), (grid.z - cell_z as f32).clamp(0.0, 1.0));
+        let normal_at = |x: usize, z: usize| self.normals[z * self.columns + x];
+        let outward_normal = ((1.0 - u) * (1.0 - v) * normal_at(cell_x, cell_z)
+            + u * (1.0 - v) * normal_at(cell_x + 1, cell_z)
+            + (1.0 - u) * v * normal_at(cell_x, cell_z + 1)
+            + u * v * normal_at(cell_x + 1, cell_z + 1)).unit_vector();
+        rec.set_face_normal(r, &outward_normal);
+        rec.u = grid.x / (self.columns - 1) as f32;
+        rec.v = grid.z / (self.rows - 1) as f32;
+        // along x and z, tilted to lie in the smooth surface
+        rec.dpdu = self.size.x * Vec3::new(1.0, -outward_normal.x / outward_normal.y, 0.0);
+        rec.dpdv = self.size.z * Vec3::new(0.0, -outward_normal.z / outward_normal.y, 1.0);
+        Some(rec)
+    }
+}
+
+// a pseudo random value in [0, 1] for every lattice point
+fn lattice_value(x: i32, z: i32, seed: u32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(0x27d4_eb2d) ^ (z as u32).wrapping_mul(0x1656_67b1) ^ seed.wrapping_mul(0x9e37_79b9);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b_3c6d);
+    h ^= h >> 12;
+    h = h.wrapping_mul(0x297a_2d39);
+    h ^= h >> 15;
+    (h & 0x00ff_ffff) as f32 / 0x00ff_ffff as f32
+}
+
+// smoothly interpolated lattice values, in [0, 1]
+pub fn value_noise(x: f32, z: f32, seed: u32) -> f32 {
+    let (x0, z0) = (x.floor(), z.floor());
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (u, v) = (smooth(x - x0), smooth(z - z0));
+    let (ix, iz) = (x0 as i32, z0 as i32);
+    let top = lattice_value(ix, iz, seed) * (1.0 - u) + lattice_value(ix + 1, iz, seed) * u;
+    let bottom = lattice_value(ix, iz + 1, seed) * (1.0 - u) + lattice_value(ix + 1, iz + 1, seed) * u;
+    top * (1.0 - v) + bottom * v
+}
+
+// octaves of value noise, each twice the frequency and half the amplitude of the last, in [0, 1]
+pub fn fractal_noise(x: f32, z: f32, octaves: u32, seed: u32) -> f32 {
+    let (mut sum, mut amplitude, mut frequency, mut total) = (0.0, 1.0, 1.0, 0.0);
+    for octave in 0..octaves {
+        sum += amplitude * value_noise(x * frequency, z * frequency, seed.wrapping_add(octave));
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::heightfield::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    fn material() -> Rc<dyn Material> {
+        Rc::new(Lambertian { albedo: Color::new(0.5, 0.5, 0.5) })
+    }
+
+    #[test]
+    fn test_slope_matches_plane() {
+        // height rises linearly along x, so the surface is the plane y = x / 2
+        let (columns, rows) = (9, 5);
+        let heights = (0..rows).flat_map(|_| (0..columns).map(|x| x as f32 / (columns - 1) as f32)).collect();
+        let terrain = Heightfield::new(heights, columns, rows, Point3::new(0.0, 0.0, 0.0), Vec3::new(8.0, 4.0, 4.0), material()).unwrap();
+        let normal = Vec3::new(-0.5, 1.0, 0.0).unit_vector();
+
+        for (origin, direction) in [
+            (Point3::new(3.3, 10.0, 1.7), Vec3::new(0.0, -1.0, 0.0)),
+            (Point3::new(-2.0, 6.0, 0.5), Vec3::new(1.0, -0.8, 0.6)),
+            (Point3::new(7.0, 8.0, 3.5), Vec3::new(-1.0, -1.5, -0.2))
+        ] {
+            let r = Ray { origin, direction };
+            let rec = terrain.hit(&r, 0.001, f32::MAX).expect("expected a hit");
+            assert!((rec.p.y - rec.p.x / 2.0).abs() < 1e-3, "{:?} is not on the plane", rec.p);
+            assert!((rec.normal - normal).length() < 1e-3);
+            assert!((rec.u - rec.p.x / 8.0).abs() < 1e-3 && (rec.v - rec.p.z / 4.0).abs() < 1e-3);
+        }
+
+        // passes above the highest point
+        let r = Ray { origin: Point3::new(-1.0, 4.5, 1.0), direction: Vec3::new(1.0, 0.0, 0.0) };
+        assert!(terrain.hit(&r, 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn test_too_few_samples() {
+        let (origin, size) = (Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+        let error = Heightfield::from_image(&Framebuffer::new(1, 5), origin, size, material()).err();
+        assert_eq!(error.as_deref(), Some("a heightfield needs at least 2x2 samples, not 1x5"));
+        assert!(Heightfield::new(vec![0.0; 3], 2, 2, origin, size, material()).is_err());
+        assert_eq!(Heightfield::from_noise(1, 1, 0, origin, size, material()).heights().len(), 4);
+    }
+
+    #[test]
+    fn test_noise_terrain() {
+        let terrain = Heightfield::from_noise(64, 64, 7, Point3::new(-10.0, 0.0, -10.0), Vec3::new(20.0, 3.0, 20.0), material());
+        let again = Heightfield::from_noise(64, 64, 7, Point3::new(-10.0, 0.0, -10.0), Vec3::new(20.0, 3.0, 20.0), material());
+        assert_eq!(terrain.heights, again.heights);
+        assert!(terrain.heights.iter().all(|h| (0.0..=1.0).contains(h)));
+
+        // every ray looking down onto the terrain hits it, at the interpolated height
+        for i in 0..20 {
+            let x = -9.0 + i as f32 * 0.8;
+            let r = Ray { origin: Point3::new(x, 10.0, x * 0.5), direction: Vec3::new(0.3, -1.0, 0.1) };
+            let rec = terrain.hit(&r, 0.001, f32::MAX).unwrap_or_else(|| panic!("missed at {}", i));
+            assert!(rec.p.y >= 0.0 && rec.p.y <= 3.0 && rec.normal.y > 0.0);
+        }
+    }
+}
@@ -5,17 +5,43 @@ use crate::vec3::Point3;
 use crate::vec3::Vec3;
 use crate::ray::Ray;
 
+#[derive(Clone)]
 pub struct HitRecord {
     pub p: Point3,
+    // the shading normal, this is what materials use and what normal and bump maps change
     pub normal: Vec3,
+    // the normal of the actual surface, faces the same side as `normal`
+    pub geometric_normal: Vec3,
     pub t: f32,
     pub front_face: bool,
-    pub material: Rc<dyn Material>
+    // surface coordinates of the hit point, both in [0, 1] for bounded shapes
+    pub u: f32,
+    pub v: f32,
+    // how the hit point moves along the surface with u and v, zero if the shape doesn't provide them
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    pub material: Rc<dyn Material>,
+    // which object was hit, used for the object id pass
+    // a `HittableList` numbers its objects one after the other, the objects of a group (a nested list, a `SphereSet`)
+    // get as many numbers as the group has objects
+    pub object_id: usize
 }
 
 impl HitRecord {
     pub fn new(p: &Point3, t: f32, material: Rc<dyn Material>) -> HitRecord {
-        HitRecord { p: *p, normal: Vec3::new_empty(), t: t, front_face: false, material: material }
+        HitRecord {
+            p: *p,
+            normal: Vec3::new_empty(),
+            geometric_normal: Vec3::new_empty(),
+            t,
+            front_face: false,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new_empty(),
+            dpdv: Vec3::new_empty(),
+            material,
+            object_id: 0
+        }
     }
 
     // set the "normal" vector to be always pointing to the opposite direction of the ray
@@ -24,10 +50,101 @@ impl HitRecord {
         // if the dot product is smaller than 0, then it means the ray hits the outside surface, we can keep the outward_normal
         // otherwise it means the ray hits from the inside of the surface, we should revert the direction of the outward_normal
         self.front_face = r.direction.dot(*outward_normal) < 0.0;
-        self.normal = if self.front_face { *outward_normal } else { -outward_normal }
+        self.normal = if self.front_face { *outward_normal } else { -outward_normal };
+        self.geometric_normal = self.normal;
     }
+
+    // point the shading and the geometric normal out of the surface (or into it), used by `Interval`
+    pub fn set_outward_normal(&mut self, outward_normal: &Vec3) {
+        self.normal = *outward_normal;
+        self.geometric_normal = *outward_normal;
+    }
+
+    // an orthonormal tangent and bitangent around the shading normal, the tangent follows dpdu
+    // shapes without dpdu get an arbitrary (but consistent) frame
+    pub fn tangent_frame(&self) -> (Vec3, Vec3) {
+        let n = self.normal;
+        let mut tangent = self.dpdu - n.dot(self.dpdu) * n;
+        if tangent.near_zero() {
+            let helper = if n.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+            tangent = helper - n.dot(helper) * n;
+        }
+        let tangent = tangent.unit_vector();
+        let bitangent = n.cross(tangent);
+        // keep the bitangent on the side v grows towards
+        if bitangent.dot(self.dpdv) < 0.0 { (tangent, -&bitangent) } else { (tangent, bitangent) }
+    }
+
+    // where a ray leaving the surface in `direction` should start, pushed off the geometric surface
+    // so it doesn't hit the surface it starts on
+    pub fn spawn_origin(&self, direction: &Vec3) -> Point3 {
+        let offset = 1e-4 * (1.0 + self.p.x.abs().max(self.p.y.abs()).max(self.p.z.abs()));
+        if direction.dot(self.geometric_normal) >= 0.0 {
+            self.p + offset * self.geometric_normal
+        } else {
+            self.p - offset * self.geometric_normal
+        }
+    }
+
+    // the normal pointing out of the surface, no matter which side the ray came from
+    pub fn outward_normal(&self) -> Vec3 {
+        if self.front_face { self.normal } else { -&self.normal }
+    }
+}
+
+// a stretch of the ray that is inside a solid, the normals of both ends point outwards (not towards the ray)
+#[derive(Clone)]
+pub struct Interval {
+    pub enter: HitRecord,
+    pub exit: HitRecord
 }
 
 pub trait Hittable {
     fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+
+    // every stretch of the (infinite) ray that is inside the object, sorted by t, used by CSG
+    // the default walks along the ray from hit to hit, which works for any closed surface
+    fn intervals(&self, r: &Ray) -> Vec<Interval> {
+        walk_intervals(self, r)
+    }
+
+    // how many object ids a group of objects needs, its hits set `object_id` to the one of the object they hit
+    fn object_count(&self) -> usize {
+        1
+    }
+}
+
+// most rays cross a solid only a few times, this keeps a degenerate one from looping forever
+const MAX_CROSSINGS: usize = 64;
+
+// overlapping parts (e.g. two spheres in a list) are merged, the ray is inside until it left all of them
+fn walk_intervals<H: Hittable + ?Sized>(object: &H, r: &Ray) -> Vec<Interval> {
+    let mut intervals = Vec::new();
+    let mut enter: Option<HitRecord> = None;
+    // how many of the surfaces the ray is inside of
+    let mut depth = 0;
+    let mut t = -f32::MAX;
+    for _ in 0..MAX_CROSSINGS {
+        let Some(mut rec) = object.hit(r, t, f32::MAX) else {
+            break;
+        };
+        // step a little past the hit so the same point isn't found again
+        t = rec.t + 1e-4 * rec.t.abs().max(1.0);
+        let outward_normal = rec.outward_normal();
+        rec.set_outward_normal(&outward_normal);
+        if rec.front_face {
+            if depth == 0 {
+                enter = Some(rec);
+            }
+            depth += 1;
+        } else if depth > 0 {
+            depth -= 1;
+            if depth == 0 {
+                if let Some(enter) = enter.take() {
+                    intervals.push(Interval { enter, exit: rec });
+                }
+            }
+        }
+    }
+    intervals
 }
\ No newline at end of file
@@ -2,12 +2,18 @@ use std::rc::Rc;
 
 use crate::hittable::*;
 use crate::ray::Ray;
+use crate::stats;
 
+#[derive(Default)]
 pub struct HittableList {
     pub objects: Vec<Rc<dyn Hittable>>
 }
 
 impl HittableList {
+    pub fn new() -> HittableList {
+        HittableList { objects: Vec::new() }
+    }
+
     pub fn add(&mut self, object: Rc<dyn Hittable>) {
         self.objects.push(object);
     }
@@ -19,16 +25,23 @@ impl Hittable for HittableList {
     fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
         let mut temp_rec = None::<HitRecord>;
         let mut closest_so_far = t_max;
+        stats::count(|c| c.intersection_tests += self.objects.len() as u64);
+        let mut first_id = 0;
         for object in self.objects.iter() {
-            match object.hit(r, t_min, closest_so_far) {
-                Some(rec) => {
-                    closest_so_far = rec.t;
-                    temp_rec = Some(rec);
-                },
-                None => (),
+            let count = object.object_count();
+            if let Some(mut rec) = object.hit(r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                // the id within the object, a single object (even one wrapping a list, like csg) only has one
+                rec.object_id = first_id + rec.object_id.min(count.saturating_sub(1));
+                temp_rec = Some(rec);
             }
+            first_id += count;
         }
 
         temp_rec
     }
+
+    fn object_count(&self) -> usize {
+        self.objects.iter().map(|object| object.object_count()).sum()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,462 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+
+use crate::color_utils::{self, OutputTransform};
+use crate::framebuffer::Framebuffer;
+use crate::vec3::Color;
+
+// tone map and encode the framebuffer and write it as a plain text ppm
+pub fn write_ppm(path: &str, framebuffer: &Framebuffer, transform: &OutputTransform) -> io::Result<()> {
+    let mut output = File::create(path)?;
+    output.write_all(&encode_ppm(framebuffer, transform))
+}
+
+pub fn encode_ppm(framebuffer: &Framebuffer, transform: &OutputTransform) -> Vec<u8> {
+    let mut buffer = String::new();
+    buffer.push_str(&format!("P3\n{} {}\n255\n", framebuffer.width, framebuffer.height));
+    for pixel in framebuffer.pixels.iter() {
+        color_utils::write_color(&mut buffer, pixel, 1, transform);
+    }
+    buffer.into_bytes()
+}
+
+// tone map and encode the framebuffer and write it as an 8-bit rgb png
+// the image data is stored without compression, which every png reader supports
+pub fn write_png(path: &str, framebuffer: &Framebuffer, transform: &OutputTransform) -> io::Result<()> {
+    let mut output = File::create(path)?;
+    output.write_all(&encode_png(framebuffer, transform))
+}
+
+pub fn encode_png(framebuffer: &Framebuffer, transform: &OutputTransform) -> Vec<u8> {
+    // every scanline starts with its filter type, 0 means no filter
+    let mut raw = Vec::with_capacity(framebuffer.height * (1 + 3 * framebuffer.width));
+    for y in 0..framebuffer.height {
+        raw.push(0);
+        for x in 0..framebuffer.width {
+            raw.extend_from_slice(&color_utils::to_rgb8(&framebuffer.get(x, y), transform));
+        }
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(framebuffer.width as u32).to_be_bytes());
+    header.extend_from_slice(&(framebuffer.height as u32).to_be_bytes());
+    // bit depth 8, color type 2 (rgb), deflate compression, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut bytes = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+    write_png_chunk(&mut bytes, b"IHDR", &header);
+    write_png_chunk(&mut bytes, b"IDAT", &zlib_stored(&raw));
+    write_png_chunk(&mut bytes, b"IEND", &[]);
+    bytes
+}
+
+fn write_png_chunk(bytes: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = bytes.len();
+    bytes.extend_from_slice(chunk_type);
+    bytes.extend_from_slice(data);
+    // the crc covers the chunk type and the data
+    let crc = crc32(&bytes[start..]);
+    bytes.extend_from_slice(&crc.to_be_bytes());
+}
+
+// a zlib stream made of uncompressed deflate blocks, each holds at most 65535 bytes
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0x78, 0x01];
+    let mut blocks = data.chunks(65535).peekable();
+    if blocks.peek().is_none() {
+        bytes.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        bytes.push(if last { 1 } else { 0 });
+        let len = block.len() as u16;
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(&(!len).to_le_bytes());
+        bytes.extend_from_slice(block);
+    }
+    bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    bytes
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// read a pgm or ppm image (ascii or binary) with values scaled to [0, 1]
+// the values are used as they are, without undoing any gamma
+pub fn read_pnm(path: &str) -> io::Result<Framebuffer> {
+    let bytes = fs::read(path)?;
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, message));
+
+    // the header is 4 whitespace separated tokens, comments start with '#' and go to the end of the line
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while tokens.len() < 4 {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'#') {
+            if bytes[pos] == b'#' {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+            } else {
+                pos += 1;
+            }
+        }
+        let start = pos;
+        while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(invalid("truncated header"));
+        }
+        tokens.push(String::from_utf8_lossy(&bytes[start..pos]).to_string());
+    }
+
+    let parse = |token: &str| token.parse::<usize>().map_err(|_| invalid("invalid header"));
+    let (width, height, max_value) = (parse(&tokens[1])?, parse(&tokens[2])?, parse(&tokens[3])?);
+    let (channels, binary) = match tokens[0].as_str() {
+        "P2" => (1, false),
+        "P3" => (3, false),
+        "P5" => (1, true),
+        "P6" => (3, true),
+        _ => return Err(invalid("only P2, P3, P5 and P6 images are supported"))
+    };
+    if max_value == 0 || (max_value > 255 && binary) {
+        return Err(invalid("unsupported max value"));
+    }
+
+    let count = width.checked_mul(height).and_then(|pixels| pixels.checked_mul(channels)).ok_or_else(|| invalid("image too large"))?;
+    let values: Vec<f32> = if binary {
+        // exactly one whitespace byte separates the header from the pixels
+        let end = (pos + 1).checked_add(count).ok_or_else(|| invalid("image too large"))?;
+        let data = bytes.get(pos + 1..end).ok_or_else(|| invalid("truncated pixel data"))?;
+        data.iter().map(|v| *v as f32 / max_value as f32).collect()
+    } else {
+        let values: Result<Vec<f32>, _> = String::from_utf8_lossy(&bytes[pos..]).split_ascii_whitespace()
+            .take(count).map(|v| v.parse::<f32>().map(|v| v / max_value as f32)).collect();
+        values.map_err(|_| invalid("invalid pixel value"))?
+    };
+    if values.len() != count {
+        return Err(invalid("truncated pixel data"));
+    }
+
+    let mut framebuffer = Framebuffer::new(width, height);
+    for (i, pixel) in framebuffer.pixels.iter_mut().enumerate() {
+        *pixel = if channels == 1 {
+            Color::new(values[i], values[i], values[i])
+        } else {
+            Color::new(values[3 * i], values[3 * i + 1], values[3 * i + 2])
+        };
+    }
+    Ok(framebuffer)
+}
+
+// pfm keeps the raw float values, scanlines go from the bottom to the top
+// a negative scale means the floats are little endian
+pub fn write_pfm(path: &str, framebuffer: &Framebuffer) -> io::Result<()> {
+    write_pfm_channels(path, framebuffer, 3)
+}
+
+// single channel pfm, only the `x` component of each pixel is written
+pub fn write_pfm_mono(path: &str, framebuffer: &Framebuffer) -> io::Result<()> {
+    write_pfm_channels(path, framebuffer, 1)
+}
+
+fn write_pfm_channels(path: &str, framebuffer: &Framebuffer, channels: usize) -> io::Result<()> {
+    let mut output = File::create(path)?;
+    output.write_all(&encode_pfm(framebuffer, channels))
+}
+
+// the bytes of a pfm file with 1 or 3 channels
+pub fn encode_pfm(framebuffer: &Framebuffer, channels: usize) -> Vec<u8> {
+    let magic = if channels == 3 { "PF" } else { "Pf" };
+    let mut bytes = format!("{}\n{} {}\n-1.0\n", magic, framebuffer.width, framebuffer.height).into_bytes();
+    for y in (0..framebuffer.height).rev() {
+        for x in 0..framebuffer.width {
+            let pixel = framebuffer.get(x, y);
+            for value in [pixel.x, pixel.y, pixel.z].iter().take(channels) {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    bytes
+}
+
+// read a pfm image as written by `write_pfm`, a single channel is repeated into all three
+pub fn read_pfm(path: &str) -> io::Result<Framebuffer> {
+    decode_pfm(&fs::read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+}
+
+// the width and height of a pfm image, without looking at the pixels
+pub fn pfm_size(bytes: &[u8]) -> Result<(usize, usize), String> {
+    pfm_header(bytes).map(|header| (header.width, header.height))
+}
+
+struct PfmHeader<'a> {
+    channels: usize,
+    width: usize,
+    height: usize,
+    scale: f32,
+    data: &'a [u8]
+}
+
+// the header is 3 lines: magic, size and scale
+fn pfm_header(bytes: &[u8]) -> Result<PfmHeader<'_>, String> {
+    let mut lines = bytes.splitn(4, |b| *b == b'\n');
+    let mut line = || lines.next().map(|l| String::from_utf8_lossy(l).trim().to_string()).ok_or("truncated header");
+    let channels = match line()?.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err("not a pfm image".to_string())
+    };
+    let size = line()?;
+    let (width, height) = size.split_once(' ')
+        .and_then(|(w, h)| w.parse::<usize>().ok().zip(h.parse::<usize>().ok()))
+        .ok_or("invalid size")?;
+    let scale: f32 = line()?.parse().map_err(|_| "invalid scale")?;
+    Ok(PfmHeader { channels, width, height, scale, data: lines.next().unwrap_or_default() })
+}
+
+pub fn decode_pfm(bytes: &[u8]) -> Result<Framebuffer, String> {
+    let PfmHeader { channels, width, height, scale, data } = pfm_header(bytes)?;
+    let length = width.checked_mul(height).and_then(|pixels| pixels.checked_mul(channels * 4)).ok_or("image too large")?;
+    if data.len() < length {
+        return Err("truncated pixel data".to_string());
+    }
+
+    let mut values = data.chunks_exact(4).map(|b| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if scale < 0.0 { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }
+    });
+    let mut framebuffer = Framebuffer::new(width, height);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let mut pixel = [0.0; 3];
+            for value in pixel.iter_mut().take(channels) {
+                *value = values.next().unwrap_or_default();
+            }
+            if channels == 1 {
+                pixel = [pixel[0]; 3];
+            }
+            framebuffer.set(x, y, Color::new(pixel[0], pixel[1], pixel[2]));
+        }
+    }
+    Ok(framebuffer)
+}
+
+// one named float channel of an exr image, e.g. "albedo.R"
+pub struct ExrChannel {
+    pub name: String,
+    pub values: Vec<f32>
+}
+
+impl ExrChannel {
+    pub fn rgb(prefix: &str, framebuffer: &Framebuffer) -> [ExrChannel; 3] {
+        ExrChannel::split(prefix, ["R", "G", "B"], framebuffer)
+    }
+
+    pub fn xyz(prefix: &str, framebuffer: &Framebuffer) -> [ExrChannel; 3] {
+        ExrChannel::split(prefix, ["X", "Y", "Z"], framebuffer)
+    }
+
+    pub fn mono(name: &str, framebuffer: &Framebuffer) -> ExrChannel {
+        ExrChannel { name: name.to_string(), values: framebuffer.pixels.iter().map(|p| p.x).collect() }
+    }
+
+    fn split(prefix: &str, names: [&str; 3], framebuffer: &Framebuffer) -> [ExrChannel; 3] {
+        let pixels = &framebuffer.pixels;
+        [
+            ExrChannel { name: format!("{}{}", prefix, names[0]), values: pixels.iter().map(|p| p.x).collect() },
+            ExrChannel { name: format!("{}{}", prefix, names[1]), values: pixels.iter().map(|p| p.y).collect() },
+            ExrChannel { name: format!("{}{}", prefix, names[2]), values: pixels.iter().map(|p| p.z).collect() }
+        ]
+    }
+}
+
+// write an uncompressed, single part, scanline exr with 32-bit float channels
+// see https://openexr.com/en/latest/OpenEXRFileLayout.html for the layout
+pub fn write_exr(path: &str, width: usize, height: usize, channels: Vec<ExrChannel>) -> io::Result<()> {
+    let mut output = File::create(path)?;
+    output.write_all(&encode_exr(width, height, channels))
+}
+
+pub fn encode_exr(width: usize, height: usize, mut channels: Vec<ExrChannel>) -> Vec<u8> {
+    // exr readers expect the channel list to be sorted by name
+    channels.sort_by(|a, b| a.name.as_bytes().cmp(b.name.as_bytes()));
+    let long_names = channels.iter().any(|c| c.name.len() > 31);
+
+    let mut bytes: Vec<u8> = Vec::new();
+    bytes.extend_from_slice(&20000630_i32.to_le_bytes());
+    // version 2, set the "long names" flag if any channel name is longer than 31 bytes
+    let version: i32 = if long_names { 2 | 0x400 } else { 2 };
+    bytes.extend_from_slice(&version.to_le_bytes());
+
+    let mut chlist: Vec<u8> = Vec::new();
+    for channel in channels.iter() {
+        chlist.extend_from_slice(channel.name.as_bytes());
+        chlist.push(0);
+        // pixel type 2 is FLOAT, then pLinear and 3 reserved bytes, then x and y sampling
+        chlist.extend_from_slice(&2_i32.to_le_bytes());
+        chlist.extend_from_slice(&[0, 0, 0, 0]);
+        chlist.extend_from_slice(&1_i32.to_le_bytes());
+        chlist.extend_from_slice(&1_i32.to_le_bytes());
+    }
+    chlist.push(0);
+
+    let mut window: Vec<u8> = Vec::new();
+    for value in [0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+
+    write_exr_attribute(&mut bytes, "channels", "chlist", &chlist);
+    write_exr_attribute(&mut bytes, "compression", "compression", &[0]);
+    write_exr_attribute(&mut bytes, "dataWindow", "box2i", &window);
+    write_exr_attribute(&mut bytes, "displayWindow", "box2i", &window);
+    write_exr_attribute(&mut bytes, "lineOrder", "lineOrder", &[0]);
+    write_exr_attribute(&mut bytes, "pixelAspectRatio", "float", &1.0_f32.to_le_bytes());
+    write_exr_attribute(&mut bytes, "screenWindowCenter", "v2f", &[0; 8]);
+    write_exr_attribute(&mut bytes, "screenWindowWidth", "float", &1.0_f32.to_le_bytes());
+    bytes.push(0);
+
+    // every scanline is its own chunk, the offset table points at each of them
+    let line_size = width * channels.len() * 4;
+    let chunk_size = 8 + line_size;
+    let first_chunk = bytes.len() + height * 8;
+    for y in 0..height {
+        bytes.extend_from_slice(&((first_chunk + y * chunk_size) as u64).to_le_bytes());
+    }
+
+    for y in 0..height {
+        bytes.extend_from_slice(&(y as i32).to_le_bytes());
+        bytes.extend_from_slice(&(line_size as i32).to_le_bytes());
+        for channel in channels.iter() {
+            for value in channel.values[y * width..(y + 1) * width].iter() {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    bytes
+}
+
+fn write_exr_attribute(bytes: &mut Vec<u8>, name: &str, attribute_type: &str, value: &[u8]) {
+    bytes.extend_from_slice(name.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(attribute_type.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::image_io::*;
+    use crate::vec3::Color;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("image_io_test_{}_{}", std::process::id(), name));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn floats(bytes: &[u8]) -> Vec<f32> {
+        bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+    }
+
+    fn test_image() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(2, 2);
+        framebuffer.set(0, 0, Color::new(1.0, 2.0, 3.0));
+        framebuffer.set(0, 1, Color::new(-0.5, 0.25, 4.0));
+        framebuffer
+    }
+
+    #[test]
+    fn test_write_pfm() {
+        let path = temp_path("rgb.pfm");
+        write_pfm(&path, &test_image()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert!(bytes.starts_with(header));
+        assert_eq!(bytes.len(), header.len() + 2 * 2 * 3 * 4);
+        // the bottom scanline comes first
+        let values = floats(&bytes[header.len()..]);
+        assert_eq!(&values[0..3], &[-0.5, 0.25, 4.0]);
+        assert_eq!(&values[6..9], &[1.0, 2.0, 3.0]);
+
+        let path = temp_path("mono.pfm");
+        write_pfm_mono(&path, &test_image()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let header = b"Pf\n2 2\n-1.0\n";
+        assert!(bytes.starts_with(header));
+        assert_eq!(floats(&bytes[header.len()..]), vec![-0.5, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_decode_pfm() {
+        let bytes = encode_pfm(&test_image(), 3);
+        assert_eq!(pfm_size(&bytes), Ok((2, 2)));
+        assert_eq!(decode_pfm(&bytes).unwrap().pixels, test_image().pixels);
+        assert_eq!(decode_pfm(&bytes[..bytes.len() - 1]).err().as_deref(), Some("truncated pixel data"));
+        // the size alone overflows
+        let huge = b"PF\n4611686018427387904 4\n-1.0\n";
+        assert_eq!(pfm_size(huge), Ok((4611686018427387904, 4)));
+        assert_eq!(decode_pfm(huge).err().as_deref(), Some("image too large"));
+    }
+
+    #[test]
+    fn test_read_pnm() {
+        let path = temp_path("gray.pgm");
+        std::fs::write(&path, b"P5\n# a comment\n2 1\n255\n\x00\xff").unwrap();
+        let image = read_pnm(&path).unwrap();
+        assert_eq!(image.pixels, vec![Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0)]);
+
+        for header in ["P6\n4611686018427387904 4\n255\n", "P6\n6148914691236517205 1\n255\n"] {
+            std::fs::write(&path, header).unwrap();
+            assert!(read_pnm(&path).err().unwrap().to_string().ends_with("image too large"));
+        }
+    }
+
+    #[test]
+    fn test_write_exr() {
+        let image = test_image();
+        let path = temp_path("layers.exr");
+        let mut channels = vec![ExrChannel::mono("Z", &image)];
+        channels.extend(ExrChannel::rgb("albedo.", &image));
+        write_exr(&path, 2, 2, channels).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+
+        assert_eq!(&bytes[0..4], &[0x76, 0x2f, 0x31, 0x01]);
+        assert_eq!(&bytes[4..8], &2_i32.to_le_bytes());
+        // the channel names come sorted by their bytes, upper case first
+        let header = String::from_utf8_lossy(&bytes);
+        let names: Vec<usize> = ["Z\0", "albedo.B", "albedo.G", "albedo.R"].iter().map(|name| header.find(name).unwrap()).collect();
+        assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // two scanline chunks of y, size and 4 channels of 2 floats, after the offset table pointing at them
+        let chunk_size = 8 + 4 * 2 * 4;
+        let first_chunk = bytes.len() - 2 * chunk_size;
+        let table = &bytes[first_chunk - 16..first_chunk];
+        assert_eq!(u64::from_le_bytes(table[0..8].try_into().unwrap()) as usize, first_chunk);
+        assert_eq!(u64::from_le_bytes(table[8..16].try_into().unwrap()) as usize, first_chunk + chunk_size);
+        let second = &bytes[first_chunk + chunk_size..];
+        assert_eq!(&second[0..4], &1_i32.to_le_bytes());
+        assert_eq!(&second[4..8], &32_i32.to_le_bytes());
+        // exr goes from the top, this is the bottom scanline, Z first
+        assert_eq!(floats(&second[8..24]), vec![-0.5, 0.0, 4.0, 0.0]);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::camera::Camera;
+use crate::filter::{Filter, FilterKind};
+use crate::hittable_list::HittableList;
+use crate::navigation::OrbitView;
+use crate::render::{self, RenderSettings};
+use crate::rng;
+use crate::scene::{self, SceneKind};
+use crate::vec3::{Point3, Vec3};
+
+// the longest side and the most pixels of an image a job takes, anything bigger is most likely a typo
+// and shouldn't take the memory of the worker or the render server
+const MAX_SIDE: f32 = 65536.0;
+const MAX_PIXELS: usize = 7680 * 4320;
+
+// everything needed to render an image, as a small text document that can be sent to another process:
+// which built-in scene, the seed it's built with, the camera and the render settings
+// with the seed every process builds the same scene and gets the same pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderJob {
+    pub scene: SceneKind,
+    pub seed: u64,
+    pub view: OrbitView,
+    // `seed` overrides the seed of the settings
+    pub settings: RenderSettings
+}
+
+// the cover image
+impl Default for RenderJob {
+    fn default() -> Self {
+        RenderJob {
+            scene: SceneKind::Random,
+            seed: 0,
+            view: OrbitView {
+                look_from: Point3::new(13.0, 2.0, 3.0),
+                look_at: Point3::new(0.0, 0.0, 0.0),
+                vup: Vec3::new(0.0, 1.0, 0.0),
+                vfov: 20.0,
+                aperture: 0.1,
+                focus_dist: 10.0
+            },
+            settings: RenderSettings::default()
+        }
+    }
+}
+
+impl RenderJob {
+    // one `key values...` per line, the keys that are left out keep the defaults, e.g.
+    //   scene random
+    //   seed 7
+    //   size 600 400
+    //   samples 50
+    //   look_from 13 2 3
+    // empty lines and everything after a '#' are ignored
+    pub fn parse(text: &str) -> Result<RenderJob, String> {
+        let mut job = RenderJob::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut words = line.split_whitespace();
+            let Some(key) = words.next() else {
+                continue;
+            };
+            let values: Vec<&str> = words.collect();
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+            let numbers = |count: usize| -> Result<Vec<f32>, String> {
+                let numbers: Vec<f32> = values.iter().map(|v| v.parse::<f32>()).collect::<Result<_, _>>()
+                    .map_err(|_| error(&format!("expected numbers after '{}'", key)))?;
+                if numbers.len() != count || numbers.iter().any(|n| !n.is_finite()) {
+                    return Err(error(&format!("expected {} numbers after '{}'", count, key)));
+                }
+                Ok(numbers)
+            };
+            let vector = |v: Vec<f32>| Vec3::new(v[0], v[1], v[2]);
+            let positive = |v: f32| if v >= 1.0 { Ok(v) } else { Err(error(&format!("'{}' must be at least 1", key))) };
+
+            match key {
+                "scene" => {
+                    let name = values.first().copied().unwrap_or("");
+                    job.scene = SceneKind::from_name(name).ok_or_else(|| error(&format!("unknown scene '{}'", name)))?;
+                },
+                "seed" => job.seed = values.first().and_then(|v| v.parse().ok()).ok_or_else(|| error("invalid seed"))?,
+                "size" => {
+                    // the camera spreads the pixels from 0 to width - 1, so a side needs at least 2 of them
+                    let size = numbers(2)?;
+                    if size.iter().any(|side| *side < 2.0) {
+                        return Err(error("an image needs at least 2x2 pixels"));
+                    }
+                    if size.iter().any(|side| *side > MAX_SIDE) {
+                        return Err(error(&format!("a side of the image is limited to {} pixels", MAX_SIDE)));
+                    }
+                    job.settings.image_width = size[0] as usize;
+                    job.settings.image_height = size[1] as usize;
+                    if job.settings.image_width.checked_mul(job.settings.image_height).is_none_or(|pixels| pixels > MAX_PIXELS) {
+                        return Err(error(&format!("the image is limited to {} pixels", MAX_PIXELS)));
+                    }
+                },
+                "samples" => job.settings.samples_per_pixel = positive(numbers(1)?[0])? as i32,
+                "max_depth" => job.settings.max_depth = positive(numbers(1)?[0])? as i32,
+                "filter" => {
+                    let name = values.first().copied().unwrap_or("");
+                    let kind = FilterKind::from_name(name).ok_or_else(|| error(&format!("unknown filter '{}'", name)))?;
+                    job.settings.filter = Filter::new(kind);
+                    if let Some(radius) = values.get(1) {
+                        job.settings.filter.radius = radius.parse().ok().filter(|r: &f32| *r > 0.0)
+                            .ok_or_else(|| error("invalid filter radius"))?;
+                    }
+                },
+                // seconds, as many samples as fit in that time, up to `samples`
+                "budget" => job.settings.time_budget = Some(render::time_budget(numbers(1)?[0]).map_err(|e| error(&e))?),
+                "spectral" => job.settings.spectral = match values.first().copied() {
+                    Some("true") => true,
+                    Some("false") => false,
+                    _ => return Err(error("expected 'true' or 'false' after 'spectral'"))
+                },
+                "look_from" => job.view.look_from = vector(numbers(3)?),
+                "look_at" => job.view.look_at = vector(numbers(3)?),
+                "vup" => job.view.vup = vector(numbers(3)?),
+                "vfov" => job.view.vfov = numbers(1)?[0],
+                "aperture" => job.view.aperture = numbers(1)?[0],
+                "focus_dist" => job.view.focus_dist = numbers(1)?[0],
+                _ => return Err(error(&format!("unknown key '{}'", key)))
+            }
+        }
+        Ok(job)
+    }
+
+    // the document `parse` reads back into the same job
+    pub fn to_text(&self) -> String {
+        let vector = |v: Vec3| format!("{} {} {}", v.x, v.y, v.z);
+        let settings = &self.settings;
+        [
+            format!("scene {}", self.scene.name()),
+            format!("seed {}", self.seed),
+            format!("size {} {}", settings.image_width, settings.image_height),
+            format!("samples {}", settings.samples_per_pixel),
+            format!("max_depth {}", settings.max_depth),
+            format!("filter {} {}", settings.filter.kind.name(), settings.filter.radius),
+            format!("spectral {}", settings.spectral),
+            format!("look_from {}", vector(self.view.look_from)),
+            format!("look_at {}", vector(self.view.look_at)),
+            format!("vup {}", vector(self.view.vup)),
+            format!("vfov {}", self.view.vfov),
+            format!("aperture {}", self.view.aperture),
+            format!("focus_dist {}", self.view.focus_dist)
+        ].iter().chain(settings.time_budget.map(|budget| format!("budget {}", budget.as_secs_f32())).iter())
+            .map(|line| format!("{}\n", line)).collect()
+    }
+
+    // seeds this thread's random numbers first, so the random scene comes out the same everywhere
+    pub fn build_world(&self) -> HittableList {
+        rng::seed(self.seed);
+        match self.scene {
+            SceneKind::Random => scene::random_scene(),
+            SceneKind::Landscape => scene::landscape_scene(None).expect("the noise terrain needs no height map")
+        }
+    }
+
+    pub fn camera(&self) -> Camera {
+        self.view.camera(self.settings.image_width as f32 / self.settings.image_height as f32)
+    }
+
+    pub fn settings(&self) -> RenderSettings {
+        RenderSettings { seed: Some(self.seed), ..self.settings }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+
+    use crate::job::*;
+
+    #[test]
+    fn test_parse() {
+        let job = RenderJob::parse("# a small one\nscene landscape\nseed 7\nsize 60 40\nfilter gaussian 2\n\nlook_from 1 2.5 -3 # close\n").unwrap();
+        assert_eq!(job.scene, SceneKind::Landscape);
+        assert_eq!(job.seed, 7);
+        assert_eq!((job.settings.image_width, job.settings.image_height), (60, 40));
+        assert_eq!(job.settings.filter, Filter { kind: FilterKind::Gaussian, radius: 2.0 });
+        assert_eq!(job.view.look_from, Point3::new(1.0, 2.5, -3.0));
+        assert_eq!(job.settings.samples_per_pixel, RenderJob::default().settings.samples_per_pixel);
+        assert_eq!(job.settings().seed, Some(7));
+
+        assert_eq!(RenderJob::parse(&job.to_text()), Ok(job));
+        let budgeted = RenderJob::parse("budget 1.5").unwrap();
+        assert_eq!(budgeted.settings.time_budget, Some(Duration::from_millis(1500)));
+        assert_eq!(RenderJob::parse(&budgeted.to_text()), Ok(budgeted));
+        assert_eq!(RenderJob::parse("budget 0").unwrap_err(), "line 1: the time budget must be positive");
+        for huge in ["budget 1e19", "budget 1e30"] {
+            assert_eq!(RenderJob::parse(huge).unwrap_err(), "line 1: the time budget is limited to 604800 seconds");
+        }
+
+        assert_eq!(RenderJob::parse("size 60").unwrap_err(), "line 1: expected 2 numbers after 'size'");
+        assert_eq!(RenderJob::parse("size 1 40").unwrap_err(), "line 1: an image needs at least 2x2 pixels");
+        assert_eq!(RenderJob::parse("size 4294967296 40").unwrap_err(), "line 1: a side of the image is limited to 65536 pixels");
+        assert_eq!(RenderJob::parse("size 60000 60000").unwrap_err(), "line 1: the image is limited to 33177600 pixels");
+        assert_eq!(RenderJob::parse("\nsamples 0").unwrap_err(), "line 2: 'samples' must be at least 1");
+        assert_eq!(RenderJob::parse("lights on").unwrap_err(), "line 1: unknown key 'lights'");
+    }
+}
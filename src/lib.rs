@@ -0,0 +1,51 @@
+// a simple ray tracer following "Ray Tracing in One Weekend"
+//
+// build a scene out of `Hittable`s (see `scene` for an example), point a `Camera` at it,
+// `render::render` it into a float `Framebuffer` and write that out with `image_io`
+//
+// `rng`, `simd`, `packet`, `distributed` and `server` are internals, what the binaries need from them is re-exported below
+pub(crate) mod rng;
+pub mod vec3;
+pub(crate) mod simd;
+pub mod color_utils;
+pub mod ray;
+pub mod hittable;
+pub mod hittable_list;
+pub mod sphere;
+pub(crate) mod packet;
+pub mod shapes;
+pub mod csg;
+pub mod sdf;
+pub mod heightfield;
+pub mod cutout;
+pub mod camera;
+pub mod navigation;
+pub mod animation;
+pub mod material;
+pub mod texture;
+pub mod framebuffer;
+pub mod cancel;
+pub mod render;
+pub mod aov;
+pub mod image_io;
+pub mod denoise;
+pub mod filter;
+pub mod spectrum;
+pub mod scene;
+pub mod job;
+pub(crate) mod distributed;
+pub(crate) mod server;
+pub mod stats;
+pub mod progress;
+pub mod preview;
+
+pub use camera::{Camera, CameraModel};
+pub use distributed::{render_distributed, serve as serve_worker};
+pub use framebuffer::Framebuffer;
+pub use hittable::{HitRecord, Hittable};
+pub use hittable_list::HittableList;
+pub use packet::SphereSet;
+pub use render::{render, RenderOutput, RenderSettings};
+pub use rng::seed;
+pub use server::serve as serve_http;
+pub use vec3::{Color, Point3, Vec3};
@@ -1,148 +1,491 @@
-use std::fs::File;
-use std::io::Write;
-use std::rc::Rc;
-use rand;
-
-mod vec3;
-mod color_utils;
-mod ray;
-mod hittable;
-mod hittable_list;
-mod sphere;
-mod camera;
-mod material;
-
-use hittable::Hittable;
-use hittable_list::HittableList;
-use rand::Rng;
-use ray::Ray;
-use vec3::Point3;
-use vec3::Vec3;
-use vec3::Color;
-use sphere::Sphere;
-use camera::Camera;
-use material::*;
-
-fn ray_color(r: &Ray, hittable: &impl Hittable, depth: i32) -> Color {
-
-    // if we've exceeded the ray bouncing limit, we will not gather more light
-    if depth <= 0 {
-        return Color::black();
-    }
-
-    // some of the reflected rays hit the object they are reflecting off of not at exactly t = 0,
-    // but something extremely close to 0 (shadow acne problem)
-    match hittable.hit(r, 0.001, f32::MAX) {
-        Some(rec) => {
-            match rec.material.scatter(r, &rec) {
-                Some((attenuation, scattered)) => {
-                    return attenuation * ray_color(&scattered, hittable, depth - 1);
-                },
-                None => return Color::white()
-            }
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::time::Instant;
+
+use ray_tracing_rust::animation::{CameraAnimation, CameraKeyframe};
+use ray_tracing_rust::aov::AovOutput;
+use ray_tracing_rust::camera::{self, ApertureMask, ApertureShape, Camera, CameraModel, EquirectangularCamera, FisheyeCamera, OrthographicCamera};
+use ray_tracing_rust::cancel::CancellationToken;
+use ray_tracing_rust::denoise::{self, DenoiseSettings};
+use ray_tracing_rust::framebuffer::{Framebuffer, Region};
+use ray_tracing_rust::hittable_list::HittableList;
+use ray_tracing_rust::image_io;
+use ray_tracing_rust::job::RenderJob;
+use ray_tracing_rust::navigation::OrbitView;
+use ray_tracing_rust::preview::{Preview, PreviewFile};
+use ray_tracing_rust::progress::{self, Progress};
+use ray_tracing_rust::render::{self, RenderOutput, RenderSettings};
+use ray_tracing_rust::scene::{self, SceneKind};
+use ray_tracing_rust::stats::Report;
+use ray_tracing_rust::vec3::{Point3, Vec3};
+use ray_tracing_rust::{render_distributed, serve_worker};
+
+mod options;
+
+use options::{Options, ProgressMode, Projection};
+
+// what the user sees of the run: progress and maybe the image while rendering, the statistics at the end
+struct Feedback {
+    progress: Box<dyn Progress>,
+    preview: Option<Box<dyn Preview>>,
+    report: Report,
+    // ctrl-c stops the render and the image so far is written
+    cancel: CancellationToken
+}
+
+// with a preview the image is rendered in passes, more passes show up sooner but each one costs a redraw
+const SAMPLES_PER_PASS: i32 = 1;
+
+// the grid the image is cut into for the workers, more tiles than workers so the fast ones pick up more
+const WORKER_TILES: (usize, usize) = (8, 8);
+
+// with a time budget the samples only stop at the deadline
+const BUDGET_SAMPLES: i32 = 1 << 20;
+
+fn main() -> io::Result<()> {
+    let start_time = Instant::now();
+    let mut options = Options::parse(std::env::args().skip(1))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut feedback = Feedback {
+        progress: match options.progress {
+            ProgressMode::Bar => Box::new(progress::Bar::new()),
+            ProgressMode::Json => Box::new(progress::JsonLines::new()),
+            ProgressMode::Quiet => Box::new(progress::Quiet)
         },
-        None => (),
+        preview: None,
+        report: Report::default(),
+        cancel: CancellationToken::new()
+    };
+    if let Some((columns, rows)) = options.stitch {
+        return stitch(&options, columns, rows);
+    }
+    if let Some(address) = options.worker.as_ref() {
+        let listener = TcpListener::bind(address)?;
+        if options.progress != ProgressMode::Quiet {
+            eprintln!("worker listening on {}", listener.local_addr()?);
+        }
+        return serve_worker(listener);
     }
-    let unit_direction = r.direction.unit_vector();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    (1.0 - t) * Color::black() + t * Color {x: 0.5, y: 0.7, z: 1.0}
-}
 
-fn main() -> std::io::Result<()> {
-    
     // image
-    let aspect_ratio: f32 = 3.0 / 2.0;
-    let image_width: i32 = 1200;
-    let image_height: i32 = (image_width as f32 / aspect_ratio) as i32;
-    let samples_per_pixel: i32 = 100;
-    let max_depth: i32 = 50;
-
-    // world
-    let world = random_scene();
+    let defaults = RenderSettings::default();
+    let aspect_ratio = options.projection.aspect_ratio();
+    let image_width: usize = defaults.image_width;
+    let image_height: usize = (image_width as f32 / aspect_ratio) as usize;
+    let samples_per_pixel: i32 = if options.time_budget.is_some() { BUDGET_SAMPLES } else { defaults.samples_per_pixel };
+    let max_depth: i32 = defaults.max_depth;
 
     // camera
-    let look_from = Point3::new(13.0, 2.0, 3.0);
-    let look_to = Point3::new(0.0, 0.0, 0.0);
-
-    let camera = Camera::new(
-        look_from,
-        look_to,
-        Vec3::new(0.0, 1.0, 0.0),
-        20.0,
-        aspect_ratio,
-        0.1,
-        10.0
-    );
+    let start = CameraKeyframe {
+        frame: 1.0,
+        look_from: Point3::new(13.0, 2.0, 3.0),
+        look_at: Point3::new(0.0, 0.0, 0.0),
+        vfov: 20.0,
+        focus_dist: 10.0
+    };
+    if options.projection == Projection::Perspective {
+        if let Some(lens) = options.lens {
+            options.output_transform.exposure += lens.exposure();
+        }
+    }
+    let aperture_shape = match options.aperture_image.as_ref() {
+        Some(path) => Some(ApertureShape::Image(ApertureMask::new(&image_io::read_pnm(path)?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{}: aperture image is completely black", path)))?)),
+        None => options.blades.map(|blades| ApertureShape::Polygon { blades, rotation: 0.0 })
+    };
 
     // render
-    let mut buffer = String::new();
-    let mut rng = rand::thread_rng();
-
-    buffer.push_str(&format!("P3\n{} {}\n255\n", image_width, image_height));
-
-    for j in (0..image_height).rev() {
-        println!("Scanlines remaining: {}", j);
-        for i in 0..image_width {
-            let mut pixel_color = Color::new_empty();
-            for _ in 0..samples_per_pixel {
-                let u = (i as f32 + rng.gen::<f32>()) / (image_width - 1) as f32;
-                let v = (j as f32 + rng.gen::<f32>()) / (image_height - 1) as f32;
-                let r = camera.get_ray(u, v);
-                pixel_color += ray_color(&r, &world, max_depth);
+    let region = match (options.tiles, options.tile) {
+        (Some((columns, rows)), Some(tile)) => {
+            Region::check_grid(image_width, image_height, columns, rows).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            Some(Region::tile(image_width, image_height, columns, rows, tile))
+        },
+        _ => options.region
+    };
+    if let Some(region) = region.filter(|r| r.x + r.width > image_width || r.y + r.height > image_height) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("region {:?} is outside the {}x{} image", region, image_width, image_height)));
+    }
+    let settings = RenderSettings {
+        image_width,
+        image_height,
+        samples_per_pixel,
+        max_depth,
+        filter: options.filter,
+        spectral: options.spectral,
+        // the denoiser is guided by the albedo and normal passes
+        collect_aovs: options.aov.is_some() || options.denoise,
+        region,
+        seed: options.seed,
+        time_budget: options.time_budget
+    };
+
+    if let Some(workers) = options.workers.as_ref() {
+        return render_on_workers(&options, workers, &start, &settings, feedback, start_time);
+    }
+
+    // world, shared by every frame
+    // the random scene is only the same on every run (and every machine) with a seed
+    if let Some(seed) = options.seed {
+        ray_tracing_rust::seed(seed);
+    }
+    let phase_start = Instant::now();
+    let world = match options.scene {
+        SceneKind::Random => scene::random_scene(),
+        SceneKind::Landscape => {
+            let height_map = options.height_map.as_ref().map(|path| image_io::read_pnm(path)).transpose()?;
+            scene::landscape_scene(height_map.as_ref()).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", options.height_map.as_deref().unwrap_or_default(), e))
+            })?
+        }
+    };
+    feedback.report.add_phase("scene build", phase_start.elapsed());
+
+    let animation = match options.keyframes.as_ref() {
+        Some(path) => Some(CameraAnimation::parse(&fs::read_to_string(path)?, options.interpolation)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))?),
+        None => options.turntable.map(|frames| CameraAnimation::turntable(&start, frames))
+    };
+    if animation.is_some() && options.tile.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--tile renders a single image, not an animation"));
+    }
+
+    // in the window of a single perspective image the camera can be moved around
+    let interactive = options.preview && options.preview_file.is_none() && animation.is_none() && options.projection == Projection::Perspective;
+    feedback.preview = match options.preview_file.as_ref() {
+        Some(path) => Some(Box::new(PreviewFile::new(path, options.output_transform, samples_per_pixel))),
+        None if options.preview && !interactive => Some(open_window(image_width, image_height, &options)?),
+        None => None
+    };
+    let progressive = feedback.preview.is_some() || settings.time_budget.is_some();
+    let progress_units = if progressive { render::progress_units(&settings, SAMPLES_PER_PASS) } else { image_height as u64 };
+    cancel_on_interrupt(&feedback.cancel);
+
+    let Some(animation) = animation else {
+        let output = if interactive {
+            explore(&options, &start, aperture_shape.as_ref(), aspect_ratio, &world, &settings, &mut feedback)?
+        } else {
+            feedback.progress.start("render", progress_units);
+            let output = render_frame(&options, &start, aperture_shape.as_ref(), aspect_ratio, &world, &settings, &mut feedback)?;
+            feedback.progress.finish();
+            output
+        };
+        let phase_start = Instant::now();
+        match (options.tile, settings.region) {
+            // lossless, so the stitched image is the same as a full render
+            (Some(tile), Some(_)) => image_io::write_pfm(&format!("tile_{:04}.pfm", tile), &output.beauty)?,
+            _ => {
+                let output = crop(&options, &settings, output);
+                image_io::write_ppm("image.ppm", &output.beauty, &options.output_transform)?;
+                write_aovs(&options, &output, "image")?;
             }
+        }
+        feedback.report.add_phase("encode", phase_start.elapsed());
+        return write_report(&options, feedback.report, start_time);
+    };
 
-            color_utils::write_color(&mut buffer, &pixel_color, samples_per_pixel);
+    // the last turntable keyframe is the first one again, leave it out so the sequence loops
+    let last_frame = animation.last_frame().round() as i32 - if options.keyframes.is_none() { 1 } else { 0 };
+    let (first, last) = options.frames.unwrap_or((animation.first_frame().round() as i32, last_frame));
+    for frame in first..=last {
+        feedback.progress.start(&format!("frame {} of {}..{}", frame, first, last), progress_units);
+        let key = animation.evaluate(frame as f32);
+        let output = render_frame(&options, &key, aperture_shape.as_ref(), aspect_ratio, &world, &settings, &mut feedback)?;
+        feedback.progress.finish();
+        let output = crop(&options, &settings, output);
+        let interrupted = feedback.cancel.is_cancelled();
+        let phase_start = Instant::now();
+        let stem = format!("frame_{:04}", frame);
+        image_io::write_png(&format!("{}.png", stem), &output.beauty, &options.output_transform)?;
+        write_aovs(&options, &output, &stem)?;
+        feedback.report.add_phase("encode", phase_start.elapsed());
+        if interrupted || feedback.preview.as_ref().is_some_and(|preview| !preview.is_open()) {
+            break;
         }
     }
 
-    let mut output = File::create("image.ppm")?;
-    output.write(buffer.as_bytes())?;
+    write_report(&options, feedback.report, start_time)
+}
+
+#[cfg(feature = "preview")]
+fn open_window(width: usize, height: usize, options: &Options) -> io::Result<Box<dyn Preview>> {
+    let window = ray_tracing_rust::preview::PreviewWindow::new("preview", width, height, options.output_transform)
+        .map_err(io::Error::other)?;
+    Ok(Box::new(window))
+}
+
+#[cfg(not(feature = "preview"))]
+fn open_window(_width: usize, _height: usize, _options: &Options) -> io::Result<Box<dyn Preview>> {
+    Err(no_preview_window())
+}
+
+#[cfg(not(feature = "preview"))]
+fn no_preview_window() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "--preview needs the preview window, build with `--features preview`")
+}
 
+// the first ctrl-c cancels the render, a second one quits right away
+// the handler runs on a thread of its own, not in the signal handler, so it can touch the token
+fn cancel_on_interrupt(cancel: &CancellationToken) {
+    let cancel = cancel.clone();
+    let result = ctrlc::set_handler(move || {
+        if cancel.is_cancelled() {
+            std::process::exit(130);
+        }
+        cancel.cancel();
+    });
+    if let Err(e) = result {
+        eprintln!("ctrl-c won't stop the render early: {}", e);
+    }
+}
+
+// the statistics of the whole run, on stderr unless quiet and optionally as json
+fn write_report(options: &Options, mut report: Report, start_time: Instant) -> io::Result<()> {
+    report.wall_time = start_time.elapsed();
+    if options.progress != ProgressMode::Quiet {
+        eprint!("\n{}", report.to_text());
+    }
+    if let Some(path) = options.stats_json.as_ref() {
+        fs::write(path, report.to_json() + "\n")?;
+    }
     Ok(())
 }
 
-fn random_scene() -> HittableList {
-    let mut world = HittableList { objects: Vec::new() };
-
-    let ground_material = Rc::new(Lambertian {albedo: Color::new(0.5, 0.5, 0.5)});
-    world.add(Rc::new(Sphere { center: Point3 {x: 0.0, y: -1000.0, z: 0.0}, radius: 1000.0, material: ground_material }));
-
-    let mut rng = rand::thread_rng();
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = rng.gen::<f32>();
-            let center = Point3::new(a as f32 + 0.9 * rng.gen::<f32>(), 0.2, b as f32 + 0.9 * rng.gen::<f32>());
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                let sphere_material: Rc<dyn Material>;
-
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = Color::random() * Color::random();
-                    sphere_material = Rc::new(Lambertian {albedo: albedo});
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Color::random_range(0.5, 1.0);
-                    let fuzz: f32 = rng.gen_range(0.0..0.5);
-                    sphere_material = Rc::new(Metal {albedo, fuzz});
-                } else {
-                    // glass
-                    sphere_material = Rc::new(Dielectric {ir: 1.5});
-                }
-                world.add(Rc::new(Sphere {center: center, radius: 0.2, material: sphere_material}));
+// set up the camera for one frame and render it
+fn render_frame(
+    options: &Options,
+    key: &CameraKeyframe,
+    aperture_shape: Option<&ApertureShape>,
+    aspect_ratio: f32,
+    world: &HittableList,
+    settings: &RenderSettings,
+    feedback: &mut Feedback
+) -> io::Result<RenderOutput> {
+    let look_from = key.look_from;
+    let look_to = key.look_at;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let vfov = key.vfov;
+    let mut focus_dist = key.focus_dist;
+    if options.autofocus {
+        match camera::autofocus(look_from, look_to, world) {
+            Some(distance) => focus_dist = distance,
+            None => eprintln!("autofocus: nothing in the center of the image, keeping focus distance {}", focus_dist)
+        }
+    }
+
+    let camera: Box<dyn CameraModel> = match options.projection {
+        Projection::Perspective => {
+            let mut camera = match options.lens {
+                Some(lens) => Camera::from_physical(look_from, look_to, vup, &lens, aspect_ratio, focus_dist),
+                None => Camera::new(
+                    look_from,
+                    look_to,
+                    vup,
+                    vfov,
+                    aspect_ratio,
+                    0.1,
+                    focus_dist
+                )
+            };
+            if let Some(shape) = aperture_shape {
+                camera.set_aperture_shape(shape.clone());
             }
+            Box::new(camera)
+        },
+        // same framing as the perspective camera at the focus distance
+        Projection::Orthographic => Box::new(OrthographicCamera::new(
+            look_from, look_to, vup, 2.0 * focus_dist * (vfov.to_radians() / 2.0).tan(), aspect_ratio)),
+        Projection::Panorama => Box::new(EquirectangularCamera::new(look_from, look_to, vup)),
+        Projection::Fisheye => Box::new(FisheyeCamera::new(look_from, look_to, vup, 180.0, aspect_ratio))
+    };
+
+    let (progress, cancel) = (feedback.progress.as_ref(), &feedback.cancel);
+    let mut output = match (feedback.preview.as_mut(), settings.time_budget) {
+        (Some(preview), _) => render::render_progressive(camera.as_ref(), world, settings, SAMPLES_PER_PASS, progress, cancel, |accumulator| {
+            preview.update(accumulator)
+        }),
+        (None, Some(_)) => render::render_progressive(camera.as_ref(), world, settings, SAMPLES_PER_PASS, progress, cancel, |_| true),
+        (None, None) => render::render_with_progress(camera.as_ref(), world, settings, progress, cancel)
+    };
+    if cancel.is_cancelled() {
+        match output.samples_per_pixel {
+            0 => eprintln!("\ninterrupted, writing the scanlines that are done"),
+            samples => eprintln!("\ninterrupted, writing the image so far ({} samples per pixel)", samples)
+        }
+    }
+    finish_frame(options, &mut output, &mut feedback.report);
+    Ok(output)
+}
+
+// count the render into the report and denoise it
+fn finish_frame(options: &Options, output: &mut RenderOutput, report: &mut Report) {
+    report.counters += output.counters;
+    report.add_phase("render", output.duration);
+    report.add_samples_per_pixel(output.samples_per_pixel);
+
+    if options.denoise {
+        if let Some(aovs) = output.aovs.as_ref() {
+            let phase_start = Instant::now();
+            output.beauty = denoise::denoise(&output.beauty, &aovs.albedo, &aovs.normal, &DenoiseSettings::default());
+            report.add_phase("denoise", phase_start.elapsed());
         }
     }
+}
+
+// render in a window where the camera can be moved, every move starts the image over
+// the `Camera::new` call for the view is printed after each move, the image of the last view is the result
+#[cfg(feature = "preview")]
+fn explore(
+    options: &Options,
+    key: &CameraKeyframe,
+    aperture_shape: Option<&ApertureShape>,
+    aspect_ratio: f32,
+    world: &HittableList,
+    settings: &RenderSettings,
+    feedback: &mut Feedback
+) -> io::Result<RenderOutput> {
+    use ray_tracing_rust::navigation::OrbitView;
+    use ray_tracing_rust::preview::PreviewWindow;
+    use ray_tracing_rust::render::Accumulator;
+    use ray_tracing_rust::stats;
 
-    let material_1 = Rc::new(Dielectric {ir: 1.5});
-    world.add(Rc::new(Sphere {center: Point3::new(0.0, 1.0, 0.0), radius: 1.0, material: material_1}));
+    let mut window = PreviewWindow::new("preview", settings.image_width, settings.image_height, options.output_transform)
+        .map_err(io::Error::other)?;
+    let mut view = OrbitView {
+        look_from: key.look_from,
+        look_at: key.look_at,
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: options.lens.map_or(key.vfov, |lens| lens.vfov(aspect_ratio)),
+        aperture: options.lens.map_or(0.1, |lens| lens.aperture()),
+        focus_dist: key.focus_dist
+    };
+    if options.autofocus {
+        view.focus_dist = camera::autofocus(view.look_from, view.look_at, world).unwrap_or(view.focus_dist);
+    }
+    let make_camera = |view: &OrbitView| {
+        let mut camera = view.camera(aspect_ratio);
+        if let Some(shape) = aperture_shape {
+            camera.set_aperture_shape(shape.clone());
+        }
+        camera
+    };
 
-    
-    let material_2 = Rc::new(Lambertian {albedo: Color::new(0.4, 0.2, 0.1)});
-    world.add(Rc::new(Sphere {center: Point3::new(-4.0, 1.0, 0.0), radius: 1.0, material: material_2}));
+    let start = Instant::now();
+    stats::take();
+    let mut camera = make_camera(&view);
+    let mut accumulator = Accumulator::new(settings);
+    let mut unprinted = false;
+    // a time budget is for every view, moving the camera starts it over
+    let mut view_start = Instant::now();
+    while window.is_open() && !feedback.cancel.is_cancelled() {
+        let navigation = window.navigate(&mut view);
+        if navigation.autofocus {
+            view.focus_dist = camera::autofocus(view.look_from, view.look_at, world).unwrap_or(view.focus_dist);
+        }
+        if navigation.changed {
+            camera = make_camera(&view);
+            accumulator.reset();
+            view_start = Instant::now();
+            unprinted = true;
+        }
+        // not while dragging, that would print every step of the way
+        if navigation.print || (unprinted && navigation.settled) {
+            println!("{}", view.to_code(aspect_ratio));
+            unprinted = false;
+        }
+        // like `render_progressive`, the first pass is always done
+        let in_budget = accumulator.samples_per_pixel == 0 || settings.time_budget.is_none_or(|budget| view_start.elapsed() < budget);
+        if accumulator.samples_per_pixel < settings.samples_per_pixel && in_budget {
+            accumulator.add_samples(&camera, world, settings, SAMPLES_PER_PASS, &progress::Quiet, &feedback.cancel);
+            window.update(&accumulator);
+        } else {
+            std::thread::sleep(std::time::Duration::from_millis(15));
+        }
+    }
 
-    
-    let material_3 = Rc::new(Metal {albedo: Color::new(0.7, 0.6, 0.5), fuzz: 0.0});
-    world.add(Rc::new(Sphere {center: Point3::new(4.0, 1.0, 0.0), radius: 1.0, material: material_3}));
-    world
-}
\ No newline at end of file
+    let mut output = accumulator.into_output(stats::take(), start.elapsed());
+    finish_frame(options, &mut output, &mut feedback.report);
+    Ok(output)
+}
+
+#[cfg(not(feature = "preview"))]
+fn explore(
+    _options: &Options,
+    _key: &CameraKeyframe,
+    _aperture_shape: Option<&ApertureShape>,
+    _aspect_ratio: f32,
+    _world: &HittableList,
+    _settings: &RenderSettings,
+    _feedback: &mut Feedback
+) -> io::Result<RenderOutput> {
+    Err(no_preview_window())
+}
+
+// a region is rendered on its own, without `--crop` it's put back into a black image of the whole size
+fn crop(options: &Options, settings: &RenderSettings, output: RenderOutput) -> RenderOutput {
+    let (width, height) = (settings.image_width, settings.image_height);
+    match settings.region.filter(|_| !options.crop) {
+        Some(region) => RenderOutput {
+            beauty: output.beauty.pad(&region, width, height),
+            aovs: output.aovs.map(|aovs| aovs.pad(&region, width, height)),
+            ..output
+        },
+        None => output
+    }
+}
+
+// render the image on the workers and write it, the workers build the scene of the job themselves
+fn render_on_workers(
+    options: &Options,
+    workers: &[String],
+    key: &CameraKeyframe,
+    settings: &RenderSettings,
+    mut feedback: Feedback,
+    start_time: Instant
+) -> io::Result<()> {
+    let aspect_ratio = settings.image_width as f32 / settings.image_height as f32;
+    let job = RenderJob {
+        scene: options.scene,
+        // every worker has to build the same scene
+        seed: options.seed.unwrap_or_else(rand::random),
+        view: OrbitView {
+            look_from: key.look_from,
+            look_at: key.look_at,
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: options.lens.map_or(key.vfov, |lens| lens.vfov(aspect_ratio)),
+            aperture: options.lens.map_or(0.1, |lens| lens.aperture()),
+            focus_dist: key.focus_dist
+        },
+        settings: *settings
+    };
+    let (columns, rows) = options.tiles.unwrap_or(WORKER_TILES);
+
+    feedback.progress.start("render", (columns * rows) as u64);
+    let phase_start = Instant::now();
+    let image = render_distributed(&job, workers, columns, rows, feedback.progress.as_ref())?;
+    feedback.progress.finish();
+    feedback.report.add_phase("render", phase_start.elapsed());
+
+    let phase_start = Instant::now();
+    image_io::write_ppm("image.ppm", &image, &options.output_transform)?;
+    feedback.report.add_phase("encode", phase_start.elapsed());
+    write_report(options, feedback.report, start_time)
+}
+
+// read the tiles rendered with `--tiles <columns>x<rows> --tile <index>` and write the whole image
+fn stitch(options: &Options, columns: usize, rows: usize) -> io::Result<()> {
+    let tiles = (0..columns * rows).map(|tile| image_io::read_pfm(&format!("tile_{:04}.pfm", tile))).collect::<io::Result<Vec<_>>>()?;
+    let image = Framebuffer::stitch(&tiles, columns, rows).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    image_io::write_ppm("image.ppm", &image, &options.output_transform)
+}
+
+fn write_aovs(options: &Options, output: &RenderOutput, stem: &str) -> io::Result<()> {
+    if let Some(aovs) = output.aovs.as_ref() {
+        match options.aov {
+            Some(AovOutput::Exr) => aovs.write_exr(&format!("{}.exr", stem), &output.beauty)?,
+            Some(AovOutput::Files) => aovs.write_files(stem)?,
+            None => ()
+        }
+    }
+    Ok(())
+}
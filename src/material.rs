@@ -1,8 +1,23 @@
 use crate::{hittable::HitRecord, ray::Ray, vec3::{Color, Vec3}};
+use std::rc::Rc;
+
+use crate::rng;
+use crate::spectrum::{self, Dispersion, SampledSpectrum, SampledWavelengths};
+use crate::texture::SurfaceMap;
 
 pub trait Material {
     // return attenuation color and scattered ray
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+
+    // same as `scatter`, but the attenuation is given for each of the sampled wavelengths
+    // by default the rgb attenuation is uplifted to a spectrum
+    fn scatter_spectral(&self, r_in: &Ray, rec: &HitRecord, wavelengths: &mut SampledWavelengths) -> Option<(SampledSpectrum, Ray)> {
+        self.scatter(r_in, rec)
+            .map(|(attenuation, scattered)| (spectrum::uplift(&attenuation, wavelengths), scattered))
+    }
+
+    // the base color of the surface, used for the albedo pass
+    fn albedo(&self) -> Color;
 }
 
 pub struct Lambertian {
@@ -19,11 +34,15 @@ impl Material for Lambertian {
             scatter_direction = rec.normal;
         }
 
-        let scattered = Ray {origin: rec.p, direction: scatter_direction};
+        let scattered = Ray {origin: rec.spawn_origin(&scatter_direction), direction: scatter_direction};
         let attenuation = self.albedo;
 
         Some((attenuation, scattered))
     }
+
+    fn albedo(&self) -> Color {
+        self.albedo
+    }
 }
 
 pub struct Metal {
@@ -34,7 +53,8 @@ pub struct Metal {
 impl Material for Metal {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         let reflected = Vec3::reflect(&r_in.direction.unit_vector(), &rec.normal);
-        let scattered = Ray {origin: rec.p, direction: reflected + self.fuzz.clamp(0.0, 1.0) * Vec3::random_in_unit_sphere()};
+        let direction = reflected + self.fuzz.clamp(0.0, 1.0) * Vec3::random_in_unit_sphere();
+        let scattered = Ray {origin: rec.spawn_origin(&direction), direction};
         let attenuation = self.albedo;
 
         if scattered.direction.dot(rec.normal) > 0.0 {
@@ -43,18 +63,46 @@ impl Material for Metal {
             None
         }
     }
-}
 
+    fn albedo(&self) -> Color {
+        self.albedo
+    }
+}
 
 pub struct Dielectric {
     // index of refaction
-    pub ir: f32
+    pub ir: f32,
+    // wavelength dependent index of refraction, only used when rendering spectrally
+    pub dispersion: Option<Dispersion>
 }
 
 impl Material for Dielectric {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let attenuation = Color::black();
-        let refraction_ratio = if rec.front_face { 1.0 / self.ir } else { self.ir };
+        Some((Color::black(), self.scatter_with_ior(r_in, rec, self.ir)))
+    }
+
+    fn scatter_spectral(&self, r_in: &Ray, rec: &HitRecord, wavelengths: &mut SampledWavelengths) -> Option<(SampledSpectrum, Ray)> {
+        let ir = match self.dispersion {
+            Some(dispersion) => {
+                // every wavelength would bend differently, so only the hero wavelength goes on
+                wavelengths.terminate_secondary();
+                dispersion.ior(wavelengths.hero())
+            },
+            None => self.ir
+        };
+        Some((SampledSpectrum::new(1.0), self.scatter_with_ior(r_in, rec, ir)))
+    }
+
+    // glass doesn't absorb anything
+    fn albedo(&self) -> Color {
+        Color::black()
+    }
+}
+
+impl Dielectric {
+
+    fn scatter_with_ior(&self, r_in: &Ray, rec: &HitRecord, ir: f32) -> Ray {
+        let refraction_ratio = if rec.front_face { 1.0 / ir } else { ir };
         
         let unit_direction = r_in.direction.unit_vector();
 
@@ -62,19 +110,15 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction;
         
-        if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > rand::random::<f32>() {
-            direction = Vec3::reflect(&unit_direction, &rec.normal);
+        let direction = if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > rng::random::<f32>() {
+            Vec3::reflect(&unit_direction, &rec.normal)
         } else {
-            direction = Vec3::refract(&unit_direction, &rec.normal, refraction_ratio);
-        }
+            Vec3::refract(&unit_direction, &rec.normal, refraction_ratio)
+        };
 
-        Some((attenuation, Ray {origin: rec.p, direction: direction}))
+        Ray {origin: rec.spawn_origin(&direction), direction}
     }
-}
-
-impl Dielectric {
 
     // Use Schlick's approximation for reflectance.
     fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
@@ -82,4 +126,33 @@ impl Dielectric {
         let r0_square = r0 * r0;
         r0_square + (1.0 - r0_square) * (1.0 - cosine).powi(5)
     }
-}
\ No newline at end of file
+}
+
+// wraps another material and shades it with the normal from a normal or bump map
+pub struct SurfaceMapped {
+    pub material: Rc<dyn Material>,
+    pub map: SurfaceMap
+}
+
+impl SurfaceMapped {
+    // the hit as the wrapped material should see it
+    fn perturbed(&self, rec: &HitRecord) -> HitRecord {
+        let mut perturbed = rec.clone();
+        perturbed.normal = self.map.perturb(rec);
+        perturbed
+    }
+}
+
+impl Material for SurfaceMapped {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.material.scatter(r_in, &self.perturbed(rec))
+    }
+
+    fn scatter_spectral(&self, r_in: &Ray, rec: &HitRecord, wavelengths: &mut SampledWavelengths) -> Option<(SampledSpectrum, Ray)> {
+        self.material.scatter_spectral(r_in, &self.perturbed(rec), wavelengths)
+    }
+
+    fn albedo(&self) -> Color {
+        self.material.albedo()
+    }
+}
@@ -0,0 +1,153 @@
+use crate::camera::Camera;
+use crate::vec3::{Point3, Vec3};
+
+// a camera that can be orbited, panned and zoomed around the point it looks at, for the interactive preview
+// the fields are the arguments of `Camera::new`, `to_code` prints them ready to paste into a scene
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitView {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: Vec3,
+    pub vfov: f32,
+    pub aperture: f32,
+    pub focus_dist: f32
+}
+
+// how close to straight up or down the view can get, the camera basis breaks down at the pole
+const MAX_PITCH: f32 = 89.0;
+
+impl OrbitView {
+    pub fn camera(&self, aspect_ratio: f32) -> Camera {
+        Camera::new(self.look_from, self.look_at, self.vup, self.vfov, aspect_ratio, self.aperture, self.focus_dist)
+    }
+
+    // distance, yaw around vup and pitch above the plane orthogonal to vup, angles in degrees
+    fn spherical(&self) -> (f32, f32, f32) {
+        let (forward, side, up) = self.reference_axes();
+        let offset = self.look_from - self.look_at;
+        let distance = offset.length();
+        let yaw = offset.dot(side).atan2(offset.dot(forward)).to_degrees();
+        let pitch = (offset.dot(up) / distance).clamp(-1.0, 1.0).asin().to_degrees();
+        (distance, yaw, pitch)
+    }
+
+    // a fixed frame around vup, yaw is measured from `forward` towards `side`
+    fn reference_axes(&self) -> (Vec3, Vec3, Vec3) {
+        let up = self.vup.unit_vector();
+        let helper = if up.x.abs() > 0.9 { Vec3::new(0.0, 0.0, 1.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let side = up.cross(helper).unit_vector();
+        let forward = side.cross(up);
+        (forward, side, up)
+    }
+
+    fn set_spherical(&mut self, distance: f32, yaw: f32, pitch: f32) {
+        let (forward, side, up) = self.reference_axes();
+        let (yaw, pitch) = (yaw.to_radians(), pitch.clamp(-MAX_PITCH, MAX_PITCH).to_radians());
+        let direction = pitch.cos() * (yaw.cos() * forward + yaw.sin() * side) + pitch.sin() * up;
+        self.look_from = self.look_at + distance * direction;
+    }
+
+    // turn the camera around the point it looks at, in degrees
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let (distance, old_yaw, old_pitch) = self.spherical();
+        self.set_spherical(distance, old_yaw + yaw, old_pitch + pitch);
+    }
+
+    // move the camera and the point it looks at sideways, in fractions of the image height at the focus distance
+    pub fn pan(&mut self, right: f32, up: f32) {
+        let w = (self.look_from - self.look_at).unit_vector();
+        let u = self.vup.cross(w).unit_vector();
+        let v = w.cross(u);
+        let height = 2.0 * self.focus_dist * (self.vfov.to_radians() / 2.0).tan();
+        let offset = height * right * u + height * up * v;
+        self.look_from += offset;
+        self.look_at += offset;
+    }
+
+    // move towards the point it looks at (factor < 1) or away from it, the focus moves along
+    pub fn zoom(&mut self, factor: f32) {
+        let (distance, yaw, pitch) = self.spherical();
+        let new_distance = (distance * factor).max(1e-3);
+        self.focus_dist = (self.focus_dist + new_distance - distance).max(1e-3);
+        self.set_spherical(new_distance, yaw, pitch);
+    }
+
+    pub fn focus(&mut self, factor: f32) {
+        self.focus_dist = (self.focus_dist * factor).max(1e-3);
+    }
+
+    // the `Camera::new` call for this view
+    pub fn to_code(&self, aspect_ratio: f32) -> String {
+        let vector = |kind: &str, v: Vec3| format!("{}::new({}, {}, {})", kind, literal(v.x), literal(v.y), literal(v.z));
+        format!(
+            "Camera::new({}, {}, {}, {}, {}, {}, {})",
+            vector("Point3", self.look_from),
+            vector("Point3", self.look_at),
+            vector("Vec3", self.vup),
+            literal(self.vfov),
+            literal(aspect_ratio),
+            literal(self.aperture),
+            literal(self.focus_dist)
+        )
+    }
+}
+
+// a float literal rounded to 4 decimals, 1.0 instead of 1 so it stays an f32
+fn literal(x: f32) -> String {
+    let text = format!("{:.4}", x);
+    let text = text.trim_end_matches('0');
+    let text = if text.ends_with('.') { format!("{}0", text) } else { text.to_string() };
+    if text == "-0.0" { "0.0".to_string() } else { text }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::navigation::*;
+
+    fn view() -> OrbitView {
+        OrbitView {
+            look_from: Point3::new(13.0, 2.0, 3.0),
+            look_at: Point3::new(0.0, 0.0, 0.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 20.0,
+            aperture: 0.1,
+            focus_dist: 10.0
+        }
+    }
+
+    #[test]
+    fn test_navigation() {
+        let start = view();
+        let distance = (start.look_from - start.look_at).length();
+
+        // a full turn comes back to the start, orbiting keeps the distance
+        let mut orbited = start;
+        orbited.orbit(90.0, 10.0);
+        assert!(((orbited.look_from - orbited.look_at).length() - distance).abs() < 1e-4);
+        orbited.orbit(270.0, -10.0);
+        assert!((orbited.look_from - start.look_from).length() < 1e-3);
+
+        // can't go over the top
+        orbited.orbit(0.0, 120.0);
+        assert!((orbited.look_from - orbited.look_at).unit_vector().y < 1.0);
+
+        // panning keeps the view direction
+        let mut panned = start;
+        panned.pan(0.5, 0.25);
+        assert!(((panned.look_at - panned.look_from).unit_vector() - (start.look_at - start.look_from).unit_vector()).length() < 1e-5);
+        assert!(panned.look_at.y > 0.0);
+
+        let mut zoomed = start;
+        zoomed.zoom(0.5);
+        assert!(((zoomed.look_from - zoomed.look_at).length() - distance / 2.0).abs() < 1e-3);
+        assert!((zoomed.focus_dist - (10.0 - distance / 2.0)).abs() < 1e-3);
+
+        assert_eq!(
+            start.to_code(1.5),
+            "Camera::new(Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 20.0, 1.5, 0.1, 10.0)"
+        );
+        assert_eq!(literal(-0.00001), "0.0");
+        assert_eq!(literal(2.34567), "2.3457");
+    }
+}
@@ -0,0 +1,365 @@
+use std::time::Duration;
+
+use ray_tracing_rust::animation::Interpolation;
+use ray_tracing_rust::aov::AovOutput;
+use ray_tracing_rust::camera::PhysicalLens;
+use ray_tracing_rust::color_utils::{ColorSpace, OutputTransform, ToneMap};
+use ray_tracing_rust::filter::{Filter, FilterKind};
+use ray_tracing_rust::framebuffer::Region;
+use ray_tracing_rust::render;
+use ray_tracing_rust::scene::SceneKind;
+
+// which camera model to render with
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    Orthographic,
+    // 360 degree equirectangular panorama
+    Panorama,
+    Fisheye
+}
+
+impl Projection {
+    // a panorama covers 360 by 180 degrees, anything but 2:1 stretches it
+    pub fn aspect_ratio(&self) -> f32 {
+        match self {
+            Projection::Panorama => 2.0,
+            _ => 3.0 / 2.0
+        }
+    }
+}
+
+// how to show the progress of a render, on stderr
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ProgressMode {
+    // a bar with percent and time left
+    #[default]
+    Bar,
+    // json lines, for logs and dashboards
+    Json,
+    // nothing, except errors and warnings
+    Quiet
+}
+
+// command line options, everything is optional and falls back to the defaults
+#[derive(Default)]
+pub struct Options {
+    pub aov: Option<AovOutput>,
+    // run the denoiser on the image before writing it
+    pub denoise: bool,
+    pub output_transform: OutputTransform,
+    pub filter: Filter,
+    // trace wavelengths instead of rgb
+    pub spectral: bool,
+    pub projection: Projection,
+    // set when any of the physical camera options is given, the rest keep their defaults
+    pub lens: Option<PhysicalLens>,
+    // number of aperture blades, for polygonal bokeh
+    pub blades: Option<u32>,
+    // a pgm or ppm image of the aperture shape
+    pub aperture_image: Option<String>,
+    // focus on whatever is in the center of the image
+    pub autofocus: bool,
+    // a camera keyframe file, renders an image sequence instead of a single image
+    pub keyframes: Option<String>,
+    pub interpolation: Interpolation,
+    // orbit the camera around the scene in this many frames
+    pub turntable: Option<u32>,
+    // first and last frame to render, both inclusive
+    pub frames: Option<(i32, i32)>,
+    pub scene: SceneKind,
+    // a grayscale image for the landscape terrain, noise otherwise
+    pub height_map: Option<String>,
+    // also write the end of render statistics to this file, as json
+    pub stats_json: Option<String>,
+    pub progress: ProgressMode,
+    // show the image in a window while it renders, needs the `preview` feature
+    pub preview: bool,
+    // write the image so far to this png every few seconds while it renders
+    pub preview_file: Option<String>,
+    // makes the scene and the image repeatable, needed to render tiles on different machines
+    pub seed: Option<u64>,
+    // only render these pixels
+    pub region: Option<Region>,
+    // write only the region instead of the full image with the rest black
+    pub crop: bool,
+    // render one tile of a columns x rows grid, to be stitched together later
+    pub tiles: Option<(usize, usize)>,
+    pub tile: Option<usize>,
+    // put the tiles of a columns x rows grid back together instead of rendering
+    pub stitch: Option<(usize, usize)>,
+    // render on these worker processes (host:port) instead of here, `tiles` is the grid to split the image into
+    pub workers: Option<Vec<String>>,
+    // be a worker, listening on this address
+    pub worker: Option<String>,
+    // keep adding samples for this long instead of stopping at a fixed number
+    pub time_budget: Option<Duration>
+}
+
+impl Options {
+    // parse the arguments after the program name
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut filter_radius = None;
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--aov" => {
+                    options.aov = Some(match next_value(&mut args, &arg)?.as_str() {
+                        "files" => AovOutput::Files,
+                        "exr" => AovOutput::Exr,
+                        other => return Err(format!("unknown aov output '{}', expected 'files' or 'exr'", other))
+                    });
+                },
+                "--denoise" => options.denoise = true,
+                "--spectral" => options.spectral = true,
+                "--focal-length" => options.lens.get_or_insert_with(PhysicalLens::default).focal_length = next_number(&mut args, &arg)?,
+                "--f-stop" => options.lens.get_or_insert_with(PhysicalLens::default).f_stop = next_number(&mut args, &arg)?,
+                "--iso" => options.lens.get_or_insert_with(PhysicalLens::default).iso = next_number(&mut args, &arg)?,
+                "--shutter" => {
+                    // either seconds or a fraction like 1/125
+                    let value = next_value(&mut args, &arg)?;
+                    let shutter = match value.split_once('/') {
+                        Some((numerator, denominator)) => numerator.parse::<f32>().ok()
+                            .zip(denominator.parse::<f32>().ok())
+                            .map(|(n, d)| n / d),
+                        None => value.parse::<f32>().ok()
+                    };
+                    options.lens.get_or_insert_with(PhysicalLens::default).shutter = shutter
+                        .filter(|s| s.is_finite() && *s > 0.0)
+                        .ok_or_else(|| format!("invalid shutter '{}'", value))?;
+                },
+                "--sensor" => {
+                    // width x height in millimeters, e.g. 36x24
+                    let value = next_value(&mut args, &arg)?;
+                    let (width, height) = value.split_once('x')
+                        .and_then(|(w, h)| w.parse::<f32>().ok().zip(h.parse::<f32>().ok()))
+                        .filter(|(w, h)| *w > 0.0 && *h > 0.0)
+                        .ok_or_else(|| format!("invalid sensor size '{}', expected <width>x<height>", value))?;
+                    let lens = options.lens.get_or_insert_with(PhysicalLens::default);
+                    lens.sensor_width = width;
+                    lens.sensor_height = height;
+                },
+                "--blades" => {
+                    let value = next_value(&mut args, &arg)?;
+                    options.blades = Some(value.parse().ok().filter(|b| *b >= 3)
+                        .ok_or_else(|| format!("invalid number of blades '{}', need at least 3", value))?);
+                },
+                "--aperture-image" => options.aperture_image = Some(next_value(&mut args, &arg)?),
+                "--autofocus" => options.autofocus = true,
+                "--scene" => {
+                    let value = next_value(&mut args, &arg)?;
+                    options.scene = SceneKind::from_name(&value)
+                        .ok_or_else(|| format!("unknown scene '{}', expected 'random' or 'landscape'", value))?;
+                },
+                "--height-map" => {
+                    options.height_map = Some(next_value(&mut args, &arg)?);
+                    options.scene = SceneKind::Landscape;
+                },
+                "--progress" => {
+                    options.progress = match next_value(&mut args, &arg)?.as_str() {
+                        "bar" => ProgressMode::Bar,
+                        "json" => ProgressMode::Json,
+                        "quiet" => ProgressMode::Quiet,
+                        other => return Err(format!("unknown progress mode '{}', expected 'bar', 'json' or 'quiet'", other))
+                    };
+                },
+                "--quiet" | "-q" => options.progress = ProgressMode::Quiet,
+                "--preview" => options.preview = true,
+                "--preview-file" => options.preview_file = Some(next_value(&mut args, &arg)?),
+                "--seed" => {
+                    let value = next_value(&mut args, &arg)?;
+                    options.seed = Some(value.parse().map_err(|_| format!("invalid seed '{}'", value))?);
+                },
+                "--region" => {
+                    // x,y,width,height in pixels from the top left corner
+                    let value = next_value(&mut args, &arg)?;
+                    let numbers: Vec<usize> = value.split(',').map(|n| n.trim().parse::<usize>()).collect::<Result<_, _>>()
+                        .map_err(|_| format!("invalid region '{}', expected <x>,<y>,<width>,<height>", value))?;
+                    options.region = match numbers[..] {
+                        [x, y, width, height] if width > 0 && height > 0 => Some(Region { x, y, width, height }),
+                        _ => return Err(format!("invalid region '{}', expected <x>,<y>,<width>,<height>", value))
+                    };
+                },
+                "--crop" => options.crop = true,
+                "--time-budget" => {
+                    let budget = render::time_budget(next_number(&mut args, &arg)?).map_err(|e| format!("invalid '{}': {}", arg, e))?;
+                    options.time_budget = Some(budget);
+                },
+                "--tiles" => options.tiles = Some(next_grid(&mut args, &arg)?),
+                "--tile" => {
+                    let value = next_value(&mut args, &arg)?;
+                    options.tile = Some(value.parse().map_err(|_| format!("invalid tile '{}'", value))?);
+                },
+                "--stitch" => options.stitch = Some(next_grid(&mut args, &arg)?),
+                "--workers" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let workers: Vec<String> = value.split(',').map(|w| w.trim().to_string()).filter(|w| !w.is_empty()).collect();
+                    if workers.is_empty() {
+                        return Err(format!("invalid workers '{}', expected <host:port>,<host:port>...", value));
+                    }
+                    options.workers = Some(workers);
+                },
+                "--worker" => options.worker = Some(next_value(&mut args, &arg)?),
+                "--stats-json" => options.stats_json = Some(next_value(&mut args, &arg)?),
+                "--keyframes" => options.keyframes = Some(next_value(&mut args, &arg)?),
+                "--interpolation" => {
+                    options.interpolation = match next_value(&mut args, &arg)?.as_str() {
+                        "linear" => Interpolation::Linear,
+                        "smooth" => Interpolation::Smooth,
+                        other => return Err(format!("unknown interpolation '{}', expected 'linear' or 'smooth'", other))
+                    };
+                },
+                "--turntable" => {
+                    let value = next_value(&mut args, &arg)?;
+                    options.turntable = Some(value.parse().ok().filter(|n| *n > 0)
+                        .ok_or_else(|| format!("invalid number of frames '{}'", value))?);
+                },
+                "--frames" => {
+                    // a single frame or a range like 1-120
+                    let value = next_value(&mut args, &arg)?;
+                    let (start, end) = value.split_once('-').unwrap_or((&value, &value));
+                    options.frames = Some(start.parse::<i32>().ok().zip(end.parse::<i32>().ok())
+                        .filter(|(start, end)| start <= end)
+                        .ok_or_else(|| format!("invalid frame range '{}', expected <start>-<end>", value))?);
+                },
+                "--exposure" => {
+                    let value = next_value(&mut args, &arg)?;
+                    options.output_transform.exposure = value.parse::<f32>().ok()
+                        .filter(|v| v.is_finite())
+                        .ok_or_else(|| format!("invalid exposure '{}'", value))?;
+                },
+                "--tonemap" => {
+                    options.output_transform.tone_map = match next_value(&mut args, &arg)?.as_str() {
+                        "clamp" => ToneMap::Clamp,
+                        "reinhard" => ToneMap::Reinhard,
+                        "aces" => ToneMap::AcesFilmic,
+                        "agx" => ToneMap::Agx,
+                        other => return Err(format!("unknown tone map '{}', expected 'clamp', 'reinhard', 'aces' or 'agx'", other))
+                    };
+                },
+                "--color-space" => {
+                    options.output_transform.color_space = match next_value(&mut args, &arg)?.as_str() {
+                        "srgb" => ColorSpace::Srgb,
+                        "rec709" => ColorSpace::Rec709,
+                        "display-p3" => ColorSpace::DisplayP3,
+                        other => return Err(format!("unknown color space '{}', expected 'srgb', 'rec709' or 'display-p3'", other))
+                    };
+                },
+                "--camera" => {
+                    options.projection = match next_value(&mut args, &arg)?.as_str() {
+                        "perspective" => Projection::Perspective,
+                        "orthographic" => Projection::Orthographic,
+                        "panorama" => Projection::Panorama,
+                        "fisheye" => Projection::Fisheye,
+                        other => return Err(format!("unknown camera '{}', expected 'perspective', 'orthographic', 'panorama' or 'fisheye'", other))
+                    };
+                },
+                "--filter" => {
+                    let value = next_value(&mut args, &arg)?;
+                    options.filter = Filter::new(FilterKind::from_name(&value)
+                        .ok_or_else(|| format!("unknown filter '{}', expected 'box', 'tent', 'gaussian', 'mitchell' or 'lanczos'", value))?);
+                },
+                "--filter-radius" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let radius: f32 = value.parse().map_err(|_| format!("invalid filter radius '{}'", value))?;
+                    if radius <= 0.0 {
+                        return Err(format!("filter radius must be positive, got {}", radius));
+                    }
+                    filter_radius = Some(radius);
+                },
+                _ => return Err(format!("unknown option '{}'", arg))
+            }
+        }
+        // the radius can be given before or after the filter
+        if let Some(radius) = filter_radius {
+            options.filter.radius = radius;
+        }
+        match (options.tiles, options.tile) {
+            (Some(_), None) if options.workers.is_none() => return Err("--tiles needs --tile <index>".to_string()),
+            (None, Some(_)) => return Err("--tile needs --tiles <columns>x<rows>".to_string()),
+            (Some((columns, rows)), Some(tile)) => {
+                if columns.checked_mul(rows).is_none_or(|count| tile >= count) {
+                    return Err(format!("tile {} is outside the {}x{} grid", tile, columns, rows));
+                }
+                if options.seed.is_none() {
+                    return Err("--tile needs --seed, tiles only fit together when every machine renders the same scene and samples".to_string());
+                }
+                if options.region.is_some() {
+                    return Err("--tile and --region can't be combined".to_string());
+                }
+                // the denoiser would see black past the tile edges and leave seams
+                if options.denoise {
+                    return Err("--tile and --denoise can't be combined, the tiles wouldn't fit together".to_string());
+                }
+                if options.time_budget.is_some() {
+                    return Err("--tile and --time-budget can't be combined, the tiles would get different samples".to_string());
+                }
+            },
+            _ => ()
+        }
+        if options.frames.is_some() && options.keyframes.is_none() && options.turntable.is_none() {
+            return Err("--frames needs --keyframes or --turntable".to_string());
+        }
+        // only the perspective camera has a lens, the others would silently ignore these
+        // (or for the orthographic camera, autofocus would change the size of the view)
+        if options.projection != Projection::Perspective {
+            let lens_only = [
+                ("--blades", options.blades.is_some()),
+                ("--aperture-image", options.aperture_image.is_some()),
+                ("--autofocus", options.autofocus),
+                ("--focal-length, --f-stop, --shutter, --iso or --sensor", options.lens.is_some())
+            ];
+            if let Some((name, _)) = lens_only.iter().find(|(_, given)| *given) {
+                return Err(format!("{} needs --camera perspective", name));
+            }
+        }
+        // the workers only get what's in a `RenderJob`
+        if options.workers.is_some() {
+            let unsupported = [
+                ("--aov", options.aov.is_some()),
+                ("--denoise", options.denoise),
+                ("--camera", options.projection != Projection::Perspective),
+                ("--blades", options.blades.is_some()),
+                ("--aperture-image", options.aperture_image.is_some()),
+                ("--autofocus", options.autofocus),
+                ("--keyframes", options.keyframes.is_some()),
+                ("--turntable", options.turntable.is_some()),
+                ("--frames", options.frames.is_some()),
+                ("--height-map", options.height_map.is_some()),
+                ("--preview", options.preview),
+                ("--preview-file", options.preview_file.is_some()),
+                ("--region", options.region.is_some()),
+                ("--tile", options.tile.is_some()),
+                ("--time-budget", options.time_budget.is_some())
+            ];
+            if let Some((name, _)) = unsupported.iter().find(|(_, given)| *given) {
+                return Err(format!("{} can't be combined with --workers", name));
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn next_number(args: &mut impl Iterator<Item = String>, name: &str) -> Result<f32, String> {
+    let value = next_value(args, name)?;
+    value.parse::<f32>().ok()
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .ok_or_else(|| format!("invalid value '{}' for '{}', expected a positive number", value, name))
+}
+
+// columns x rows, e.g. 4x2, the tile count has to fit in a usize so the tiles can be numbered
+fn next_grid(args: &mut impl Iterator<Item = String>, name: &str) -> Result<(usize, usize), String> {
+    let value = next_value(args, name)?;
+    let (columns, rows) = value.split_once('x')
+        .and_then(|(c, r)| c.parse::<usize>().ok().zip(r.parse::<usize>().ok()))
+        .filter(|(c, r)| *c > 0 && *r > 0)
+        .ok_or_else(|| format!("invalid grid '{}', expected <columns>x<rows>", value))?;
+    columns.checked_mul(rows).ok_or_else(|| format!("the grid '{}' has too many tiles", value))?;
+    Ok((columns, rows))
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, name: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("missing value for '{}'", name))
+}
@@ -0,0 +1,160 @@
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::simd::{F32x8, Vec3x8, LANES};
+use crate::sphere::Sphere;
+use crate::stats;
+use crate::vec3::Vec3;
+
+// many spheres tested together, 8 at a time, instead of one `Sphere::hit` call after the other
+// the centers and radii are kept as structure of arrays; only the closest sphere builds a `HitRecord`
+// works best for lots of small spheres like in `random_scene`
+pub struct SphereSet {
+    // fixed once the set is built, `centers` and `radii_squared` are copies
+    spheres: Vec<Sphere>,
+    centers: Vec<Vec3x8>,
+    radii_squared: Vec<F32x8>
+}
+
+impl SphereSet {
+    pub fn new(spheres: Vec<Sphere>) -> SphereSet {
+        let mut centers = Vec::new();
+        let mut radii_squared = Vec::new();
+        for chunk in spheres.chunks(LANES) {
+            let chunk_centers: Vec<Vec3> = chunk.iter().map(|sphere| sphere.center).collect();
+            centers.push(Vec3x8::gather(&chunk_centers, Vec3::new_empty()));
+            // an infinite negative radius makes the discriminant negative, so the unused lanes never hit
+            radii_squared.push(F32x8(std::array::from_fn(|i| {
+                chunk.get(i).map_or(f32::NEG_INFINITY, |sphere| sphere.radius * sphere.radius)
+            })));
+        }
+        SphereSet { spheres, centers, radii_squared }
+    }
+}
+
+// the nearest root of a * t^2 + 2 * half_b * t + c = 0 in [t_min, t_max] for each lane,
+// the same as in `Sphere::hit`, or infinity where there is none
+fn nearest_root(a: F32x8, half_b: F32x8, c: F32x8, t_min: f32, t_max: F32x8) -> F32x8 {
+    let discriminant = half_b * half_b - a * c;
+    let discriminant_sqrt = discriminant.max(F32x8::splat(0.0)).sqrt();
+    let near = (-half_b - discriminant_sqrt) / a;
+    let far = (-half_b + discriminant_sqrt) / a;
+    F32x8(std::array::from_fn(|i| {
+        let in_range = |t: f32| t >= t_min && t <= t_max.0[i];
+        if discriminant.0[i] < 0.0 {
+            f32::INFINITY
+        } else if in_range(near.0[i]) {
+            near.0[i]
+        } else if in_range(far.0[i]) {
+            far.0[i]
+        } else {
+            f32::INFINITY
+        }
+    }))
+}
+
+impl Hittable for SphereSet {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let origin = Vec3x8::splat(r.origin);
+        let direction = Vec3x8::splat(r.direction);
+        let a = F32x8::splat(r.direction.dot(r.direction));
+        stats::count(|c| c.intersection_tests += self.spheres.len() as u64);
+
+        let mut closest = t_max;
+        let mut best = None;
+        for (chunk, (centers, radii_squared)) in self.centers.iter().zip(self.radii_squared.iter()).enumerate() {
+            let a_sub_c = origin - *centers;
+            let half_b = direction.dot(a_sub_c);
+            let c = a_sub_c.dot(a_sub_c) - *radii_squared;
+            let t = nearest_root(a, half_b, c, t_min, F32x8::splat(closest));
+            for (lane, t) in t.0.iter().enumerate() {
+                if *t < closest {
+                    closest = *t;
+                    best = Some(chunk * LANES + lane);
+                }
+            }
+        }
+
+        // the full record with normal, uv and material only for the closest sphere
+        let best = best?;
+        let mut rec = self.spheres[best].hit(r, t_min, t_max)?;
+        rec.object_id = best;
+        Some(rec)
+    }
+
+    // every sphere has its own object id
+    fn object_count(&self) -> usize {
+        self.spheres.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::rc::Rc;
+
+    use crate::hittable_list::HittableList;
+    use crate::material::Lambertian;
+    use crate::packet::*;
+    use crate::vec3::{Color, Point3};
+
+    fn spheres() -> Vec<Sphere> {
+        let material = Rc::new(Lambertian { albedo: Color::new(0.5, 0.5, 0.5) });
+        // 3 rows of 5, so the last chunk is only partly filled
+        (0..15).map(|i| Sphere {
+            center: Point3::new((i % 5) as f32 - 2.0, (i / 5) as f32 - 1.0, -5.0 - (i % 3) as f32),
+            radius: 0.3 + 0.05 * (i % 4) as f32,
+            material: material.clone()
+        }).collect()
+    }
+
+    fn rays() -> Vec<Ray> {
+        (0..8).map(|i| Ray {
+            origin: Point3::new(0.0, 0.0, 0.0),
+            direction: Vec3::new(-0.4 + 0.11 * i as f32, -0.2 + 0.06 * i as f32, -1.0)
+        }).collect()
+    }
+
+    #[test]
+    fn test_matches_list() {
+        let mut list = HittableList::new();
+        for sphere in spheres() {
+            list.add(Rc::new(sphere));
+        }
+        let set = SphereSet::new(spheres());
+
+        let mut hits = 0;
+        for r in rays() {
+            let expected = list.hit(&r, 0.001, f32::MAX);
+            let actual = set.hit(&r, 0.001, f32::MAX);
+            assert_eq!(expected.is_some(), actual.is_some());
+            if let (Some(expected), Some(actual)) = (expected, actual) {
+                assert_eq!(expected.t, actual.t);
+                assert_eq!(expected.normal, actual.normal);
+                assert_eq!(expected.object_id, actual.object_id);
+                hits += 1;
+            }
+        }
+        assert!(hits > 2, "only {} of the rays hit a sphere", hits);
+    }
+
+    #[test]
+    fn test_object_ids() {
+        // the spheres of a set in a list keep their own ids, the objects after the set count on from there
+        let behind = Sphere { center: Point3::new(0.0, 0.0, 10.0), ..spheres().remove(0) };
+        let mut world = HittableList::new();
+        world.add(Rc::new(behind.clone()));
+        world.add(Rc::new(SphereSet::new(spheres())));
+        world.add(Rc::new(behind));
+        assert_eq!(world.object_count(), 17);
+
+        let set = SphereSet::new(spheres());
+        let mut ids = Vec::new();
+        for r in rays() {
+            let id = world.hit(&r, 0.001, f32::MAX).map(|rec| rec.object_id);
+            assert_eq!(id, set.hit(&r, 0.001, f32::MAX).map(|rec| rec.object_id + 1));
+            ids.extend(id);
+        }
+        ids.dedup();
+        assert!(ids.len() > 2, "{:?}", ids);
+    }
+}
@@ -0,0 +1,215 @@
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::color_utils::OutputTransform;
+use crate::image_io;
+use crate::render::Accumulator;
+#[cfg(feature = "preview")]
+use crate::navigation::OrbitView;
+
+// shows the image while it renders, `render::render_progressive` calls `update` after every pass
+pub trait Preview {
+    // false when the render should stop early (e.g. the window was closed)
+    fn update(&mut self, accumulator: &Accumulator) -> bool;
+
+    // false once the user closed it, there's no point rendering more frames then
+    fn is_open(&self) -> bool {
+        true
+    }
+}
+
+// headless preview: writes the image so far to a png every `interval` and once all samples are in
+// the file is written next to the target and renamed over it, so a viewer never sees half an image
+pub struct PreviewFile {
+    pub path: String,
+    pub transform: OutputTransform,
+    pub interval: Duration,
+    // the samples per pixel of the finished render
+    pub final_samples: i32,
+    last_write: Option<Instant>
+}
+
+impl PreviewFile {
+    pub fn new(path: &str, transform: OutputTransform, final_samples: i32) -> PreviewFile {
+        PreviewFile { path: path.to_string(), transform, interval: Duration::from_secs(2), final_samples, last_write: None }
+    }
+
+    fn write(&self, accumulator: &Accumulator) -> io::Result<()> {
+        let temporary = format!("{}.partial", self.path);
+        image_io::write_png(&temporary, &accumulator.image(), &self.transform)?;
+        fs::rename(&temporary, &self.path)
+    }
+}
+
+impl Preview for PreviewFile {
+    fn update(&mut self, accumulator: &Accumulator) -> bool {
+        let due = self.last_write.is_none_or(|last| last.elapsed() >= self.interval);
+        if due || accumulator.samples_per_pixel >= self.final_samples {
+            // a preview that can't be written isn't worth stopping the render for
+            if let Err(e) = self.write(accumulator) {
+                eprintln!("preview: can't write {}: {}", self.path, e);
+            }
+            self.last_write = Some(Instant::now());
+        }
+        true
+    }
+}
+
+// a window showing the image, refreshed after every pass, closing it (or escape) stops the render
+#[cfg(feature = "preview")]
+pub struct PreviewWindow {
+    window: minifb::Window,
+    // 0RGB pixels
+    buffer: Vec<u32>,
+    transform: OutputTransform,
+    // where the mouse was at the last `navigate`, for dragging
+    last_mouse: Option<(f32, f32)>
+}
+
+// what the input did to the view since the last `navigate`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Navigation {
+    pub changed: bool,
+    // no mouse button is held down, a drag is over
+    pub settled: bool,
+    // focus on whatever is in the center of the image
+    pub autofocus: bool,
+    // print the camera now
+    pub print: bool
+}
+
+#[cfg(feature = "preview")]
+impl PreviewWindow {
+    pub fn new(title: &str, width: usize, height: usize, transform: OutputTransform) -> Result<PreviewWindow, String> {
+        let window = minifb::Window::new(title, width, height, minifb::WindowOptions::default())
+            .map_err(|e| format!("can't open the preview window: {}", e))?;
+        Ok(PreviewWindow { window, buffer: vec![0; width * height], transform, last_mouse: None })
+    }
+
+    // apply the mouse and keyboard input to the view:
+    // drag to orbit, drag with the right button (or shift) to pan, scroll to zoom,
+    // arrow keys orbit, page up/down zoom, [ and ] move the focus, f autofocuses and p prints the camera
+    pub fn navigate(&mut self, view: &mut OrbitView) -> Navigation {
+        use minifb::{Key, KeyRepeat, MouseButton, MouseMode};
+
+        // without a new image the window still needs to look for events
+        self.window.update();
+        let mut navigation = Navigation::default();
+        let height = self.window.get_size().1.max(1) as f32;
+
+        let left = self.window.get_mouse_down(MouseButton::Left);
+        let right = self.window.get_mouse_down(MouseButton::Right) || self.window.get_mouse_down(MouseButton::Middle);
+        let shift = self.window.is_key_down(Key::LeftShift) || self.window.is_key_down(Key::RightShift);
+        let mouse = self.window.get_mouse_pos(MouseMode::Pass);
+        if let (Some((x, y)), Some((last_x, last_y))) = (mouse, self.last_mouse) {
+            let (dx, dy) = (x - last_x, y - last_y);
+            if (dx != 0.0 || dy != 0.0) && (left || right) {
+                if right || shift {
+                    view.pan(-dx / height, dy / height);
+                } else {
+                    view.orbit(-0.3 * dx, 0.3 * dy);
+                }
+                navigation.changed = true;
+            }
+        }
+        self.last_mouse = mouse;
+        navigation.settled = !left && !right;
+
+        if let Some((_, scroll)) = self.window.get_scroll_wheel() {
+            if scroll != 0.0 {
+                view.zoom(0.9_f32.powf(scroll.signum()));
+                navigation.changed = true;
+            }
+        }
+
+        for key in self.window.get_keys_pressed(KeyRepeat::Yes) {
+            match key {
+                Key::Left => view.orbit(5.0, 0.0),
+                Key::Right => view.orbit(-5.0, 0.0),
+                Key::Up => view.orbit(0.0, 5.0),
+                Key::Down => view.orbit(0.0, -5.0),
+                Key::PageUp => view.zoom(0.9),
+                Key::PageDown => view.zoom(1.0 / 0.9),
+                Key::LeftBracket => view.focus(0.95),
+                Key::RightBracket => view.focus(1.0 / 0.95),
+                Key::F => navigation.autofocus = true,
+                Key::P => navigation.print = true,
+                _ => continue
+            }
+            navigation.changed |= key != Key::P;
+        }
+        navigation
+    }
+}
+
+#[cfg(feature = "preview")]
+impl Preview for PreviewWindow {
+    fn update(&mut self, accumulator: &Accumulator) -> bool {
+        let image = accumulator.image();
+        for (pixel, color) in self.buffer.iter_mut().zip(image.pixels.iter()) {
+            let [r, g, b] = crate::color_utils::to_rgb8(color, &self.transform);
+            *pixel = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+        }
+        self.window.set_title(&format!("preview, {} samples per pixel", accumulator.samples_per_pixel));
+        if self.window.update_with_buffer(&self.buffer, image.width, image.height).is_err() {
+            return false;
+        }
+        self.is_open()
+    }
+
+    fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(minifb::Key::Escape)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::preview::*;
+    use crate::camera::Camera;
+    use crate::cancel::CancellationToken;
+    use crate::hittable_list::HittableList;
+    use crate::progress::Quiet;
+    use crate::render::{self, RenderSettings};
+    use crate::vec3::{Point3, Vec3};
+
+    #[test]
+    fn test_progressive() {
+        let settings = RenderSettings {
+            image_width: 8,
+            image_height: 6,
+            samples_per_pixel: 5,
+            max_depth: 4,
+            collect_aovs: true,
+            ..Default::default()
+        };
+        let camera = Camera::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 90.0, 4.0 / 3.0, 0.0, 1.0);
+        let world = HittableList::new();
+        let path = std::env::temp_dir().join(format!("preview_test_{}.png", std::process::id()));
+        let mut preview = PreviewFile::new(path.to_str().unwrap(), OutputTransform::default(), settings.samples_per_pixel);
+        preview.interval = Duration::from_secs(3600);
+
+        // passes of 2, 2 and 1 samples, the first and the last one are written
+        let mut passes = Vec::new();
+        let output = render::render_progressive(&camera, &world, &settings, 2, &Quiet, &CancellationToken::new(), |accumulator| {
+            passes.push(accumulator.samples_per_pixel);
+            let written = fs::metadata(&path).is_ok();
+            let keep_going = preview.update(accumulator);
+            assert_eq!(fs::metadata(&path).is_ok(), written || passes.len() == 1);
+            keep_going
+        });
+        assert_eq!(passes, vec![2, 4, 5]);
+        assert_eq!(render::pass_count(&settings, 2), 3);
+        assert!(fs::read(&path).unwrap().starts_with(b"\x89PNG"));
+        fs::remove_file(&path).unwrap();
+
+        // with nothing in the scene every pixel is the sky
+        assert!(output.beauty.pixels.iter().all(|pixel| pixel.z > 0.9));
+        assert!(output.aovs.unwrap().depth.pixels.iter().all(|pixel| pixel.x == f32::INFINITY));
+
+        // stopping after the first pass
+        let output = render::render_progressive(&camera, &world, &settings, 2, &Quiet, &CancellationToken::new(), |_| false);
+        assert_eq!(output.counters.camera_rays, 2 * 8 * 6);
+    }
+}
@@ -0,0 +1,243 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// how far along a long running task is (rendering the scanlines of an image)
+// `advance` takes `&self` and the reporters only use atomics and locks inside, so render threads can share one
+pub trait Progress: Sync {
+    // a new task with `total` units of work, e.g. "frame 3" with one unit per scanline
+    fn start(&self, task: &str, total: u64);
+    // `units` more are done
+    fn advance(&self, units: u64);
+    fn finish(&self);
+}
+
+// prints nothing
+pub struct Quiet;
+
+impl Progress for Quiet {
+    fn start(&self, _task: &str, _total: u64) {}
+    fn advance(&self, _units: u64) {}
+    fn finish(&self) {}
+}
+
+// the state shared by the reporters that print something
+struct Tracker {
+    task: Mutex<String>,
+    total: AtomicU64,
+    done: AtomicU64,
+    started: Mutex<Instant>,
+    // to print at most every `interval`, redrawing on every scanline would slow the render down
+    last_print: Mutex<Option<Instant>>,
+    interval: Duration
+}
+
+// a snapshot of the tracker
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    pub task: String,
+    pub done: u64,
+    pub total: u64,
+    pub elapsed: Duration
+}
+
+impl Status {
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 { 1.0 } else { (self.done as f64 / self.total as f64).min(1.0) }
+    }
+
+    // assuming the rest goes as fast as what's done so far, `None` before anything is done
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.fraction();
+        if fraction <= 0.0 {
+            return None;
+        }
+        Some(self.elapsed.mul_f64((1.0 - fraction) / fraction))
+    }
+}
+
+impl Tracker {
+    fn new(interval: Duration) -> Tracker {
+        Tracker {
+            task: Mutex::new(String::new()),
+            total: AtomicU64::new(0),
+            done: AtomicU64::new(0),
+            started: Mutex::new(Instant::now()),
+            last_print: Mutex::new(None),
+            interval
+        }
+    }
+
+    fn start(&self, task: &str, total: u64) {
+        *self.task.lock().unwrap() = task.to_string();
+        self.total.store(total, Ordering::Relaxed);
+        self.done.store(0, Ordering::Relaxed);
+        *self.started.lock().unwrap() = Instant::now();
+        *self.last_print.lock().unwrap() = None;
+    }
+
+    // the status after adding `units`, if it's time to print it
+    fn advance(&self, units: u64) -> Option<Status> {
+        let done = self.done.fetch_add(units, Ordering::Relaxed) + units;
+        let finished = done >= self.total.load(Ordering::Relaxed);
+        let mut last_print = self.last_print.lock().unwrap();
+        let now = Instant::now();
+        if !finished && last_print.is_some_and(|last| now - last < self.interval) {
+            return None;
+        }
+        *last_print = Some(now);
+        Some(self.status())
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            task: self.task.lock().unwrap().clone(),
+            done: self.done.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+            elapsed: self.started.lock().unwrap().elapsed()
+        }
+    }
+}
+
+// a bar with percent and time left on one terminal line of stderr, redrawn in place
+pub struct Bar {
+    tracker: Tracker,
+    width: usize
+}
+
+impl Bar {
+    pub fn new() -> Bar {
+        Bar { tracker: Tracker::new(Duration::from_millis(100)), width: 30 }
+    }
+
+    pub fn format(&self, status: &Status) -> String {
+        let filled = (status.fraction() * self.width as f64).round() as usize;
+        let eta = status.eta().map_or("--:--".to_string(), format_duration);
+        format!("{} [{}{}] {:5.1}% ETA {}", status.task, "#".repeat(filled), "-".repeat(self.width - filled), status.fraction() * 100.0, eta)
+    }
+}
+
+impl Default for Bar {
+    fn default() -> Self {
+        Bar::new()
+    }
+}
+
+impl Progress for Bar {
+    fn start(&self, task: &str, total: u64) {
+        // drawn on the first `advance`, so messages printed before the work starts get their own line
+        self.tracker.start(task, total);
+    }
+
+    fn advance(&self, units: u64) {
+        if let Some(status) = self.tracker.advance(units) {
+            // the trailing spaces clear what's left of a longer line
+            eprint!("\r{}  ", self.format(&status));
+            let _ = io::stderr().flush();
+        }
+    }
+
+    fn finish(&self) {
+        let status = self.tracker.status();
+        eprintln!("\r{} done in {}{}", status.task, format_duration(status.elapsed), " ".repeat(self.width + 10));
+    }
+}
+
+// one json object per line on stderr, for log collectors and dashboards
+// {"event": "progress", "task": "frame 1", "done": 200, "total": 800, "percent": 25, "elapsed": 1.5, "eta": 4.5}
+pub struct JsonLines {
+    tracker: Tracker
+}
+
+impl JsonLines {
+    pub fn new() -> JsonLines {
+        JsonLines { tracker: Tracker::new(Duration::from_secs(1)) }
+    }
+
+    pub fn format(event: &str, status: &Status) -> String {
+        let eta = status.eta().map_or("null".to_string(), |eta| eta.as_secs_f64().to_string());
+        format!(
+            "{{\"event\": \"{}\", \"task\": \"{}\", \"done\": {}, \"total\": {}, \"percent\": {}, \"elapsed\": {}, \"eta\": {}}}",
+            event,
+            status.task.replace('\\', "\\\\").replace('"', "\\\""),
+            status.done,
+            status.total,
+            status.fraction() * 100.0,
+            status.elapsed.as_secs_f64(),
+            eta
+        )
+    }
+}
+
+impl Default for JsonLines {
+    fn default() -> Self {
+        JsonLines::new()
+    }
+}
+
+impl Progress for JsonLines {
+    fn start(&self, task: &str, total: u64) {
+        self.tracker.start(task, total);
+        eprintln!("{}", JsonLines::format("start", &self.tracker.status()));
+    }
+
+    fn advance(&self, units: u64) {
+        if let Some(status) = self.tracker.advance(units) {
+            eprintln!("{}", JsonLines::format("progress", &status));
+        }
+    }
+
+    fn finish(&self) {
+        eprintln!("{}", JsonLines::format("finish", &self.tracker.status()));
+    }
+}
+
+// h:mm:ss, or m:ss below an hour
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::progress::*;
+
+    #[test]
+    fn test_status() {
+        let status = Status { task: "frame \"1\"".to_string(), done: 25, total: 100, elapsed: Duration::from_secs(10) };
+        assert_eq!(status.eta(), Some(Duration::from_secs(30)));
+        assert_eq!(Status { done: 0, ..status.clone() }.eta(), None);
+        assert_eq!(format_duration(Duration::from_secs(3725)), "1:02:05");
+        assert_eq!(format_duration(Duration::from_secs(65)), "1:05");
+
+        assert_eq!(
+            JsonLines::format("progress", &status),
+            "{\"event\": \"progress\", \"task\": \"frame \\\"1\\\"\", \"done\": 25, \"total\": 100, \"percent\": 25, \"elapsed\": 10, \"eta\": 30}"
+        );
+        let bar = Bar { tracker: Tracker::new(Duration::ZERO), width: 4 };
+        assert_eq!(bar.format(&status), "frame \"1\" [#---]  25.0% ETA 0:30");
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let tracker = Tracker::new(Duration::from_secs(60));
+        tracker.start("render", 400);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        tracker.advance(1);
+                    }
+                });
+            }
+        });
+        assert_eq!(tracker.status().done, 400);
+    }
+}
@@ -0,0 +1,392 @@
+use std::time::{Duration, Instant};
+
+use crate::aov::{self, AovBuffers};
+use crate::camera::CameraModel;
+use crate::cancel::CancellationToken;
+use crate::filter::Filter;
+use crate::framebuffer::{Framebuffer, Region};
+use crate::hittable::{HitRecord, Hittable};
+use crate::progress::{Progress, Quiet};
+use crate::ray::Ray;
+use crate::rng;
+use crate::spectrum::{self, SampledSpectrum, SampledWavelengths};
+use crate::stats::{self, Counters};
+use crate::vec3::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderSettings {
+    pub image_width: usize,
+    pub image_height: usize,
+    pub samples_per_pixel: i32,
+    pub max_depth: i32,
+    // how the samples are weighted into the pixels around them
+    pub filter: Filter,
+    // trace sampled wavelengths instead of rgb, needed for dispersion
+    pub spectral: bool,
+    // whether to also fill the auxiliary buffers (albedo, normal, depth...)
+    pub collect_aovs: bool,
+    // only render these pixels, the rest of the image stays black
+    pub region: Option<Region>,
+    // with a seed every pixel gets its own random numbers from the seed and its position, so a pixel comes out
+    // the same whether it's rendered with the whole image or in a region (tiles stitch together seamlessly)
+    pub seed: Option<u64>,
+    // with `render_progressive`, keep adding passes for this long, `samples_per_pixel` is the most it takes then
+    pub time_budget: Option<Duration>
+}
+
+// the 1200x800 image, 100 samples and 50 bounces of the book, everything extra is off
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            image_width: 1200,
+            image_height: 800,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            filter: Filter::default(),
+            spectral: false,
+            collect_aovs: false,
+            region: None,
+            seed: None,
+            time_budget: None
+        }
+    }
+}
+
+pub struct RenderOutput {
+    // the averaged color of each pixel, before gamma correction
+    // only the pixels of the region when there is one, `Framebuffer::pad` puts them back into the whole image
+    pub beauty: Framebuffer,
+    pub aovs: Option<AovBuffers>,
+    // what the render did and how long it took
+    pub counters: Counters,
+    pub duration: Duration,
+    // every pixel got at least this many samples, less than asked for when the render stopped early
+    pub samples_per_pixel: i32
+}
+
+pub fn ray_color(r: &Ray, hittable: &impl Hittable, depth: i32) -> Color {
+
+    // if we've exceeded the ray bouncing limit, we will not gather more light
+    if depth <= 0 {
+        return Color::black();
+    }
+
+    // some of the reflected rays hit the object they are reflecting off of not at exactly t = 0,
+    // but something extremely close to 0 (shadow acne problem)
+    stats::count(|c| c.rays += 1);
+    match hittable.hit(r, 0.001, f32::MAX) {
+        Some(rec) => hit_color(r, &rec, hittable, depth),
+        None => background(r)
+    }
+}
+
+// the rest of `ray_color` once `r` is known to hit `rec`, for callers that already traced it
+pub fn hit_color(r: &Ray, rec: &HitRecord, hittable: &impl Hittable, depth: i32) -> Color {
+    match rec.material.scatter(r, rec) {
+        Some((attenuation, scattered)) => attenuation * ray_color(&scattered, hittable, depth - 1),
+        None => Color::white()
+    }
+}
+
+// spectral version of `ray_color`, the wavelengths can be narrowed down to the hero wavelength on the way
+pub fn ray_spectrum(r: &Ray, hittable: &impl Hittable, depth: i32, wavelengths: &mut SampledWavelengths) -> SampledSpectrum {
+    if depth <= 0 {
+        return spectrum::uplift(&Color::black(), wavelengths);
+    }
+
+    stats::count(|c| c.rays += 1);
+    if let Some(rec) = hittable.hit(r, 0.001, f32::MAX) {
+        match rec.material.scatter_spectral(r, &rec, wavelengths) {
+            Some((attenuation, scattered)) => {
+                return attenuation * ray_spectrum(&scattered, hittable, depth - 1, wavelengths);
+            },
+            None => return SampledSpectrum::new(0.0)
+        }
+    }
+    spectrum::uplift(&background(r), wavelengths)
+}
+
+// the sky gradient a ray sees when it escapes the scene
+pub fn background(r: &Ray) -> Color {
+    let unit_direction = r.direction.unit_vector();
+    let t = 0.5 * (unit_direction.y + 1.0);
+    (1.0 - t) * Color::black() + t * Color {x: 0.5, y: 0.7, z: 1.0}
+}
+
+pub fn render(camera: &dyn CameraModel, world: &impl Hittable, settings: &RenderSettings) -> RenderOutput {
+    render_with_progress(camera, world, settings, &Quiet, &CancellationToken::new())
+}
+
+// `render` that reports each finished scanline to `progress`, `settings.image_height` units in total
+// starting and finishing the task is up to the caller, it knows what to call it (e.g. which frame)
+// once `cancel` is cancelled the scanlines left stay black
+pub fn render_with_progress(
+    camera: &dyn CameraModel,
+    world: &impl Hittable,
+    settings: &RenderSettings,
+    progress: &dyn Progress,
+    cancel: &CancellationToken
+) -> RenderOutput {
+    let start = Instant::now();
+    stats::take();
+    let mut accumulator = Accumulator::new(settings);
+    accumulator.add_samples(camera, world, settings, settings.samples_per_pixel, progress, cancel);
+    accumulator.into_output(stats::take(), start.elapsed())
+}
+
+// renders `settings.samples_per_pixel` in passes of `samples_per_pass`, calling `on_pass` with the image so far after each one
+// `on_pass` returns false to stop early, the output then has the samples of the passes up to there
+// cancelling stops in the middle of a pass, the pixels it got to keep their extra samples
+// with a time budget it stops at the deadline, or before a pass that wouldn't be done by then if the last one is anything to go by
+// the first pass is always finished though, a partial image isn't much use
+// progress counts every scanline of every pass, `progress_units(...)` in total
+pub fn render_progressive(
+    camera: &dyn CameraModel,
+    world: &impl Hittable,
+    settings: &RenderSettings,
+    samples_per_pass: i32,
+    progress: &dyn Progress,
+    cancel: &CancellationToken,
+    mut on_pass: impl FnMut(&Accumulator) -> bool
+) -> RenderOutput {
+    let start = Instant::now();
+    stats::take();
+    // a budget too big to add to the clock is no deadline at all
+    let deadline = settings.time_budget.and_then(|budget| start.checked_add(budget));
+    let timed = deadline.map_or(cancel.clone(), |deadline| cancel.with_deadline(deadline));
+    let mut accumulator = Accumulator::new(settings);
+    let mut last_pass = Duration::ZERO;
+    while accumulator.samples_per_pixel < settings.samples_per_pixel {
+        let pass_start = Instant::now();
+        if deadline.is_some_and(|deadline| pass_start + last_pass > deadline) {
+            break;
+        }
+        let samples = samples_per_pass.min(settings.samples_per_pixel - accumulator.samples_per_pixel);
+        let cancel = if accumulator.samples_per_pixel == 0 { cancel } else { &timed };
+        // against a time budget the progress is in milliseconds, one pass at a time
+        let finished = match settings.time_budget {
+            Some(_) => {
+                let finished = accumulator.add_samples(camera, world, settings, samples, &Quiet, cancel);
+                progress.advance(pass_start.elapsed().as_millis() as u64);
+                finished
+            },
+            None => accumulator.add_samples(camera, world, settings, samples, progress, cancel)
+        };
+        last_pass = pass_start.elapsed();
+        // an unfinished pass is still worth a look
+        if !on_pass(&accumulator) || !finished {
+            break;
+        }
+    }
+    accumulator.into_output(stats::take(), start.elapsed())
+}
+
+// a week, a longer budget is most likely a typo
+pub const MAX_TIME_BUDGET: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+// a time budget given in seconds, as `--time-budget` and a job's `budget` take it
+pub fn time_budget(seconds: f32) -> Result<Duration, String> {
+    if seconds.is_nan() || seconds <= 0.0 {
+        return Err("the time budget must be positive".to_string());
+    }
+    Duration::try_from_secs_f32(seconds).ok()
+        .filter(|budget| *budget <= MAX_TIME_BUDGET)
+        .ok_or_else(|| format!("the time budget is limited to {} seconds", MAX_TIME_BUDGET.as_secs()))
+}
+
+pub fn pass_count(settings: &RenderSettings, samples_per_pass: i32) -> i32 {
+    (settings.samples_per_pixel + samples_per_pass - 1) / samples_per_pass
+}
+
+// what `render_progressive` reports to its progress in total: the scanlines of every pass,
+// or the milliseconds of the time budget
+pub fn progress_units(settings: &RenderSettings, samples_per_pass: i32) -> u64 {
+    match settings.time_budget {
+        Some(budget) => budget.as_millis() as u64,
+        None => settings.image_height as u64 * pass_count(settings, samples_per_pass) as u64
+    }
+}
+
+// below this fraction of its positive weight a pixel's weight is too far gone to divide by
+const MIN_WEIGHT_FRACTION: f32 = 0.25;
+
+// the running sums of a render, more samples can be added at any time and the image looked at in between
+// the buffers only cover the region, the pixels around it are traced for the filter but not kept
+pub struct Accumulator {
+    // the sum of weight * color and the sum of weights of every pixel
+    weighted_colors: Framebuffer,
+    weights: Vec<f32>,
+    // only the weights above zero, what's left of `weights` after the negative lobes of the filter is compared to it
+    positive_weights: Vec<f32>,
+    // summed up, not averaged yet
+    aovs: Option<AovBuffers>,
+    region: Region,
+    image_width: usize,
+    image_height: usize,
+    // the samples every pixel of each row of the region got, more than `samples_per_pixel` in the rows of a pass
+    // that was cancelled
+    row_samples: Vec<i32>,
+    // finished passes only
+    pub samples_per_pixel: i32
+}
+
+impl Accumulator {
+    pub fn new(settings: &RenderSettings) -> Accumulator {
+        let (image_width, image_height) = (settings.image_width, settings.image_height);
+        let region = settings.region.unwrap_or(Region { x: 0, y: 0, width: image_width, height: image_height });
+        let (width, height) = (region.width, region.height);
+        Accumulator {
+            weighted_colors: Framebuffer::new(width, height),
+            weights: vec![0.0; width * height],
+            positive_weights: vec![0.0; width * height],
+            aovs: if settings.collect_aovs { Some(AovBuffers::new(width, height)) } else { None },
+            region,
+            image_width,
+            image_height,
+            row_samples: vec![0; height],
+            samples_per_pixel: 0
+        }
+    }
+
+    // start over, e.g. after the camera moved
+    pub fn reset(&mut self) {
+        let (width, height) = (self.region.width, self.region.height);
+        self.weighted_colors = Framebuffer::new(width, height);
+        self.weights.iter_mut().for_each(|weight| *weight = 0.0);
+        self.positive_weights.iter_mut().for_each(|weight| *weight = 0.0);
+        if self.aovs.is_some() {
+            self.aovs = Some(AovBuffers::new(width, height));
+        }
+        self.row_samples.iter_mut().for_each(|samples| *samples = 0);
+        self.samples_per_pixel = 0;
+    }
+
+    // trace `samples` more samples for every pixel, reporting each scanline to `progress`
+    // false when it was cancelled before every scanline was done
+    pub fn add_samples(
+        &mut self,
+        camera: &dyn CameraModel,
+        world: &impl Hittable,
+        settings: &RenderSettings,
+        samples: i32,
+        progress: &dyn Progress,
+        cancel: &CancellationToken
+    ) -> bool {
+        let (width, height) = (self.image_width, self.image_height);
+        let region = self.region;
+        let first_pass = self.samples_per_pixel == 0;
+        // the filter spreads the samples of the pixels around the region into it, so those are traced too
+        let apron = (settings.filter.radius + 0.5).ceil() as usize;
+        let columns = region.x.saturating_sub(apron)..(region.x + region.width + apron).min(width);
+        let rows = region.y.saturating_sub(apron)..(region.y + region.height + apron).min(height);
+
+        for j in (0..height).rev() {
+            // the framebuffer starts from the top scanline while v starts from the bottom
+            let y = height - 1 - j;
+            if !rows.contains(&y) {
+                progress.advance(1);
+                continue;
+            }
+            if cancel.is_cancelled() {
+                return false;
+            }
+            for i in columns.clone() {
+                if let Some(seed) = settings.seed {
+                    rng::seed(rng::hash(&[seed, i as u64, y as u64, self.samples_per_pixel as u64]));
+                }
+                for s in 0..samples {
+                    // sample position in continuous pixel coordinates, pixel (i, j) covers [i, i + 1) x [j, j + 1)
+                    let sx = i as f32 + rng::random::<f32>();
+                    let sy = j as f32 + rng::random::<f32>();
+                    let r = camera.get_ray(sx / (width - 1) as f32, sy / (height - 1) as f32);
+                    stats::count(|c| c.camera_rays += 1);
+                    let mut color = match self.aovs.as_mut() {
+                        Some(buffers) => {
+                            let sample = aov::trace(&r, world, settings.max_depth, camera);
+                            if region.contains(i, y) {
+                                buffers.accumulate(i - region.x, y - region.y, &sample, first_pass && s == 0);
+                            }
+                            sample.direct + sample.indirect
+                        },
+                        None if !settings.spectral => ray_color(&r, world, settings.max_depth),
+                        None => Color::new_empty()
+                    };
+                    // the passes are still traced in rgb, only the beauty image is spectral
+                    if settings.spectral {
+                        let mut wavelengths = SampledWavelengths::sample_uniform(rng::random::<f32>());
+                        let radiance = ray_spectrum(&r, world, settings.max_depth, &mut wavelengths);
+                        color = radiance.to_rgb(&wavelengths);
+                    }
+                    self.splat(&settings.filter, sx, sy, color);
+                }
+            }
+            if let Some(row) = y.checked_sub(region.y).filter(|row| *row < region.height) {
+                self.row_samples[row] += samples;
+            }
+            progress.advance(1);
+        }
+        self.samples_per_pixel += samples;
+        true
+    }
+
+    // add the sample to every pixel of the region within the filter radius
+    fn splat(&mut self, filter: &Filter, sx: f32, sy: f32, color: Color) {
+        let region = &self.region;
+        // sx and sy count from the bottom left of the image, the top and bottom row of the region counted the same way
+        let top = (self.image_height - 1 - region.y) as i64;
+        let bottom = top + 1 - region.height as i64;
+        // pixel centers are at i + 0.5
+        let min_i = ((sx - 0.5 - filter.radius).ceil() as i64).max(region.x as i64);
+        let max_i = ((sx - 0.5 + filter.radius).floor() as i64).min((region.x + region.width) as i64 - 1);
+        let min_j = ((sy - 0.5 - filter.radius).ceil() as i64).max(bottom);
+        let max_j = ((sy - 0.5 + filter.radius).floor() as i64).min(top);
+
+        for j in min_j..=max_j {
+            for i in min_i..=max_i {
+                let weight = filter.evaluate(i as f32 + 0.5 - sx, j as f32 + 0.5 - sy);
+                if weight == 0.0 {
+                    continue;
+                }
+                let index = ((top - j) * region.width as i64 + i - region.x as i64) as usize;
+                self.weighted_colors.pixels[index] += weight * color;
+                self.weights[index] += weight;
+                self.positive_weights[index] += weight.max(0.0);
+            }
+        }
+    }
+
+    // the part of the image the buffers cover
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    // the averaged color of each pixel of the region so far
+    pub fn beauty(&self) -> Framebuffer {
+        let mut beauty = self.weighted_colors.clone();
+        for ((pixel, weight), positive) in beauty.pixels.iter_mut().zip(self.weights.iter()).zip(self.positive_weights.iter()) {
+            // the negative lobes of mitchell and lanczos can cancel out most of the weight where the samples on one side
+            // are missing (the image edges, the apron of a region), dividing by what's left would blow the pixel up
+            // or flip its sign, the positive lobes alone keep it in range
+            let weight = if *weight >= MIN_WEIGHT_FRACTION * positive { *weight } else { *positive };
+            *pixel = if weight > 1e-8 { *pixel / weight } else { Color::new_empty() };
+            // what ringing is left can still dip below black
+            *pixel = Color::new(pixel.x.max(0.0), pixel.y.max(0.0), pixel.z.max(0.0));
+        }
+        beauty
+    }
+
+    // the whole image, black around the region, for previews
+    pub fn image(&self) -> Framebuffer {
+        self.beauty().pad(&self.region, self.image_width, self.image_height)
+    }
+
+    // the final image and passes, `counters` and `duration` are passed on to the output
+    pub fn into_output(self, counters: Counters, duration: Duration) -> RenderOutput {
+        let beauty = self.beauty();
+        let mut aovs = self.aovs;
+        if let Some(buffers) = aovs.as_mut() {
+            buffers.resolve(&self.row_samples);
+        }
+        RenderOutput { beauty, aovs, counters, duration, samples_per_pixel: self.samples_per_pixel }
+    }
+}
@@ -0,0 +1,57 @@
+use std::cell::RefCell;
+use std::ops::Range;
+
+use rand::distributions::uniform::SampleUniform;
+use rand::distributions::{Distribution, Standard};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// every random number of the renderer comes from here, one generator per thread
+// it starts from entropy like `rand::thread_rng`, `seed` makes the following numbers repeatable
+// (for benchmarks and tests, the same seed and the same scene give the same image)
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+// mixes the values into one well spread seed (splitmix64 steps), e.g. a pixel's seed from the image seed and its position
+pub fn hash(values: &[u64]) -> u64 {
+    let mut h: u64 = 0x9e37_79b9_7f4a_7c15;
+    for value in values {
+        h = (h ^ value).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^= h >> 31;
+    }
+    h
+}
+
+// like `rand::random`
+pub fn random<T>() -> T where Standard: Distribution<T> {
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
+
+// like `Rng::gen_range` with a half open range
+pub fn gen_range<T: SampleUniform + PartialOrd>(range: Range<T>) -> T {
+    RNG.with(|rng| rng.borrow_mut().gen_range(range))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::rng::*;
+
+    #[test]
+    fn test_seed() {
+        seed(7);
+        let first: Vec<f32> = (0..4).map(|_| random::<f32>()).collect();
+        seed(7);
+        let second: Vec<f32> = (0..4).map(|_| random::<f32>()).collect();
+        assert_eq!(first, second);
+        assert!((0..100).map(|_| gen_range(2..5)).all(|i| (2..5).contains(&i)));
+    }
+}
@@ -0,0 +1,130 @@
+use std::rc::Rc;
+
+use crate::framebuffer::Framebuffer;
+use crate::heightfield::Heightfield;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::ray::Ray;
+use crate::rng;
+use crate::material::*;
+use crate::packet::SphereSet;
+use crate::spectrum::Dispersion;
+use crate::shapes::Plane;
+use crate::sphere::Sphere;
+use crate::vec3::{Color, Point3, Vec3};
+
+// the built-in scenes
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SceneKind {
+    #[default]
+    Random,
+    // terrain instead of a flat ground
+    Landscape
+}
+
+impl SceneKind {
+    // the names used on the command line and in job files
+    pub fn from_name(name: &str) -> Option<SceneKind> {
+        match name {
+            "random" => Some(SceneKind::Random),
+            "landscape" => Some(SceneKind::Landscape),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SceneKind::Random => "random",
+            SceneKind::Landscape => "landscape"
+        }
+    }
+}
+
+// the cover scene of "Ray Tracing in One Weekend", a lot of small random spheres around 3 big ones
+// the small spheres are tested 8 at a time as one `SphereSet`
+pub fn random_scene() -> HittableList {
+    let mut world = HittableList::new();
+
+    let ground_material = Rc::new(Lambertian {albedo: Color::new(0.5, 0.5, 0.5)});
+    world.add(Rc::new(Plane::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), ground_material)));
+
+    world.add(Rc::new(SphereSet::new(random_spheres())));
+
+    // the big glass sphere splits light into colors when rendered with `--spectral`
+    let material_1 = Rc::new(Dielectric {ir: 1.5, dispersion: Some(Dispersion::bk7())});
+    world.add(Rc::new(Sphere {center: Point3::new(0.0, 1.0, 0.0), radius: 1.0, material: material_1}));
+
+    
+    let material_2 = Rc::new(Lambertian {albedo: Color::new(0.4, 0.2, 0.1)});
+    world.add(Rc::new(Sphere {center: Point3::new(-4.0, 1.0, 0.0), radius: 1.0, material: material_2}));
+
+    
+    let material_3 = Rc::new(Metal {albedo: Color::new(0.7, 0.6, 0.5), fuzz: 0.0});
+    world.add(Rc::new(Sphere {center: Point3::new(4.0, 1.0, 0.0), radius: 1.0, material: material_3}));
+    world
+}
+
+// the small spheres of `random_scene`, on a grid with random offsets and random materials
+pub fn random_spheres() -> Vec<Sphere> {
+    let mut spheres = Vec::new();
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = rng::random::<f32>();
+            let center = Point3::new(a as f32 + 0.9 * rng::random::<f32>(), 0.2, b as f32 + 0.9 * rng::random::<f32>());
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                let sphere_material: Rc<dyn Material>;
+
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Color::random() * Color::random();
+                    sphere_material = Rc::new(Lambertian {albedo});
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Color::random_range(0.5, 1.0);
+                    let fuzz: f32 = rng::gen_range(0.0..0.5);
+                    sphere_material = Rc::new(Metal {albedo, fuzz});
+                } else {
+                    // glass
+                    sphere_material = Rc::new(Dielectric {ir: 1.5, dispersion: Some(Dispersion::Cauchy {a: 1.5046, b: 0.0042})});
+                }
+                spheres.push(Sphere {center, radius: 0.2, material: sphere_material});
+            }
+        }
+    }
+    spheres
+}
+
+// the 3 big spheres of the cover scene on rolling hills, or on the terrain from a grayscale height map
+// fails when the height map is too small for a terrain
+pub fn landscape_scene(height_map: Option<&Framebuffer>) -> Result<HittableList, String> {
+    let mut world = HittableList::new();
+
+    let ground_material = Rc::new(Lambertian {albedo: Color::new(0.35, 0.45, 0.25)});
+    let origin = Point3::new(-40.0, 0.0, -40.0);
+    let size = Vec3::new(80.0, 4.0, 80.0);
+    let mut terrain = match height_map {
+        Some(image) => Heightfield::from_image(image, origin, size, ground_material)?,
+        None => Heightfield::from_noise(256, 256, 1, origin, size, ground_material)
+    };
+
+    // move the terrain down so the camera (aimed at the origin) looks at the ground
+    terrain.origin.y -= ground_height(&terrain, 0.0, 0.0);
+
+    let material_1 = Rc::new(Dielectric {ir: 1.5, dispersion: Some(Dispersion::bk7())});
+    let material_2 = Rc::new(Lambertian {albedo: Color::new(0.4, 0.2, 0.1)});
+    let material_3 = Rc::new(Metal {albedo: Color::new(0.7, 0.6, 0.5), fuzz: 0.0});
+    let spheres: [(f32, Rc<dyn Material>); 3] = [(0.0, material_1), (-4.0, material_2), (4.0, material_3)];
+    for (x, material) in spheres {
+        // rest each sphere on the ground below its center
+        world.add(Rc::new(Sphere {center: Point3::new(x, ground_height(&terrain, x, 0.0) + 1.0, 0.0), radius: 1.0, material}));
+    }
+
+    world.add(Rc::new(terrain));
+    Ok(world)
+}
+
+fn ground_height(terrain: &Heightfield, x: f32, z: f32) -> f32 {
+    let down = Ray {origin: Point3::new(x, 100.0, z), direction: Vec3::new(0.0, -1.0, 0.0)};
+    terrain.hit(&down, 0.001, f32::MAX).map_or(0.0, |rec| rec.p.y)
+}
@@ -0,0 +1,226 @@
+use std::rc::Rc;
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+// a signed distance function: negative inside, positive outside and never more than the
+// distance to the closest surface, so a ray can safely move that far ahead
+#[derive(Clone)]
+pub struct Sdf {
+    function: Rc<dyn Fn(Point3) -> f32>
+}
+
+impl Sdf {
+    pub fn new(function: impl Fn(Point3) -> f32 + 'static) -> Sdf {
+        Sdf { function: Rc::new(function) }
+    }
+
+    pub fn distance(&self, p: Point3) -> f32 {
+        (self.function)(p)
+    }
+
+    pub fn sphere(radius: f32) -> Sdf {
+        Sdf::new(move |p| p.length() - radius)
+    }
+
+    // a box reaching from -half_size to half_size
+    pub fn cuboid(half_size: Vec3) -> Sdf {
+        Sdf::new(move |p| {
+            let q = Vec3::new(p.x.abs() - half_size.x, p.y.abs() - half_size.y, p.z.abs() - half_size.z);
+            let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+            outside + q.x.max(q.y).max(q.z).min(0.0)
+        })
+    }
+
+    // a ring around the y axis
+    pub fn torus(major_radius: f32, minor_radius: f32) -> Sdf {
+        Sdf::new(move |p| {
+            let ring = (p.x * p.x + p.z * p.z).sqrt() - major_radius;
+            (ring * ring + p.y * p.y).sqrt() - minor_radius
+        })
+    }
+
+    pub fn translate(self, offset: Vec3) -> Sdf {
+        Sdf::new(move |p| self.distance(p - offset))
+    }
+
+    // blends the two shapes together where they're closer than `k`
+    // see https://iquilezles.org/articles/smin/
+    pub fn smooth_union(self, other: Sdf, k: f32) -> Sdf {
+        Sdf::new(move |p| {
+            let (a, b) = (self.distance(p), other.distance(p));
+            let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+            b + (a - b) * h - k * h * (1.0 - h)
+        })
+    }
+
+    // infinite copies of the shape, one in every cell of the given size, centered on the origin
+    // a size of 0 doesn't repeat along that axis
+    pub fn repeat(self, period: Vec3) -> Sdf {
+        let wrap = |x: f32, period: f32| if period > 0.0 { x - period * (x / period).round() } else { x };
+        Sdf::new(move |p| self.distance(Vec3::new(wrap(p.x, period.x), wrap(p.y, period.y), wrap(p.z, period.z))))
+    }
+
+    // rotates the shape around the y axis by `rate` radians per unit of height
+    // this stretches distances, so the `SdfShape` needs a smaller step scale
+    pub fn twist(self, rate: f32) -> Sdf {
+        Sdf::new(move |p| {
+            let (sin, cos) = (rate * p.y).sin_cos();
+            self.distance(Vec3::new(cos * p.x + sin * p.z, p.y, -sin * p.x + cos * p.z))
+        })
+    }
+
+    // grows the shape by `radius`, which rounds off its edges
+    pub fn round(self, radius: f32) -> Sdf {
+        Sdf::new(move |p| self.distance(p) - radius)
+    }
+
+    // the normalized gradient, by central differences
+    pub fn normal(&self, p: Point3, h: f32) -> Vec3 {
+        let dx = Vec3::new(h, 0.0, 0.0);
+        let dy = Vec3::new(0.0, h, 0.0);
+        let dz = Vec3::new(0.0, 0.0, h);
+        Vec3::new(
+            self.distance(p + dx) - self.distance(p - dx),
+            self.distance(p + dy) - self.distance(p - dy),
+            self.distance(p + dz) - self.distance(p - dz)
+        ).unit_vector()
+    }
+}
+
+// a hittable made from an sdf, intersected by sphere tracing
+// see "Sphere Tracing: A Geometric Method for the Antialiased Ray Tracing of Implicit Surfaces" (Hart 1996)
+pub struct SdfShape {
+    pub sdf: Sdf,
+    // the shape has to fit in this sphere, rays only march through it
+    pub bounds_center: Point3,
+    pub bounds_radius: f32,
+    pub material: Rc<dyn Material>,
+    pub max_steps: usize,
+    // how close to the surface counts as a hit
+    pub epsilon: f32,
+    // fraction of the distance to move in each step, below 1 for distance functions that overestimate (like `twist`)
+    pub step_scale: f32
+}
+
+impl SdfShape {
+    pub fn new(sdf: Sdf, bounds_center: Point3, bounds_radius: f32, material: Rc<dyn Material>) -> SdfShape {
+        SdfShape { sdf, bounds_center, bounds_radius, material, max_steps: 256, epsilon: 1e-4, step_scale: 1.0 }
+    }
+}
+
+impl Hittable for SdfShape {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        // march along a unit direction so the distances are in scene units
+        let length = r.direction.length();
+        let direction = r.direction / length;
+
+        // only the part of the ray inside the bounding sphere
+        let oc = r.origin - self.bounds_center;
+        let half_b = oc.dot(direction);
+        let discriminant = half_b * half_b - (oc.length_squared() - self.bounds_radius * self.bounds_radius);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let mut t = (-half_b - discriminant.sqrt()).max(t_min * length);
+        let end = (-half_b + discriminant.sqrt()).min(t_max * length);
+
+        // rays starting inside the shape look for the way out
+        let sign = if self.sdf.distance(r.origin + t * direction) < 0.0 { -1.0 } else { 1.0 };
+        let mut previous = t;
+        let mut found = None;
+        for step in 0..self.max_steps {
+            if t > end {
+                return None;
+            }
+            let distance = sign * self.sdf.distance(r.origin + t * direction);
+            // the first step doesn't count, the ray may start right on the surface it just left
+            if step > 0 && distance < self.epsilon {
+                // stepped over the surface, find it between the last two steps
+                if distance < 0.0 {
+                    let (mut inside, mut outside) = (t, previous);
+                    for _ in 0..16 {
+                        let middle = 0.5 * (inside + outside);
+                        if sign * self.sdf.distance(r.origin + middle * direction) < 0.0 {
+                            inside = middle;
+                        } else {
+                            outside = middle;
+                        }
+                    }
+                    t = outside;
+                }
+                found = Some(t);
+                break;
+            }
+            previous = t;
+            t += (distance * self.step_scale).max(self.epsilon);
+        }
+
+        let t = found? / length;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let mut rec = HitRecord::new(&r.at(t), t, Rc::clone(&self.material));
+        let outward_normal = self.sdf.normal(rec.p, self.epsilon);
+        rec.set_face_normal(r, &outward_normal);
+        Some(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::material::Lambertian;
+    use crate::sdf::*;
+    use crate::sphere::Sphere;
+    use crate::vec3::Color;
+
+    fn material() -> Rc<dyn Material> {
+        Rc::new(Lambertian { albedo: Color::new(0.5, 0.5, 0.5) })
+    }
+
+    #[test]
+    fn test_matches_sphere() {
+        let center = Point3::new(1.0, 0.5, -2.0);
+        let sdf = SdfShape::new(Sdf::sphere(1.0).translate(center), center, 1.5, material());
+        let sphere = Sphere { center, radius: 1.0, material: material() };
+        for direction in [Vec3::new(0.1, 0.05, -0.2), Vec3::new(0.12, 0.0, -0.25), Vec3::new(0.0, 0.3, -1.0)] {
+            let r = Ray { origin: Point3::new(0.0, 0.0, 0.0), direction };
+            let expected = sphere.hit(&r, 0.001, f32::MAX);
+            let actual = sdf.hit(&r, 0.001, f32::MAX);
+            assert_eq!(expected.is_some(), actual.is_some());
+            if let (Some(expected), Some(actual)) = (expected, actual) {
+                assert!((expected.p - actual.p).length() < 1e-3);
+                assert!((expected.normal - actual.normal).length() < 1e-2);
+            }
+        }
+
+        // from inside, the ray finds its way out
+        let r = Ray { origin: center, direction: Vec3::new(0.0, 2.0, 0.0) };
+        let rec = sdf.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((rec.p - (center + Vec3::new(0.0, 1.0, 0.0))).length() < 1e-3);
+        assert!(!rec.front_face);
+    }
+
+    #[test]
+    fn test_combinators() {
+        let a = Sdf::sphere(1.0).translate(Vec3::new(-1.2, 0.0, 0.0));
+        let b = Sdf::sphere(1.0).translate(Vec3::new(1.2, 0.0, 0.0));
+        // the gap between the spheres gets filled in
+        assert!(a.smooth_union(b, 1.0).distance(Point3::new(0.0, 0.0, 0.0)) < 0.0);
+
+        let repeated = Sdf::sphere(0.5).repeat(Vec3::new(4.0, 0.0, 4.0));
+        assert!(repeated.distance(Point3::new(8.0, 0.0, -12.0)) < 0.0);
+        assert!((repeated.distance(Point3::new(6.0, 0.0, 0.0)) - 1.5).abs() < 1e-5);
+
+        let rounded = Sdf::cuboid(Vec3::new(1.0, 1.0, 1.0)).round(0.2);
+        assert!((rounded.distance(Point3::new(2.0, 0.0, 0.0)) - 0.8).abs() < 1e-5);
+
+        // a quarter turn per unit of height
+        let twisted = Sdf::cuboid(Vec3::new(1.0, 2.0, 0.1)).twist(std::f32::consts::FRAC_PI_2);
+        assert!(twisted.distance(Point3::new(0.0, 1.0, 0.9)) < 0.0);
+        assert!(twisted.distance(Point3::new(0.0, 0.0, 0.9)) > 0.0);
+    }
+}
@@ -0,0 +1,563 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancellationToken;
+use crate::color_utils::OutputTransform;
+use crate::framebuffer::Framebuffer;
+use crate::image_io::{self, ExrChannel};
+use crate::job::RenderJob;
+use crate::progress::Progress;
+use crate::render;
+
+// a render server: jobs (`job::RenderJob` documents) are posted over http, queued and rendered one after the other
+// on a render thread, while clients poll their progress and fetch the images
+//
+//   POST   /jobs                   the job document as the body, answers with the status of the new job
+//   GET    /jobs                   the status of every job
+//   GET    /jobs/<id>              the status of one job: state (queued, rendering, done, cancelled or failed),
+//                                  samples per pixel so far, percent, seconds rendering
+//   DELETE /jobs/<id>              cancel it, a job that's rendering stops after the scanline it's on,
+//                                  a job that's over is forgotten with its image
+//   GET    /jobs/<id>/image        the image, `?format=png` (the default), `ppm`, `pfm` or `exr`
+//
+// everything but the images is json, errors are {"error": "..."} with a 4xx status
+
+// the image is rendered in passes of this many samples, the status shows how many are done
+const SAMPLES_PER_PASS: i32 = 4;
+const MAX_BODY: usize = 1 << 20;
+// the request line and all headers together
+const MAX_HEADER: u64 = 16 << 10;
+// jobs waiting to be rendered, more are turned down until there's room again
+const MAX_QUEUED: usize = 16;
+// jobs that are over are kept with their image (up to 400 MB each) until there are more than this,
+// then the oldest one is forgotten
+const MAX_FINISHED: usize = 4;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobState {
+    Queued,
+    Rendering,
+    Done,
+    Cancelled,
+    // the render panicked, the server goes on with the next job
+    Failed
+}
+
+impl JobState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Rendering => "rendering",
+            JobState::Done => "done",
+            JobState::Cancelled => "cancelled",
+            JobState::Failed => "failed"
+        }
+    }
+}
+
+// a submitted job, shared by the render thread and the connections asking about it
+struct ServerJob {
+    id: u64,
+    job: RenderJob,
+    state: Mutex<JobState>,
+    cancel: CancellationToken,
+    // scanlines of every pass, from the render's progress
+    done: AtomicU64,
+    total: AtomicU64,
+    samples_per_pixel: AtomicI32,
+    // when the render started and how long it took once it's over
+    started: Mutex<Option<Instant>>,
+    duration: Mutex<Option<Duration>>,
+    // the finished image, or what there was when it was cancelled
+    image: Mutex<Option<Framebuffer>>
+}
+
+impl Progress for ServerJob {
+    fn start(&self, _task: &str, total: u64) {
+        self.total.store(total, Ordering::Relaxed);
+        self.done.store(0, Ordering::Relaxed);
+    }
+
+    fn advance(&self, units: u64) {
+        self.done.fetch_add(units, Ordering::Relaxed);
+    }
+
+    fn finish(&self) {}
+}
+
+impl ServerJob {
+    fn status_json(&self) -> String {
+        let state = *self.state.lock().unwrap();
+        let total = self.total.load(Ordering::Relaxed);
+        let percent = if state == JobState::Done { 100.0 } else if total == 0 { 0.0 } else {
+            100.0 * self.done.load(Ordering::Relaxed) as f64 / total as f64
+        };
+        let elapsed = match (*self.duration.lock().unwrap(), *self.started.lock().unwrap()) {
+            (Some(duration), _) => duration,
+            (None, Some(started)) => started.elapsed(),
+            (None, None) => Duration::ZERO
+        };
+        format!(
+            "{{\"id\": {}, \"status\": \"{}\", \"samples\": {}, \"samples_per_pixel\": {}, \"percent\": {}, \"elapsed\": {}}}",
+            self.id,
+            state.name(),
+            self.samples_per_pixel.load(Ordering::Relaxed),
+            self.job.settings.samples_per_pixel,
+            percent,
+            elapsed.as_secs_f64()
+        )
+    }
+}
+
+// the jobs by id (the first one is 1), the ones waiting for the render thread and the ones that are over, oldest first
+#[derive(Default)]
+struct Jobs {
+    all: Mutex<BTreeMap<u64, Arc<ServerJob>>>,
+    last_id: AtomicU64,
+    queue: Mutex<VecDeque<Arc<ServerJob>>>,
+    queued: Condvar,
+    finished: Mutex<VecDeque<u64>>
+}
+
+impl Jobs {
+    fn get(&self, id: u64) -> Option<Arc<ServerJob>> {
+        self.all.lock().unwrap().get(&id).cloned()
+    }
+
+    // `None` when the queue is full
+    fn submit(&self, job: RenderJob) -> Option<Arc<ServerJob>> {
+        let mut all = self.all.lock().unwrap();
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= MAX_QUEUED {
+            return None;
+        }
+        let entry = Arc::new(ServerJob {
+            id: self.last_id.fetch_add(1, Ordering::Relaxed) + 1,
+            job,
+            state: Mutex::new(JobState::Queued),
+            cancel: CancellationToken::new(),
+            done: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            samples_per_pixel: AtomicI32::new(0),
+            started: Mutex::new(None),
+            duration: Mutex::new(None),
+            image: Mutex::new(None)
+        });
+        all.insert(entry.id, entry.clone());
+        queue.push_back(entry.clone());
+        self.queued.notify_one();
+        Some(entry)
+    }
+
+    // the job is over, forget the oldest ones that are over if there are too many
+    fn finish(&self, id: u64) {
+        let mut finished = self.finished.lock().unwrap();
+        finished.push_back(id);
+        while finished.len() > MAX_FINISHED {
+            let oldest = finished.pop_front().unwrap();
+            self.all.lock().unwrap().remove(&oldest);
+        }
+    }
+
+    fn forget(&self, id: u64) {
+        self.finished.lock().unwrap().retain(|finished| *finished != id);
+        self.all.lock().unwrap().remove(&id);
+    }
+
+    fn next(&self) -> Arc<ServerJob> {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if let Some(entry) = queue.pop_front() {
+                return entry;
+            }
+            queue = self.queued.wait(queue).unwrap();
+        }
+    }
+}
+
+// answer http requests on the listener until it fails, every connection gets its own thread
+// the renderer isn't `Send`, so the jobs are rendered on one thread that builds every scene itself
+pub fn serve(listener: TcpListener) -> io::Result<()> {
+    let jobs = Arc::new(Jobs::default());
+    let render_jobs = jobs.clone();
+    thread::spawn(move || render_loop(&render_jobs));
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let jobs = jobs.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &jobs) {
+                eprintln!("server: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn render_loop(jobs: &Jobs) {
+    loop {
+        let entry = jobs.next();
+        {
+            let mut state = entry.state.lock().unwrap();
+            if *state == JobState::Cancelled {
+                drop(state);
+                jobs.finish(entry.id);
+                continue;
+            }
+            *state = JobState::Rendering;
+        }
+        let started = Instant::now();
+        *entry.started.lock().unwrap() = Some(started);
+
+        // a bug in one job shouldn't take down the only render thread, the panic message still goes to stderr
+        let output = panic::catch_unwind(AssertUnwindSafe(|| {
+            let world = entry.job.build_world();
+            let settings = entry.job.settings();
+            entry.start("render", render::progress_units(&settings, SAMPLES_PER_PASS));
+            render::render_progressive(&entry.job.camera(), &world, &settings, SAMPLES_PER_PASS, entry.as_ref(), &entry.cancel, |accumulator| {
+                entry.samples_per_pixel.store(accumulator.samples_per_pixel, Ordering::Relaxed);
+                true
+            })
+        }));
+
+        *entry.duration.lock().unwrap() = Some(started.elapsed());
+        let state = match output {
+            Ok(output) => {
+                *entry.image.lock().unwrap() = Some(output.beauty);
+                if entry.cancel.is_cancelled() { JobState::Cancelled } else { JobState::Done }
+            },
+            Err(_) => JobState::Failed
+        };
+        *entry.state.lock().unwrap() = state;
+        jobs.finish(entry.id);
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    body: Vec<u8>
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>
+}
+
+impl Response {
+    fn json(status: u16, body: String) -> Response {
+        Response { status, content_type: "application/json", body: (body + "\n").into_bytes() }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, format!("{{\"error\": {}}}", json_string(message)))
+    }
+}
+
+fn json_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+fn handle_connection(stream: TcpStream, jobs: &Jobs) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let response = match read_request(&mut reader)? {
+        Ok(request) => handle(&request, jobs),
+        Err(response) => response
+    };
+
+    let reason = match response.status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => ""
+    };
+    let mut stream = reader.into_inner();
+    let header = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status, reason, response.content_type, response.body.len()
+    );
+    stream.write_all(header.as_bytes())?;
+    stream.write_all(&response.body)
+}
+
+// the outer error is the connection failing, the inner one the answer to a request that makes no sense
+fn read_request(reader: &mut BufReader<TcpStream>) -> io::Result<Result<Request, Response>> {
+    let bad = |message: &str| Ok(Err(Response::error(400, message)));
+    let too_large = || Ok(Err(Response::error(431, &format!("the request line and the headers are limited to {} bytes", MAX_HEADER))));
+    // a line that runs into the limit has no line break at the end
+    let mut header_reader = reader.by_ref().take(MAX_HEADER);
+    let mut line = String::new();
+    header_reader.read_line(&mut line)?;
+    if !line.ends_with('\n') && header_reader.limit() == 0 {
+        return too_large();
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return bad("invalid request line");
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (method, path, query) = (method.to_string(), path.to_string(), query.to_string());
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if header_reader.read_line(&mut line)? == 0 || !line.ends_with('\n') {
+            return if header_reader.limit() == 0 { too_large() } else { bad("truncated headers") };
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                let Ok(length) = value.trim().parse::<usize>() else {
+                    return bad("invalid content length");
+                };
+                content_length = length;
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return Ok(Err(Response::error(413, &format!("the body is limited to {} bytes", MAX_BODY))));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Ok(Request { method, path, query, body }))
+}
+
+fn handle(request: &Request, jobs: &Jobs) -> Response {
+    let segments: Vec<&str> = request.path.split('/').filter(|s| !s.is_empty()).collect();
+    let find = |id: &str| id.parse().ok().and_then(|id| jobs.get(id)).ok_or_else(|| Response::error(404, &format!("no job {}", id)));
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["jobs"]) => {
+            let job = match RenderJob::parse(&String::from_utf8_lossy(&request.body)) {
+                Ok(job) => job,
+                Err(e) => return Response::error(400, &format!("invalid job: {}", e))
+            };
+            match jobs.submit(job) {
+                Some(entry) => Response::json(201, entry.status_json()),
+                None => Response::error(503, &format!("the queue is full, {} jobs are waiting", MAX_QUEUED))
+            }
+        },
+        ("GET", ["jobs"]) => {
+            let all: Vec<String> = jobs.all.lock().unwrap().values().map(|entry| entry.status_json()).collect();
+            Response::json(200, format!("[{}]", all.join(", ")))
+        },
+        ("GET", ["jobs", id]) => match find(id) {
+            Ok(entry) => Response::json(200, entry.status_json()),
+            Err(response) => response
+        },
+        ("DELETE", ["jobs", id]) => match find(id) {
+            Ok(entry) => {
+                let mut state = entry.state.lock().unwrap();
+                let over = match *state {
+                    // the render thread skips it
+                    JobState::Queued => {
+                        *state = JobState::Cancelled;
+                        false
+                    },
+                    JobState::Rendering => false,
+                    // a cancelled job is only over once the render thread is done with it
+                    JobState::Cancelled => entry.duration.lock().unwrap().is_some() || entry.started.lock().unwrap().is_none(),
+                    JobState::Done | JobState::Failed => true
+                };
+                entry.cancel.cancel();
+                drop(state);
+                let status = entry.status_json();
+                if over {
+                    jobs.forget(entry.id);
+                }
+                Response::json(200, status)
+            },
+            Err(response) => response
+        },
+        ("GET", ["jobs", id, "image"]) => match find(id) {
+            Ok(entry) => image_response(&entry, &request.query),
+            Err(response) => response
+        },
+        (_, ["jobs"]) | (_, ["jobs", _]) | (_, ["jobs", _, "image"]) => Response::error(405, "method not allowed"),
+        _ => Response::error(404, "not found")
+    }
+}
+
+fn image_response(entry: &ServerJob, query: &str) -> Response {
+    let format = query.split('&').find_map(|pair| pair.strip_prefix("format=")).unwrap_or("png");
+    let image = entry.image.lock().unwrap();
+    let Some(image) = image.as_ref() else {
+        return Response::error(409, &format!("job {} has no image yet, it's {}", entry.id, entry.state.lock().unwrap().name()));
+    };
+    let transform = OutputTransform::default();
+    match format {
+        "png" => Response { status: 200, content_type: "image/png", body: image_io::encode_png(image, &transform) },
+        "ppm" => Response { status: 200, content_type: "image/x-portable-pixmap", body: image_io::encode_ppm(image, &transform) },
+        "pfm" => Response { status: 200, content_type: "application/octet-stream", body: image_io::encode_pfm(image, 3) },
+        "exr" => Response {
+            status: 200,
+            content_type: "image/x-exr",
+            body: image_io::encode_exr(image.width, image.height, ExrChannel::rgb("", image).into())
+        },
+        other => Response::error(400, &format!("unknown format '{}', expected 'png', 'ppm', 'pfm' or 'exr'", other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::server::*;
+    use crate::framebuffer::Region;
+    use crate::progress::Quiet;
+
+    // a tiny http client, one request per connection
+    fn request(address: &str, method: &str, path: &str, body: &str) -> (u16, Vec<u8>) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n{}", method, path, address, body.len(), body).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let status = String::from_utf8_lossy(&response[9..12]).parse().unwrap();
+        (status, response[split + 4..].to_vec())
+    }
+
+    fn text(response: (u16, Vec<u8>)) -> (u16, String) {
+        (response.0, String::from_utf8(response.1).unwrap())
+    }
+
+    #[test]
+    fn test_jobs() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener));
+
+        let document = "scene landscape\nseed 5\nsize 16 12\nsamples 6\nmax_depth 4\n";
+        let (status, body) = text(request(&address, "POST", "/jobs", document));
+        assert_eq!(status, 201, "{}", body);
+        assert!(body.starts_with("{\"id\": 1, \"status\": \""), "{}", body);
+
+        let start = Instant::now();
+        while !text(request(&address, "GET", "/jobs/1", "")).1.contains("\"done\"") {
+            assert!(start.elapsed() < Duration::from_secs(60), "the job never finished");
+            thread::sleep(Duration::from_millis(20));
+        }
+        let (status, body) = text(request(&address, "GET", "/jobs/1", ""));
+        assert_eq!(status, 200);
+        assert!(body.contains("\"samples\": 6, \"samples_per_pixel\": 6, \"percent\": 100"), "{}", body);
+
+        // the same pixels as rendering the job here
+        let job = RenderJob::parse(document).unwrap();
+        let local = render::render_progressive(&job.camera(), &job.build_world(), &job.settings(), SAMPLES_PER_PASS, &Quiet, &CancellationToken::new(), |_| true);
+        let (status, pfm) = request(&address, "GET", "/jobs/1/image?format=pfm", "");
+        assert_eq!(status, 200);
+        assert_eq!(image_io::decode_pfm(&pfm).unwrap().pixels, local.beauty.pixels);
+        assert!(request(&address, "GET", "/jobs/1/image", "").1.starts_with(b"\x89PNG"));
+        assert!(request(&address, "GET", "/jobs/1/image?format=ppm", "").1.starts_with(b"P3\n16 12\n"));
+        assert_eq!(request(&address, "GET", "/jobs/1/image?format=jpg", "").0, 400);
+        // a job that's over is forgotten, image and all
+        assert_eq!(request(&address, "DELETE", "/jobs/1", "").0, 200);
+        assert_eq!(request(&address, "GET", "/jobs/1", "").0, 404);
+
+        // a long job, and one queued behind it, both cancelled
+        let (status, _) = request(&address, "POST", "/jobs", "size 32 24\nsamples 100000\nmax_depth 4\n");
+        assert_eq!(status, 201);
+        assert_eq!(request(&address, "POST", "/jobs", "size 16 12\n").0, 201);
+        let (status, body) = text(request(&address, "DELETE", "/jobs/3", ""));
+        assert_eq!(status, 200);
+        assert!(body.contains("\"cancelled\""), "{}", body);
+        assert_eq!(request(&address, "GET", "/jobs/3/image", "").0, 409);
+        assert_eq!(request(&address, "DELETE", "/jobs/2", "").0, 200);
+        while !text(request(&address, "GET", "/jobs/2", "")).1.contains("\"cancelled\"") {
+            assert!(start.elapsed() < Duration::from_secs(60), "the job was never cancelled");
+            thread::sleep(Duration::from_millis(20));
+        }
+        // what was there when it stopped
+        assert_eq!(request(&address, "GET", "/jobs/2/image", "").0, 200);
+        assert_eq!(text(request(&address, "GET", "/jobs", "")).1.matches("\"id\"").count(), 2);
+        assert_eq!(request(&address, "DELETE", "/jobs/2", "").0, 200);
+        assert_eq!(request(&address, "GET", "/jobs/2/image", "").0, 404);
+
+        assert_eq!(text(request(&address, "POST", "/jobs", "size 10")).1, "{\"error\": \"invalid job: line 1: expected 2 numbers after 'size'\"}\n");
+        assert_eq!(request(&address, "POST", "/jobs", "size 60000 60000").0, 400);
+        assert_eq!(request(&address, "POST", "/jobs", "size 4294967296 4294967296").0, 400);
+        assert_eq!(request(&address, "POST", "/jobs", "budget 1e19").0, 400);
+        assert_eq!(request(&address, "POST", "/jobs", "budget 1e30").0, 400);
+        assert_eq!(request(&address, "GET", "/jobs/9", "").0, 404);
+        assert_eq!(request(&address, "PUT", "/jobs/1", "").0, 405);
+        assert_eq!(request(&address, "GET", "/", "").0, 404);
+    }
+
+    // the job that fails does so with an integer overflow, which only panics with debug assertions
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_failed_job() {
+        let jobs = Arc::new(Jobs::default());
+        let render_jobs = jobs.clone();
+        thread::spawn(move || render_loop(&render_jobs));
+
+        // `parse` doesn't let a region in, one at the end of the address space makes the render panic
+        let small = RenderJob::parse("size 16 12\nsamples 1\nmax_depth 2").unwrap();
+        let region = Some(Region { x: usize::MAX, y: 0, width: 4, height: 4 });
+        let broken = jobs.submit(RenderJob { settings: render::RenderSettings { region, ..small.settings }, ..small }).unwrap();
+        let next = jobs.submit(small).unwrap();
+
+        let start = Instant::now();
+        while *next.state.lock().unwrap() != JobState::Done {
+            assert!(start.elapsed() < Duration::from_secs(60), "the job after the failed one never finished");
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(*broken.state.lock().unwrap(), JobState::Failed);
+        assert!(broken.status_json().contains("\"failed\""));
+        assert!(broken.image.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_long_headers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener));
+
+        // exactly the limit without a blank line, so the server reads all of it before answering
+        let mut stream = TcpStream::connect(&address).unwrap();
+        let start = "GET /jobs HTTP/1.1\r\nX-Padding: ";
+        write!(stream, "{}{}", start, "a".repeat(MAX_HEADER as usize - start.len())).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 431 "), "{}", response);
+        assert_eq!(request(&address, "GET", "/jobs", "").0, 200);
+    }
+
+    #[test]
+    fn test_limits() {
+        // no render thread, so nothing leaves the queue
+        let jobs = Jobs::default();
+        for _ in 0..MAX_QUEUED {
+            assert!(jobs.submit(RenderJob::default()).is_some());
+        }
+        assert!(jobs.submit(RenderJob::default()).is_none());
+
+        // only the last jobs that are over are kept
+        for id in 1..=MAX_FINISHED as u64 + 2 {
+            jobs.finish(id);
+        }
+        assert!(jobs.get(1).is_none() && jobs.get(2).is_none());
+        assert!(jobs.get(3).is_some());
+        jobs.forget(3);
+        assert!(jobs.get(3).is_none());
+        assert_eq!(jobs.all.lock().unwrap().len(), MAX_QUEUED - 3);
+    }
+}
@@ -0,0 +1,541 @@
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+// analytic shapes besides the sphere
+// each one is intersected in its own local space, where the shape's axis is the y axis
+// and the base (or the center) sits at the origin
+
+// an orthonormal frame with `axis` as the local y axis
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+    pub origin: Point3,
+    pub x: Vec3,
+    pub y: Vec3,
+    pub z: Vec3
+}
+
+impl Frame {
+    pub fn new(origin: Point3, axis: Vec3) -> Frame {
+        let y = axis.unit_vector();
+        // pick a helper that isn't parallel to the axis, for an upright axis x ends up as the world x
+        let helper = if y.z.abs() > 0.9 { Vec3::new(1.0, 0.0, 0.0) } else { Vec3::new(0.0, 0.0, 1.0) };
+        let x = y.cross(helper).unit_vector();
+        let z = x.cross(y);
+        Frame { origin, x, y, z }
+    }
+
+    pub fn to_local(&self, p: Point3) -> Point3 {
+        let d = p - self.origin;
+        self.direction_to_local(d)
+    }
+
+    pub fn direction_to_local(&self, d: Vec3) -> Vec3 {
+        Vec3::new(d.dot(self.x), d.dot(self.y), d.dot(self.z))
+    }
+
+    pub fn direction_to_world(&self, d: Vec3) -> Vec3 {
+        d.x * self.x + d.y * self.y + d.z * self.z
+    }
+
+    // the frame is orthonormal, so t along the local ray is the same as along the world ray
+    pub fn ray_to_local(&self, r: &Ray) -> Ray {
+        Ray { origin: self.to_local(r.origin), direction: self.direction_to_local(r.direction) }
+    }
+}
+
+// a possible hit in local space: t, the outward normal, the uv coordinates and their derivatives
+struct LocalHit {
+    t: f32,
+    normal: Vec3,
+    u: f32,
+    v: f32,
+    dpdu: Vec3,
+    dpdv: Vec3
+}
+
+// turn the closest local hit within (t_min, t_max) into a hit record
+fn closest_hit(
+    hits: impl IntoIterator<Item = LocalHit>,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+    frame: &Frame,
+    material: &Rc<dyn Material>
+) -> Option<HitRecord> {
+    let hit = hits.into_iter()
+        .filter(|hit| hit.t > t_min && hit.t < t_max)
+        .min_by(|a, b| a.t.total_cmp(&b.t))?;
+    let mut rec = HitRecord::new(&r.at(hit.t), hit.t, Rc::clone(material));
+    let outward_normal = frame.direction_to_world(hit.normal).unit_vector();
+    rec.set_face_normal(r, &outward_normal);
+    rec.u = hit.u;
+    rec.v = hit.v;
+    rec.dpdu = frame.direction_to_world(hit.dpdu);
+    rec.dpdv = frame.direction_to_world(hit.dpdv);
+    Some(rec)
+}
+
+// the angle around the local y axis, mapped to [0, 1]
+fn azimuth(p: Point3) -> f32 {
+    ((-p.z).atan2(p.x) + PI) / (2.0 * PI)
+}
+
+// how p moves when its azimuth grows
+fn azimuth_tangent(p: Point3) -> Vec3 {
+    2.0 * PI * Vec3::new(p.z, 0.0, -p.x)
+}
+
+// the part of `direction` that lies in the plane with the given normal
+fn project_onto_surface(direction: Vec3, normal: Vec3) -> Vec3 {
+    direction - direction.dot(normal) * normal
+}
+
+// roots of a * t^2 + 2 * half_b * t + c, in increasing order
+fn solve_quadratic(a: f32, half_b: f32, c: f32) -> Vec<f32> {
+    if a.abs() < 1e-12 {
+        return if half_b.abs() < 1e-12 { Vec::new() } else { vec![-c / (2.0 * half_b)] };
+    }
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+    let discriminant_sqrt = discriminant.sqrt();
+    let (t0, t1) = ((-half_b - discriminant_sqrt) / a, (-half_b + discriminant_sqrt) / a);
+    vec![t0.min(t1), t0.max(t1)]
+}
+
+// hits of the local ray with the disk of the given radius in the plane y = height
+// the normal points up when `up` is set, otherwise down
+fn cap_hit(r: &Ray, height: f32, radius: f32, up: bool) -> Option<LocalHit> {
+    if r.direction.y == 0.0 {
+        return None;
+    }
+    let t = (height - r.origin.y) / r.direction.y;
+    let p = r.at(t);
+    let distance_squared = p.x * p.x + p.z * p.z;
+    if distance_squared > radius * radius {
+        return None;
+    }
+    let normal = Vec3::new(0.0, if up { 1.0 } else { -1.0 }, 0.0);
+    let distance = distance_squared.sqrt();
+    let outwards = if distance > 0.0 { Vec3::new(p.x, 0.0, p.z) * (radius / distance) } else { Vec3::new_empty() };
+    Some(LocalHit { t, normal, u: azimuth(p), v: distance / radius, dpdu: azimuth_tangent(p), dpdv: outwards })
+}
+
+// an infinite plane, it isn't a closed solid so it can't be used in csg
+// u and v are the distances along the plane's local x and z axes in scene units
+pub struct Plane {
+    pub frame: Frame,
+    pub material: Rc<dyn Material>
+}
+
+impl Plane {
+    pub fn new(point: Point3, normal: Vec3, material: Rc<dyn Material>) -> Plane {
+        Plane { frame: Frame::new(point, normal), material }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let local = self.frame.ray_to_local(r);
+        if local.direction.y == 0.0 {
+            return None;
+        }
+        let t = -local.origin.y / local.direction.y;
+        let p = local.at(t);
+        let hit = LocalHit {
+            t,
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            u: p.x,
+            v: p.z,
+            dpdu: Vec3::new(1.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, 1.0)
+        };
+        closest_hit([hit], r, t_min, t_max, &self.frame, &self.material)
+    }
+}
+
+// a flat disk facing along `normal`, u goes around the center and v from the center to the rim
+pub struct Disk {
+    pub frame: Frame,
+    pub radius: f32,
+    pub material: Rc<dyn Material>
+}
+
+impl Disk {
+    pub fn new(center: Point3, normal: Vec3, radius: f32, material: Rc<dyn Material>) -> Disk {
+        Disk { frame: Frame::new(center, normal), radius, material }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let local = self.frame.ray_to_local(r);
+        closest_hit(cap_hit(&local, 0.0, self.radius, true), r, t_min, t_max, &self.frame, &self.material)
+    }
+}
+
+// a capped cylinder standing on `base` and reaching `height` along `axis`
+// on the side u goes around the axis and v from the base to the top, on the caps v goes from the center to the rim
+pub struct Cylinder {
+    pub frame: Frame,
+    pub radius: f32,
+    pub height: f32,
+    pub material: Rc<dyn Material>
+}
+
+impl Cylinder {
+    pub fn new(base: Point3, axis: Vec3, radius: f32, height: f32, material: Rc<dyn Material>) -> Cylinder {
+        Cylinder { frame: Frame::new(base, axis), radius, height, material }
+    }
+}
+
+// hits of the local ray with the infinite cylinder x^2 + z^2 = radius^2, limited to 0 <= y <= height
+fn cylinder_side_hits(r: &Ray, radius: f32, height: f32) -> impl Iterator<Item = LocalHit> {
+    let (o, d) = (r.origin, r.direction);
+    let a = d.x * d.x + d.z * d.z;
+    let half_b = o.x * d.x + o.z * d.z;
+    let c = o.x * o.x + o.z * o.z - radius * radius;
+    // an owned copy of the ray for the returned iterator
+    let ray = Ray { origin: o, direction: d };
+    solve_quadratic(a, half_b, c).into_iter().filter_map(move |t| {
+        let p = ray.at(t);
+        (p.y >= 0.0 && p.y <= height)
+            .then(|| LocalHit {
+                t,
+                normal: Vec3::new(p.x, 0.0, p.z) / radius,
+                u: azimuth(p),
+                v: p.y / height,
+                dpdu: azimuth_tangent(p),
+                dpdv: Vec3::new(0.0, height, 0.0)
+            })
+    })
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let local = self.frame.ray_to_local(r);
+        let hits = cylinder_side_hits(&local, self.radius, self.height)
+            .chain(cap_hit(&local, 0.0, self.radius, false))
+            .chain(cap_hit(&local, self.height, self.radius, true));
+        closest_hit(hits, r, t_min, t_max, &self.frame, &self.material)
+    }
+}
+
+// a cone with its base disk on `base` and its tip `height` along `axis`
+// u and v work like on the cylinder
+pub struct Cone {
+    pub frame: Frame,
+    pub radius: f32,
+    pub height: f32,
+    pub material: Rc<dyn Material>
+}
+
+impl Cone {
+    pub fn new(base: Point3, axis: Vec3, radius: f32, height: f32, material: Rc<dyn Material>) -> Cone {
+        Cone { frame: Frame::new(base, axis), radius, height, material }
+    }
+}
+
+impl Hittable for Cone {
+    // x^2 + z^2 = (k * (height - y))^2 with k = radius / height, limited to 0 <= y <= height
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let local = self.frame.ray_to_local(r);
+        let (o, d) = (local.origin, local.direction);
+        let k2 = (self.radius / self.height) * (self.radius / self.height);
+        let h = self.height - o.y;
+        let a = d.x * d.x + d.z * d.z - k2 * d.y * d.y;
+        let half_b = o.x * d.x + o.z * d.z + k2 * h * d.y;
+        let c = o.x * o.x + o.z * o.z - k2 * h * h;
+
+        let side = solve_quadratic(a, half_b, c).into_iter().filter_map(|t| {
+            let p = local.at(t);
+            if p.y < 0.0 || p.y > self.height {
+                return None;
+            }
+            // the gradient of the implicit function, it's degenerate right at the tip
+            let normal = Vec3::new(p.x, k2 * (self.height - p.y), p.z);
+            let normal = if normal.near_zero() { Vec3::new(0.0, 1.0, 0.0) } else { normal };
+            // up the side towards the tip
+            let k = self.radius / self.height;
+            let rho = (p.x * p.x + p.z * p.z).sqrt();
+            let dpdv = if rho > 0.0 { self.height * Vec3::new(-k * p.x / rho, 1.0, -k * p.z / rho) } else { Vec3::new(0.0, self.height, 0.0) };
+            Some(LocalHit { t, normal, u: azimuth(p), v: p.y / self.height, dpdu: azimuth_tangent(p), dpdv })
+        });
+        let hits = side.chain(cap_hit(&local, 0.0, self.radius, false));
+        closest_hit(hits, r, t_min, t_max, &self.frame, &self.material)
+    }
+}
+
+// a cylinder with half spheres as caps, from `start` to `end`
+// u goes around the axis and v from the bottom of the start cap to the top of the end cap
+pub struct Capsule {
+    pub frame: Frame,
+    pub radius: f32,
+    // the distance between the centers of the two half spheres
+    pub length: f32,
+    pub material: Rc<dyn Material>
+}
+
+impl Capsule {
+    pub fn new(start: Point3, end: Point3, radius: f32, material: Rc<dyn Material>) -> Capsule {
+        Capsule { frame: Frame::new(start, end - start), radius, length: (end - start).length(), material }
+    }
+
+    // hits of the local ray with the sphere around (0, center_y, 0), keeping the half where keep(y) holds
+    fn cap_hits(&self, r: &Ray, center_y: f32, keep: impl Fn(f32) -> bool) -> Vec<LocalHit> {
+        let oc = r.origin - Vec3::new(0.0, center_y, 0.0);
+        let a = r.direction.length_squared();
+        let half_b = r.direction.dot(oc);
+        let c = oc.length_squared() - self.radius * self.radius;
+        let total = self.length + 2.0 * self.radius;
+        solve_quadratic(a, half_b, c).into_iter().filter_map(|t| {
+            let p = r.at(t);
+            keep(p.y).then(|| {
+                let normal = (p - Vec3::new(0.0, center_y, 0.0)) / self.radius;
+                LocalHit {
+                    t,
+                    normal,
+                    u: azimuth(p),
+                    v: (p.y + self.radius) / total,
+                    dpdu: azimuth_tangent(p),
+                    dpdv: total * project_onto_surface(Vec3::new(0.0, 1.0, 0.0), normal)
+                }
+            })
+        }).collect()
+    }
+}
+
+impl Hittable for Capsule {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let local = self.frame.ray_to_local(r);
+        let total = self.length + 2.0 * self.radius;
+        let side = cylinder_side_hits(&local, self.radius, self.length).map(|hit| LocalHit {
+            v: (hit.v * self.length + self.radius) / total,
+            dpdv: Vec3::new(0.0, total, 0.0),
+            ..hit
+        });
+        let hits = side
+            .chain(self.cap_hits(&local, 0.0, |y| y < 0.0))
+            .chain(self.cap_hits(&local, self.length, |y| y > self.length));
+        closest_hit(hits, r, t_min, t_max, &self.frame, &self.material)
+    }
+}
+
+// a ring around `axis`, the tube of radius `minor_radius` runs at `major_radius` from the center
+// u goes around the axis and v around the tube
+pub struct Torus {
+    pub frame: Frame,
+    pub major_radius: f32,
+    pub minor_radius: f32,
+    pub material: Rc<dyn Material>
+}
+
+impl Torus {
+    pub fn new(center: Point3, axis: Vec3, major_radius: f32, minor_radius: f32, material: Rc<dyn Material>) -> Torus {
+        Torus { frame: Frame::new(center, axis), major_radius, minor_radius, material }
+    }
+}
+
+impl Hittable for Torus {
+    // (|p|^2 + R^2 - r^2)^2 = 4 * R^2 * (x^2 + z^2), a quartic in t
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let local = self.frame.ray_to_local(r);
+        let length = local.direction.length();
+        let d = local.direction / length;
+        // start from the point closest to the center, that keeps the coefficients small
+        let shift = -local.origin.dot(d);
+        let o = local.origin + shift * d;
+
+        let (o, d) = (
+            [o.x as f64, o.y as f64, o.z as f64],
+            [d.x as f64, d.y as f64, d.z as f64]
+        );
+        let major2 = (self.major_radius as f64).powi(2);
+        let minor2 = (self.minor_radius as f64).powi(2);
+        let oo = o[0] * o[0] + o[1] * o[1] + o[2] * o[2];
+        let od = o[0] * d[0] + o[1] * d[1] + o[2] * d[2];
+        let k = oo + major2 - minor2;
+        let coefficients = [
+            1.0,
+            4.0 * od,
+            4.0 * od * od + 2.0 * k - 4.0 * major2 * (d[0] * d[0] + d[2] * d[2]),
+            4.0 * od * k - 8.0 * major2 * (o[0] * d[0] + o[2] * d[2]),
+            k * k - 4.0 * major2 * (o[0] * o[0] + o[2] * o[2])
+        ];
+
+        let hits = solve_quartic(coefficients).into_iter().map(|s| {
+            let t = (s as f32 + shift) / length;
+            let p = local.at(t);
+            let ring = Vec3::new(p.x, 0.0, p.z);
+            let ring_distance = ring.length();
+            // from the closest point on the center circle of the tube to the hit point
+            let center = if ring_distance > 0.0 { ring * (self.major_radius / ring_distance) } else { ring };
+            let normal = p - center;
+            let tube_angle = p.y.atan2(ring_distance - self.major_radius);
+            let dpdu = azimuth_tangent(p);
+            // around the tube, outwards first and then up
+            let dpdv = 2.0 * PI * self.minor_radius * normal.unit_vector().cross(dpdu.unit_vector());
+            LocalHit { t, normal, u: azimuth(p), v: (tube_angle + PI) / (2.0 * PI), dpdu, dpdv }
+        });
+        closest_hit(hits, r, t_min, t_max, &self.frame, &self.material)
+    }
+}
+
+// real roots of c[0] * x^4 + c[1] * x^3 + c[2] * x^2 + c[3] * x + c[4] with Ferrari's method,
+// each root is polished with a few newton steps since the closed form loses precision
+pub fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    let (a, b, cc, d) = (c[1] / c[0], c[2] / c[0], c[3] / c[0], c[4] / c[0]);
+
+    // substitute x = y - a / 4 to get the depressed quartic y^4 + p * y^2 + q * y + r
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = cc - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * cc / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+
+    let mut ys = Vec::new();
+    if q.abs() < 1e-12 {
+        // biquadratic, solve for y^2
+        for z in quadratic_roots(1.0, p, r) {
+            if z >= 0.0 {
+                ys.push(z.sqrt());
+                ys.push(-z.sqrt());
+            }
+        }
+    } else {
+        // m makes both sides of (y^2 + p / 2 + m)^2 = 2m * (y - q / (4m))^2 perfect squares,
+        // with q != 0 the resolvent cubic always has a positive root
+        let m = largest_cubic_root(p, p * p / 4.0 - r, -q * q / 8.0);
+        if m > 0.0 {
+            let s = (2.0 * m).sqrt();
+            ys.extend(quadratic_roots(1.0, -s, p / 2.0 + m + s * q / (4.0 * m)));
+            ys.extend(quadratic_roots(1.0, s, p / 2.0 + m - s * q / (4.0 * m)));
+        }
+    }
+
+    let polynomial = |x: f64| (((c[0] * x + c[1]) * x + c[2]) * x + c[3]) * x + c[4];
+    let derivative = |x: f64| ((4.0 * c[0] * x + 3.0 * c[1]) * x + 2.0 * c[2]) * x + c[3];
+    let mut roots: Vec<f64> = ys.into_iter().map(|y| {
+        let mut x = y - a / 4.0;
+        for _ in 0..4 {
+            let slope = derivative(x);
+            if slope == 0.0 {
+                break;
+            }
+            x -= polynomial(x) / slope;
+        }
+        x
+    }).collect();
+    roots.sort_by(|a, b| a.total_cmp(b));
+    roots
+}
+
+// real roots of a * x^2 + b * x + c
+fn quadratic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+    // avoids the cancellation of -b + sqrt(discriminant) when b is large
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q == 0.0 {
+        return vec![0.0];
+    }
+    vec![q / a, c / q]
+}
+
+// the largest real root of x^3 + a * x^2 + b * x + c
+fn largest_cubic_root(a: f64, b: f64, c: f64) -> f64 {
+    // substitute x = y - a / 3 to get y^3 + p * y + q
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let discriminant = q * q / 4.0 + p * p * p / 27.0;
+    let y = if discriminant >= 0.0 {
+        // one real root (Cardano)
+        let sqrt = discriminant.sqrt();
+        (-q / 2.0 + sqrt).cbrt() + (-q / 2.0 - sqrt).cbrt()
+    } else {
+        // three real roots, the trigonometric form gives the largest one for k = 0
+        let theta = ((3.0 * q / (2.0 * p)) * (-3.0 / p).sqrt()).clamp(-1.0, 1.0).acos();
+        2.0 * (-p / 3.0).sqrt() * (theta / 3.0).cos()
+    };
+    y - a / 3.0
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::material::Lambertian;
+    use crate::shapes::*;
+    use crate::vec3::Color;
+
+    fn material() -> Rc<dyn Material> {
+        Rc::new(Lambertian { albedo: Color::new(0.5, 0.5, 0.5) })
+    }
+
+    fn ray(origin: Point3, direction: Vec3) -> Ray {
+        Ray { origin, direction }
+    }
+
+    fn assert_hit(object: &dyn Hittable, r: &Ray, p: Point3, normal: Vec3) {
+        let rec = object.hit(r, 0.001, f32::MAX).expect("expected a hit");
+        assert!((rec.p - p).length() < 1e-3, "hit at {:?}, expected {:?}", rec.p, p);
+        assert!((rec.normal - normal).length() < 1e-3, "normal {:?}, expected {:?}", rec.normal, normal);
+        assert!(rec.front_face);
+    }
+
+    #[test]
+    fn test_quartic() {
+        // (x - 1)(x + 2)(x - 3)(x - 0.5) = x^4 - 2.5x^3 - 4x^2 + 8.5x - 3
+        let roots = solve_quartic([1.0, -2.5, -4.0, 8.5, -3.0]);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([-2.0, 0.5, 1.0, 3.0]) {
+            assert!((root - expected).abs() < 1e-9, "{:?}", roots);
+        }
+        // x^4 + 1 has no real roots
+        assert!(solve_quartic([1.0, 0.0, 0.0, 0.0, 1.0]).is_empty());
+    }
+
+    #[test]
+    fn test_cylinder_cone_capsule() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let cylinder = Cylinder::new(Point3::new(0.0, 0.0, 0.0), up, 1.0, 2.0, material());
+        assert_hit(&cylinder, &ray(Point3::new(-5.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), Point3::new(-1.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert_hit(&cylinder, &ray(Point3::new(0.5, 5.0, 0.0), -&up), Point3::new(0.5, 2.0, 0.0), up);
+        assert!(cylinder.hit(&ray(Point3::new(-5.0, 3.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), 0.001, f32::MAX).is_none());
+
+        // a cone lying on its side, with the tip at x = 2
+        let cone = Cone::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 1.0, 2.0, material());
+        let normal = Vec3::new(1.0, 0.0, 2.0).unit_vector();
+        assert_hit(&cone, &ray(Point3::new(1.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)), Point3::new(1.0, 0.0, 0.5), normal);
+        assert_hit(&cone, &ray(Point3::new(-5.0, 0.2, 0.0), Vec3::new(1.0, 0.0, 0.0)), Point3::new(0.0, 0.2, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+
+        let capsule = Capsule::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 2.0, 0.0), 0.5, material());
+        assert_hit(&capsule, &ray(Point3::new(0.0, 5.0, 0.0), -&up), Point3::new(0.0, 2.5, 0.0), up);
+        assert_hit(&capsule, &ray(Point3::new(5.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)), Point3::new(0.5, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_torus_disk_plane() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let torus = Torus::new(Point3::new(0.0, 0.0, 0.0), up, 2.0, 0.5, material());
+        assert_hit(&torus, &ray(Point3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), Point3::new(-2.5, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert_hit(&torus, &ray(Point3::new(2.0, 10.0, 0.0), -&up), Point3::new(2.0, 0.5, 0.0), up);
+        // straight through the hole
+        assert!(torus.hit(&ray(Point3::new(0.0, 10.0, 0.0), -&up), 0.001, f32::MAX).is_none());
+
+        let disk = Disk::new(Point3::new(0.0, 1.0, 0.0), up, 1.0, material());
+        assert_hit(&disk, &ray(Point3::new(0.5, 5.0, 0.0), -&up), Point3::new(0.5, 1.0, 0.0), up);
+        assert!(disk.hit(&ray(Point3::new(1.5, 5.0, 0.0), -&up), 0.001, f32::MAX).is_none());
+
+        let plane = Plane::new(Point3::new(0.0, 0.0, 0.0), up, material());
+        let rec = plane.hit(&ray(Point3::new(3.0, 1.0, -2.0), Vec3::new(0.0, -1.0, 1.0)), 0.001, f32::MAX).unwrap();
+        assert!((rec.p - Point3::new(3.0, 0.0, -1.0)).length() < 1e-4);
+        assert!((rec.u - 3.0).abs() < 1e-4 && (rec.v + 1.0).abs() < 1e-4);
+    }
+}
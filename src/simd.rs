@@ -0,0 +1,110 @@
+use std::ops;
+
+use crate::vec3::Vec3;
+
+// 8 floats worked on together, one per lane
+// `std::simd` is still nightly only, so these are plain arrays with lane by lane loops
+// that the compiler turns into sse/avx (or neon) instructions, and into scalar code where there are none
+pub const LANES: usize = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C, align(32))]
+pub struct F32x8(pub [f32; LANES]);
+
+impl F32x8 {
+    pub fn splat(value: f32) -> F32x8 {
+        F32x8([value; LANES])
+    }
+
+    pub fn map(self, f: impl Fn(f32) -> f32) -> F32x8 {
+        F32x8(std::array::from_fn(|i| f(self.0[i])))
+    }
+
+    pub fn zip(self, other: F32x8, f: impl Fn(f32, f32) -> f32) -> F32x8 {
+        F32x8(std::array::from_fn(|i| f(self.0[i], other.0[i])))
+    }
+
+    pub fn sqrt(self) -> F32x8 {
+        self.map(f32::sqrt)
+    }
+
+    pub fn max(self, other: F32x8) -> F32x8 {
+        self.zip(other, f32::max)
+    }
+}
+
+impl ops::Add<F32x8> for F32x8 {
+    type Output = F32x8;
+
+    fn add(self, rhs: F32x8) -> Self::Output {
+        self.zip(rhs, |a, b| a + b)
+    }
+}
+
+impl ops::Sub<F32x8> for F32x8 {
+    type Output = F32x8;
+
+    fn sub(self, rhs: F32x8) -> Self::Output {
+        self.zip(rhs, |a, b| a - b)
+    }
+}
+
+impl ops::Mul<F32x8> for F32x8 {
+    type Output = F32x8;
+
+    fn mul(self, rhs: F32x8) -> Self::Output {
+        self.zip(rhs, |a, b| a * b)
+    }
+}
+
+impl ops::Div<F32x8> for F32x8 {
+    type Output = F32x8;
+
+    fn div(self, rhs: F32x8) -> Self::Output {
+        self.zip(rhs, |a, b| a / b)
+    }
+}
+
+impl ops::Neg for F32x8 {
+    type Output = F32x8;
+
+    fn neg(self) -> Self::Output {
+        self.map(|a| -a)
+    }
+}
+
+// 8 vectors stored as structure of arrays, so every operation works on whole registers
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Vec3x8 {
+    pub x: F32x8,
+    pub y: F32x8,
+    pub z: F32x8
+}
+
+impl Vec3x8 {
+    pub fn splat(v: Vec3) -> Vec3x8 {
+        Vec3x8 { x: F32x8::splat(v.x), y: F32x8::splat(v.y), z: F32x8::splat(v.z) }
+    }
+
+    // lanes past the end of `vectors` are filled with `padding`
+    pub fn gather(vectors: &[Vec3], padding: Vec3) -> Vec3x8 {
+        let lane = |i: usize| vectors.get(i).copied().unwrap_or(padding);
+        Vec3x8 {
+            x: F32x8(std::array::from_fn(|i| lane(i).x)),
+            y: F32x8(std::array::from_fn(|i| lane(i).y)),
+            z: F32x8(std::array::from_fn(|i| lane(i).z))
+        }
+    }
+
+    pub fn dot(self, v: Vec3x8) -> F32x8 {
+        self.x * v.x + self.y * v.y + self.z * v.z
+    }
+}
+
+impl ops::Sub<Vec3x8> for Vec3x8 {
+    type Output = Vec3x8;
+
+    fn sub(self, rhs: Vec3x8) -> Self::Output {
+        Vec3x8 { x: self.x - rhs.x, y: self.y - rhs.y, z: self.z - rhs.z }
+    }
+}
@@ -0,0 +1,260 @@
+use std::ops;
+use std::sync::OnceLock;
+
+use crate::vec3::Color;
+
+// spectral rendering traces a handful of wavelengths per camera ray instead of rgb
+// see "Hero Wavelength Spectral Sampling" (Wilkie et al. 2014)
+pub const LAMBDA_MIN: f32 = 380.0;
+pub const LAMBDA_MAX: f32 = 780.0;
+pub const SPECTRUM_SAMPLES: usize = 4;
+
+// the wavelengths (in nm) carried by one path, the first one is the hero wavelength
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampledWavelengths {
+    pub lambda: [f32; SPECTRUM_SAMPLES],
+    pub pdf: [f32; SPECTRUM_SAMPLES]
+}
+
+// the value of a spectrum at each of the sampled wavelengths
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampledSpectrum {
+    pub values: [f32; SPECTRUM_SAMPLES]
+}
+
+impl SampledWavelengths {
+    // u in [0, 1) picks the hero wavelength, the others are spread evenly over the range from there
+    pub fn sample_uniform(u: f32) -> SampledWavelengths {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.0; SPECTRUM_SAMPLES];
+        lambda[0] = LAMBDA_MIN + u * range;
+        let delta = range / SPECTRUM_SAMPLES as f32;
+        for i in 1..SPECTRUM_SAMPLES {
+            lambda[i] = lambda[i - 1] + delta;
+            if lambda[i] > LAMBDA_MAX {
+                lambda[i] -= range;
+            }
+        }
+        SampledWavelengths { lambda, pdf: [1.0 / range; SPECTRUM_SAMPLES] }
+    }
+
+    pub fn hero(&self) -> f32 {
+        self.lambda[0]
+    }
+
+    // once a path splits by wavelength (dispersion) only the hero wavelength can follow it,
+    // it then stands in for all the dropped wavelengths
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for pdf in self.pdf.iter_mut().skip(1) {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= SPECTRUM_SAMPLES as f32;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|pdf| *pdf == 0.0)
+    }
+}
+
+impl SampledSpectrum {
+    pub fn new(value: f32) -> SampledSpectrum {
+        SampledSpectrum { values: [value; SPECTRUM_SAMPLES] }
+    }
+
+    // turn the spectrum seen along a path into a linear sRGB color
+    pub fn to_rgb(self, wavelengths: &SampledWavelengths) -> Color {
+        let xyz = self.to_xyz(wavelengths);
+        let white = white_rgb();
+        let rgb = xyz_to_linear_srgb(xyz);
+        // white balance, so a flat spectrum of 1 comes out as rgb (1, 1, 1)
+        Color::new(rgb.x / white.x, rgb.y / white.y, rgb.z / white.z)
+    }
+
+    // monte carlo estimate of the integral of the spectrum times the color matching functions
+    fn to_xyz(self, wavelengths: &SampledWavelengths) -> Color {
+        let mut xyz = Color::new_empty();
+        for i in 0..SPECTRUM_SAMPLES {
+            if wavelengths.pdf[i] == 0.0 {
+                continue;
+            }
+            xyz += (self.values[i] / wavelengths.pdf[i]) * cie_xyz(wavelengths.lambda[i]);
+        }
+        xyz / SPECTRUM_SAMPLES as f32
+    }
+}
+
+impl ops::Mul<SampledSpectrum> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, rhs: SampledSpectrum) -> Self::Output {
+        let mut values = self.values;
+        for (value, other) in values.iter_mut().zip(rhs.values.iter()) {
+            *value *= other;
+        }
+        SampledSpectrum { values }
+    }
+}
+
+// rgb to spectrum uplifting for reflectances
+// see "An RGB to Spectrum Conversion for Reflectances" (Smits 1999),
+// the spectra are 10 bins spread evenly from 380nm to 720nm
+const SMITS_WHITE: [f32; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f32; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f32; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f32; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f32; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f32; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f32; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+pub fn uplift(color: &Color, wavelengths: &SampledWavelengths) -> SampledSpectrum {
+    let mut values = [0.0; SPECTRUM_SAMPLES];
+    for (value, lambda) in values.iter_mut().zip(wavelengths.lambda.iter()) {
+        *value = smits(color, *lambda);
+    }
+    SampledSpectrum { values }
+}
+
+// the spectrum is white up to the smallest channel, then the secondary color (cyan, magenta, yellow)
+// up to the middle channel and finally the primary color (red, green, blue) for the largest channel
+fn smits(color: &Color, lambda: f32) -> f32 {
+    let bin = (((lambda - 380.0) / (720.0 - 380.0) * 10.0) as i32).clamp(0, 9) as usize;
+    let (r, g, b) = (color.x, color.y, color.z);
+    if r <= g && r <= b {
+        r * SMITS_WHITE[bin] + if g <= b {
+            (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+        } else {
+            (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+        }
+    } else if g <= r && g <= b {
+        g * SMITS_WHITE[bin] + if r <= b {
+            (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+        } else {
+            (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+        }
+    } else {
+        b * SMITS_WHITE[bin] + if r <= g {
+            (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+        } else {
+            (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+        }
+    }
+}
+
+// CIE 1931 color matching functions, using the multi-lobe gaussian fit from
+// "Simple Analytic Approximations to the CIE XYZ Color Matching Functions" (Wyman et al. 2013)
+pub fn cie_xyz(lambda: f32) -> Color {
+    let g = |mu: f32, sigma_low: f32, sigma_high: f32| {
+        let t = (lambda - mu) / if lambda < mu { sigma_low } else { sigma_high };
+        (-0.5 * t * t).exp()
+    };
+    Color::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8)
+    )
+}
+
+fn xyz_to_linear_srgb(xyz: Color) -> Color {
+    Color::new(
+        3.240_454 * xyz.x - 1.537_138_5 * xyz.y - 0.498_531_4 * xyz.z,
+        -0.969_266 * xyz.x + 1.876_010_8 * xyz.y + 0.041_556 * xyz.z,
+        0.055_643_4 * xyz.x - 0.204_025_9 * xyz.y + 1.057_225_2 * xyz.z
+    )
+}
+
+// the rgb a flat spectrum of 1 integrates to, computed once
+fn white_rgb() -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let steps = 400;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f32;
+        let mut xyz = Color::new_empty();
+        for i in 0..steps {
+            xyz += step * cie_xyz(LAMBDA_MIN + (i as f32 + 0.5) * step);
+        }
+        xyz_to_linear_srgb(xyz)
+    })
+}
+
+// how the index of refraction of a dielectric changes with the wavelength
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dispersion {
+    // n = a + b / lambda^2, lambda in micrometers
+    Cauchy { a: f32, b: f32 },
+    // n^2 = 1 + sum(b_i * lambda^2 / (lambda^2 - c_i)), lambda in micrometers
+    Sellmeier { b: [f32; 3], c: [f32; 3] }
+}
+
+impl Dispersion {
+    // Schott N-BK7, a common optical glass
+    pub fn bk7() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [1.039_612, 0.231_792_34, 1.010_469_5],
+            c: [0.006_000_699, 0.020_017_914, 103.560_65]
+        }
+    }
+
+    pub fn ior(&self, lambda_nm: f32) -> f32 {
+        let l = lambda_nm / 1000.0;
+        let l2 = l * l;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f32 = b.iter().zip(c.iter()).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::spectrum::*;
+
+    // average over many hero wavelengths to get rid of the sampling noise
+    fn round_trip(color: Color) -> Color {
+        let n = 1000;
+        let mut sum = Color::new_empty();
+        for i in 0..n {
+            let wavelengths = SampledWavelengths::sample_uniform((i as f32 + 0.5) / n as f32);
+            sum += uplift(&color, &wavelengths).to_rgb(&wavelengths);
+        }
+        sum / n as f32
+    }
+
+    #[test]
+    fn test_white_round_trip() {
+        let white = round_trip(Color::new(1.0, 1.0, 1.0));
+        assert!((white - Color::new(1.0, 1.0, 1.0)).length() < 0.01, "{:?}", white);
+    }
+
+    #[test]
+    fn test_red_stays_red() {
+        let red = round_trip(Color::new(0.8, 0.1, 0.1));
+        assert!(red.x > 0.5 && red.y < 0.25 && red.z < 0.25, "{:?}", red);
+    }
+
+    #[test]
+    fn test_dispersion_is_stronger_for_blue() {
+        for dispersion in [Dispersion::bk7(), Dispersion::Cauchy { a: 1.5046, b: 0.0042 }] {
+            let blue = dispersion.ior(450.0);
+            let red = dispersion.ior(650.0);
+            assert!(blue > red);
+            assert!((red - 1.51).abs() < 0.02, "{:?} gave {}", dispersion, red);
+        }
+    }
+
+    #[test]
+    fn test_terminate_secondary_keeps_hero() {
+        let mut wavelengths = SampledWavelengths::sample_uniform(0.3);
+        let pdf = wavelengths.pdf[0];
+        wavelengths.terminate_secondary();
+        wavelengths.terminate_secondary();
+        assert_eq!(wavelengths.pdf[0], pdf / SPECTRUM_SAMPLES as f32);
+        assert!(wavelengths.secondary_terminated());
+    }
+}
@@ -1,15 +1,29 @@
+use std::f32::consts::PI;
 use std::rc::Rc;
 
 use crate::material::Material;
 use crate::vec3::*;
 use crate::hittable::*;
+use crate::ray::Ray;
 
+#[derive(Clone)]
 pub struct Sphere {
     pub center: Point3,
     pub radius: f32,
     pub material: Rc<dyn Material>
 }
 
+impl Sphere {
+    // dp/du and dp/dv for the uv mapping of `sphere_uv`, u is the angle phi / 2pi and v is theta / pi
+    fn derivatives(&self, n: &Vec3) -> (Vec3, Vec3) {
+        let dpdu = 2.0 * PI * self.radius * Vec3::new(n.z, 0.0, -n.x);
+        // sin(theta) is 0 at the poles, where u isn't defined
+        let sin_theta = (1.0 - n.y * n.y).max(0.0).sqrt().max(1e-6);
+        let dpdv = PI * self.radius * Vec3::new(-n.x * n.y / sin_theta, sin_theta, -n.z * n.y / sin_theta);
+        (dpdu, dpdv)
+    }
+}
+
 impl Hittable for Sphere {
 
     // t^2 * b^2 + 2 * t * b * (A - C) + (A - C)^2 - r ^ 2 = 0
@@ -21,7 +35,7 @@ impl Hittable for Sphere {
     // the formula to solve this equation is generally (-b +- sqrt(b^2 - 4ac)) / (2a)
     // subtitute b with 2h
     // we can get (-h +- sqrt(h^2 - ac)) / a
-    fn hit(&self, r: &crate::ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
         let a_sub_c = r.origin - self.center;
         let a = r.direction.dot(r.direction);
         let half_b = r.direction.dot(a_sub_c);
@@ -45,7 +59,44 @@ impl Hittable for Sphere {
         let mut rec = HitRecord::new(&r.at(root), root, Rc::clone(&self.material));
         let outward_normal = (rec.p - self.center) / self.radius;
         rec.set_face_normal(r, &outward_normal);
+        (rec.u, rec.v) = sphere_uv(&outward_normal);
+        (rec.dpdu, rec.dpdv) = self.derivatives(&outward_normal);
 
         Some(rec)
     }
-}
\ No newline at end of file
+
+    // both roots of the equation above, without any range check
+    fn intervals(&self, r: &Ray) -> Vec<Interval> {
+        let a_sub_c = r.origin - self.center;
+        let a = r.direction.dot(r.direction);
+        let half_b = r.direction.dot(a_sub_c);
+        let c = a_sub_c.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+
+        if discriminant <= 0.0 {
+            return Vec::new();
+        }
+
+        let discriminant_sqrt = discriminant.sqrt();
+        let record = |t: f32, front_face: bool| {
+            let mut rec = HitRecord::new(&r.at(t), t, Rc::clone(&self.material));
+            let outward_normal = (rec.p - self.center) / self.radius;
+            rec.set_outward_normal(&outward_normal);
+            rec.front_face = front_face;
+            (rec.u, rec.v) = sphere_uv(&outward_normal);
+            (rec.dpdu, rec.dpdv) = self.derivatives(&outward_normal);
+            rec
+        };
+        vec![Interval {
+            enter: record((-half_b - discriminant_sqrt) / a, true),
+            exit: record((-half_b + discriminant_sqrt) / a, false)
+        }]
+    }
+}
+
+// u goes around the y axis starting from -x, v from the bottom (-y) to the top (+y)
+pub fn sphere_uv(outward_normal: &Vec3) -> (f32, f32) {
+    let theta = (-outward_normal.y).clamp(-1.0, 1.0).acos();
+    let phi = (-outward_normal.z).atan2(outward_normal.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
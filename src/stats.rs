@@ -0,0 +1,171 @@
+use std::cell::Cell;
+use std::ops;
+use std::time::Duration;
+
+// what a render did, counted while it runs
+// the counters are per thread, `render` resets them at the start and hands them out at the end
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Counters {
+    // rays starting at the camera, one per sample
+    pub camera_rays: u64,
+    // every ray traced through the scene for its color, the camera rays included
+    pub rays: u64,
+    // rays that only check whether the way to the sky is free (for the direct light pass)
+    pub shadow_rays: u64,
+    // ray against object tests, every object of a list and every sphere of a sphere set counts once
+    pub intersection_tests: u64
+}
+
+impl Counters {
+    pub fn bounce_rays(&self) -> u64 {
+        self.rays - self.camera_rays
+    }
+
+    // segments per path, a path that leaves the scene right away has length 1
+    pub fn average_path_length(&self) -> f64 {
+        self.rays as f64 / self.camera_rays.max(1) as f64
+    }
+
+    pub fn tests_per_ray(&self) -> f64 {
+        self.intersection_tests as f64 / (self.rays + self.shadow_rays).max(1) as f64
+    }
+}
+
+impl ops::AddAssign<Counters> for Counters {
+    fn add_assign(&mut self, rhs: Counters) {
+        self.camera_rays += rhs.camera_rays;
+        self.rays += rhs.rays;
+        self.shadow_rays += rhs.shadow_rays;
+        self.intersection_tests += rhs.intersection_tests;
+    }
+}
+
+thread_local! {
+    static COUNTERS: Cell<Counters> = const { Cell::new(Counters { camera_rays: 0, rays: 0, shadow_rays: 0, intersection_tests: 0 }) };
+}
+
+// e.g. `stats::count(|c| c.rays += 1)`
+pub fn count(f: impl FnOnce(&mut Counters)) {
+    COUNTERS.with(|counters| {
+        let mut value = counters.get();
+        f(&mut value);
+        counters.set(value);
+    });
+}
+
+// the counts so far, starting again from 0
+pub fn take() -> Counters {
+    COUNTERS.with(|counters| counters.replace(Counters::default()))
+}
+
+// the end of render report: the counters and how long each phase took
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub counters: Counters,
+    // in the order they first ran, a phase that runs again (once per frame) adds up
+    pub phases: Vec<(String, Duration)>,
+    pub wall_time: Duration,
+    // the samples every pixel got, the fewest of any frame, when it's known
+    pub samples_per_pixel: Option<i32>
+}
+
+impl Report {
+    pub fn add_phase(&mut self, name: &str, duration: Duration) {
+        match self.phases.iter_mut().find(|(phase, _)| phase == name) {
+            Some((_, total)) => *total += duration,
+            None => self.phases.push((name.to_string(), duration))
+        }
+    }
+
+    pub fn add_samples_per_pixel(&mut self, samples: i32) {
+        self.samples_per_pixel = Some(self.samples_per_pixel.map_or(samples, |fewest| fewest.min(samples)));
+    }
+
+    pub fn phase(&self, name: &str) -> Option<Duration> {
+        self.phases.iter().find(|(phase, _)| phase == name).map(|(_, duration)| *duration)
+    }
+
+    // all rays per second of the render phase
+    pub fn rays_per_second(&self) -> f64 {
+        let render = self.phase("render").unwrap_or(self.wall_time).as_secs_f64();
+        (self.counters.rays + self.counters.shadow_rays) as f64 / render.max(1e-9)
+    }
+
+    // human readable, one value per line
+    pub fn to_text(&self) -> String {
+        let c = &self.counters;
+        let mut text = format!("wall time            {:.3} s\n", self.wall_time.as_secs_f64());
+        for (name, duration) in self.phases.iter() {
+            text += &format!("  {:<18} {:.3} s\n", name, duration.as_secs_f64());
+        }
+        if let Some(samples) = self.samples_per_pixel {
+            text += &format!("samples per pixel    {}\n", samples);
+        }
+        text += &format!("camera rays          {}\n", c.camera_rays);
+        text += &format!("bounce rays          {}\n", c.bounce_rays());
+        text += &format!("shadow rays          {}\n", c.shadow_rays);
+        text += &format!("average path length  {:.2}\n", c.average_path_length());
+        text += &format!("tests per ray        {:.1}\n", c.tests_per_ray());
+        text += &format!("rays per second      {:.0}\n", self.rays_per_second());
+        text
+    }
+
+    // a single json object, times in seconds
+    pub fn to_json(&self) -> String {
+        let c = &self.counters;
+        let phases: Vec<String> = self.phases.iter()
+            .map(|(name, duration)| format!("\"{}\": {}", name.replace('\\', "\\\\").replace('"', "\\\""), duration.as_secs_f64()))
+            .collect();
+        let samples = self.samples_per_pixel.map_or(String::new(), |samples| format!("\"samples_per_pixel\": {}, ", samples));
+        format!(
+            "{{\"wall_time\": {}, \"phases\": {{{}}}, {}\"camera_rays\": {}, \"bounce_rays\": {}, \"shadow_rays\": {}, \
+             \"intersection_tests\": {}, \"average_path_length\": {}, \"tests_per_ray\": {}, \"rays_per_second\": {}}}",
+            self.wall_time.as_secs_f64(),
+            phases.join(", "),
+            samples,
+            c.camera_rays,
+            c.bounce_rays(),
+            c.shadow_rays,
+            c.intersection_tests,
+            c.average_path_length(),
+            c.tests_per_ray(),
+            self.rays_per_second()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::stats::*;
+
+    #[test]
+    fn test_report() {
+        take();
+        count(|c| c.camera_rays += 2);
+        count(|c| c.rays += 5);
+        count(|c| c.intersection_tests += 50);
+        let mut report = Report { counters: take(), ..Report::default() };
+        assert_eq!(take(), Counters::default());
+        assert_eq!(report.counters.bounce_rays(), 3);
+        assert_eq!(report.counters.average_path_length(), 2.5);
+        assert_eq!(report.counters.tests_per_ray(), 10.0);
+
+        report.add_phase("render", Duration::from_millis(500));
+        report.add_phase("encode", Duration::from_millis(20));
+        report.add_phase("render", Duration::from_millis(500));
+        assert_eq!(report.phase("render"), Some(Duration::from_secs(1)));
+        assert_eq!(report.rays_per_second(), 5.0);
+        assert_eq!(
+            report.to_json(),
+            "{\"wall_time\": 0, \"phases\": {\"render\": 1, \"encode\": 0.02}, \"camera_rays\": 2, \"bounce_rays\": 3, \
+             \"shadow_rays\": 0, \"intersection_tests\": 50, \"average_path_length\": 2.5, \"tests_per_ray\": 10, \"rays_per_second\": 5}"
+        );
+
+        report.add_samples_per_pixel(40);
+        report.add_samples_per_pixel(37);
+        assert_eq!(report.samples_per_pixel, Some(37));
+        assert!(report.to_json().contains("\"encode\": 0.02}, \"samples_per_pixel\": 37, \"camera_rays\": 2"));
+        assert!(report.to_text().contains("samples per pixel    37\n"));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::framebuffer::Framebuffer;
+use crate::hittable::HitRecord;
+use crate::vec3::{Color, Vec3};
+
+// an image looked up by the (u, v) of a hit, repeating outside of [0, 1]
+// v = 0 is the bottom row of the image, the values are used as they are (no color space conversion)
+#[derive(Clone)]
+pub struct ImageTexture {
+    pub image: Framebuffer
+}
+
+impl ImageTexture {
+    pub fn new(image: Framebuffer) -> ImageTexture {
+        ImageTexture { image }
+    }
+
+    // bilinear interpolation between the 4 closest pixels
+    pub fn sample(&self, u: f32, v: f32) -> Color {
+        let (width, height) = (self.image.width, self.image.height);
+        let x = u.rem_euclid(1.0) * width as f32 - 0.5;
+        let y = (1.0 - v.rem_euclid(1.0)) * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let pixel = |x: f32, y: f32| {
+            self.image.get((x as i64).rem_euclid(width as i64) as usize, (y as i64).rem_euclid(height as i64) as usize)
+        };
+        let top = (1.0 - fx) * pixel(x0, y0) + fx * pixel(x0 + 1.0, y0);
+        let bottom = (1.0 - fx) * pixel(x0, y0 + 1.0) + fx * pixel(x0 + 1.0, y0 + 1.0);
+        (1.0 - fy) * top + fy * bottom
+    }
+
+    // the average of the channels, for grayscale maps
+    pub fn sample_mono(&self, u: f32, v: f32) -> f32 {
+        let c = self.sample(u, v);
+        (c.x + c.y + c.z) / 3.0
+    }
+}
+
+// surface detail that tilts the shading normal without changing the geometry
+#[derive(Clone)]
+pub enum SurfaceMap {
+    // a tangent space normal map, rgb in [0, 1] stands for xyz in [-1, 1] with z along the normal
+    // strength scales the tilt, 1 uses the map as it is
+    Normal { texture: ImageTexture, strength: f32 },
+    // a grayscale height map, scale is the height of a white pixel relative to the size of the texture
+    Bump { texture: ImageTexture, scale: f32 }
+}
+
+impl SurfaceMap {
+    // the shading normal at the hit, facing the same side as `rec.normal`
+    pub fn perturb(&self, rec: &HitRecord) -> Vec3 {
+        let (tangent, bitangent) = rec.tangent_frame();
+        let n = rec.normal;
+        let perturbed = match self {
+            SurfaceMap::Normal { texture, strength } => {
+                let c = texture.sample(rec.u, rec.v);
+                let local = Vec3::new((2.0 * c.x - 1.0) * strength, (2.0 * c.y - 1.0) * strength, 2.0 * c.z - 1.0);
+                local.x * tangent + local.y * bitangent + local.z.max(1e-3) * n
+            },
+            SurfaceMap::Bump { texture, scale } => {
+                // central differences one pixel apart
+                let du = 1.0 / texture.image.width as f32;
+                let dv = 1.0 / texture.image.height as f32;
+                let dh_du = (texture.sample_mono(rec.u + du, rec.v) - texture.sample_mono(rec.u - du, rec.v)) / (2.0 * du);
+                let dh_dv = (texture.sample_mono(rec.u, rec.v + dv) - texture.sample_mono(rec.u, rec.v - dv)) / (2.0 * dv);
+                n - *scale * (dh_du * tangent + dh_dv * bitangent)
+            }
+        };
+        let perturbed = perturbed.unit_vector();
+        // a shading normal facing away from the ray would let light through the surface
+        if perturbed.dot(rec.geometric_normal) <= 0.0 { n } else { perturbed }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::rc::Rc;
+
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::texture::*;
+    use crate::vec3::Point3;
+
+    fn flat(color: Color) -> ImageTexture {
+        let mut image = Framebuffer::new(4, 4);
+        for pixel in image.pixels.iter_mut() {
+            *pixel = color;
+        }
+        ImageTexture::new(image)
+    }
+
+    fn sphere_hit(direction: Vec3) -> HitRecord {
+        let sphere = Sphere { center: Point3::new(0.0, 0.0, 0.0), radius: 2.0, material: Rc::new(Lambertian { albedo: Color::new(0.5, 0.5, 0.5) }) };
+        sphere.hit(&Ray { origin: Point3::new(0.0, 0.0, 0.0) - 10.0 * direction, direction }, 0.001, f32::MAX).unwrap()
+    }
+
+    #[test]
+    fn test_sphere_tangents() {
+        let rec = sphere_hit(Vec3::new(-0.3, -0.2, -1.0).unit_vector());
+        assert!(rec.dpdu.dot(rec.normal).abs() < 1e-4 && rec.dpdv.dot(rec.normal).abs() < 1e-4);
+
+        // moving a little along u and v on the sphere matches dp/du and dp/dv
+        let step = 1e-3;
+        let from_uv = |u: f32, v: f32| {
+            let (phi, theta) = (u * 2.0 * std::f32::consts::PI, v * std::f32::consts::PI);
+            2.0 * Vec3::new(-phi.cos() * theta.sin(), -theta.cos(), phi.sin() * theta.sin())
+        };
+        assert!((from_uv(rec.u, rec.v) - rec.p).length() < 1e-3);
+        let dpdu = (from_uv(rec.u + step, rec.v) - from_uv(rec.u - step, rec.v)) / (2.0 * step);
+        let dpdv = (from_uv(rec.u, rec.v + step) - from_uv(rec.u, rec.v - step)) / (2.0 * step);
+        assert!((dpdu - rec.dpdu).length() < 1e-2 * rec.dpdu.length(), "{:?} != {:?}", dpdu, rec.dpdu);
+        assert!((dpdv - rec.dpdv).length() < 1e-2 * rec.dpdv.length(), "{:?} != {:?}", dpdv, rec.dpdv);
+    }
+
+    #[test]
+    fn test_surface_maps() {
+        let rec = sphere_hit(Vec3::new(0.2, 0.1, -1.0).unit_vector());
+        let (tangent, bitangent) = rec.tangent_frame();
+
+        // a flat normal map and a flat height map leave the normal alone
+        let flat_normal = SurfaceMap::Normal { texture: flat(Color::new(0.5, 0.5, 1.0)), strength: 1.0 };
+        assert!((flat_normal.perturb(&rec) - rec.normal).length() < 1e-4);
+        let flat_bump = SurfaceMap::Bump { texture: flat(Color::new(0.3, 0.3, 0.3)), scale: 1.0 };
+        assert!((flat_bump.perturb(&rec) - rec.normal).length() < 1e-4);
+
+        // a normal map pointing along +x in tangent space tilts the normal towards the tangent
+        let tilted = SurfaceMap::Normal { texture: flat(Color::new(1.0, 0.5, 1.0)), strength: 1.0 }.perturb(&rec);
+        assert!((tilted - (tangent + rec.normal).unit_vector()).length() < 1e-4);
+        assert!(tilted.dot(bitangent).abs() < 1e-4);
+    }
+}
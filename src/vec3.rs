@@ -1,8 +1,11 @@
 use std::ops;
 
-use rand::Rng;
+use crate::rng;
 
+// the element wise operations below go through 4 lanes (see `zip`) so the compiler can use sse/neon instructions for them.
+// it's not aligned to 16 bytes: that makes every ray and hit record bigger and was slower in `cargo bench --bench renderer`
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -21,34 +24,58 @@ impl Vec3 {
         Vec3 {x: e1, y: e2, z: e3}
     }
 
+    // the 4th lane is only there to fill the register
+    #[inline(always)]
+    fn lanes(self) -> [f32; 4] {
+        [self.x, self.y, self.z, 0.0]
+    }
+
+    #[inline(always)]
+    fn zip(self, other: Vec3, f: impl Fn(f32, f32) -> f32) -> Vec3 {
+        let (a, b) = (self.lanes(), other.lanes());
+        let lanes: [f32; 4] = std::array::from_fn(|i| f(a[i], b[i]));
+        Vec3 {x: lanes[0], y: lanes[1], z: lanes[2]}
+    }
+
+    #[inline(always)]
+    fn map(self, f: impl Fn(f32) -> f32) -> Vec3 {
+        let a = self.lanes();
+        let lanes: [f32; 4] = std::array::from_fn(|i| f(a[i]));
+        Vec3 {x: lanes[0], y: lanes[1], z: lanes[2]}
+    }
+
+    #[inline]
     pub fn length_squared(self: Vec3) -> f32 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
+    #[inline]
     pub fn length(self: Vec3) -> f32 {
         self.length_squared().sqrt()
     }
 
+    #[inline]
     pub fn dot(self: Vec3, v: Vec3) -> f32 {
         self.x * v.x + self.y * v.y + self.z * v.z
     }
 
     // The cross product of two vectors is the third vector that is perpendicular to the two original vectors
+    #[inline]
     pub fn cross(self: Vec3, v: Vec3) -> Vec3 {
         Vec3 { x: self.y * v.z - self.z * v.y, y: self.z * v.x - self.x * v.z, z: self.x * v.y - self.y * v.x }
     }
 
+    #[inline]
     pub fn unit_vector(self: Vec3) -> Vec3 {
         self / self.length()
     }
 
     pub fn random() -> Vec3 {
-        Vec3 { x: rand::random::<f32>(), y: rand::random::<f32>(), z: rand::random::<f32>() }
+        Vec3 { x: rng::random::<f32>(), y: rng::random::<f32>(), z: rng::random::<f32>() }
     }
 
     pub fn random_range(min: f32, max: f32) -> Vec3 {
-        let mut rng = rand::thread_rng();
-        Vec3 { x: rng.gen_range(min..max), y: rng.gen_range(min..max), z: rng.gen_range(min..max) }
+        Vec3 { x: rng::gen_range(min..max), y: rng::gen_range(min..max), z: rng::gen_range(min..max) }
     }
 
     pub fn random_in_unit_sphere() -> Vec3 {
@@ -83,9 +110,8 @@ impl Vec3 {
     }
 
     pub fn random_in_unit_disk() -> Vec3 {
-        let mut rng = rand::thread_rng();
         loop {
-            let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+            let p = Vec3::new(rng::gen_range(-1.0..1.0), rng::gen_range(-1.0..1.0), 0.0);
             if p.length_squared() >= 1.0 {
                 continue;
             }
@@ -101,11 +127,12 @@ impl Default for Vec3 {
 }
 
 // this is for &v1 + &v2
-impl<'a, 'b> ops::Add<&'b Vec3> for &'a Vec3 {
+impl<'b> ops::Add<&'b Vec3> for &Vec3 {
     type Output = Vec3;
 
+    #[inline]
     fn add(self, rhs: &'b Vec3) -> Self::Output {
-        Vec3 {x: self.x + rhs.x, y: self.y + rhs.y, z: self.z + rhs.z}
+        self.zip(*rhs, |a, b| a + b)
     }
 }
 
@@ -113,47 +140,49 @@ impl<'a, 'b> ops::Add<&'b Vec3> for &'a Vec3 {
 impl ops::Add<Vec3> for Vec3 {
     type Output = Vec3;
 
+    #[inline]
     fn add(self, rhs: Vec3) -> Self::Output {
-        Vec3 {x: self.x + rhs.x, y: self.y + rhs.y, z: self.z + rhs.z}
+        self.zip(rhs, |a, b| a + b)
     }
 }
 
 impl ops::AddAssign<Vec3> for Vec3 {
+    #[inline]
     fn add_assign(&mut self, rhs: Vec3) {
-        self.x += rhs.x;
-        self.y += rhs.y;
-        self.z += rhs.z;
+        *self = *self + rhs;
     }
 }
 
 impl ops::Sub<Vec3> for Vec3 {
     type Output = Vec3;
 
+    #[inline]
     fn sub(self, rhs: Vec3) -> Self::Output {
-        Vec3 {x: self.x - rhs.x, y: self.y - rhs.y, z: self.z - rhs.z}
+        self.zip(rhs, |a, b| a - b)
     }
 }
 
 // this is for &v1 + &v2
-impl<'a, 'b> ops::Sub<&'b Vec3> for &'a Vec3 {
+impl<'b> ops::Sub<&'b Vec3> for &Vec3 {
     type Output = Vec3;
 
+    #[inline]
     fn sub(self, rhs: &'b Vec3) -> Self::Output {
-        Vec3 {x: self.x - rhs.x, y: self.y - rhs.y, z: self.z - rhs.z}
+        self.zip(*rhs, |a, b| a - b)
     }
 }
 
 impl ops::SubAssign<Vec3> for Vec3 {
+    #[inline]
     fn sub_assign(&mut self, rhs: Vec3) {
-        self.x -= rhs.x;
-        self.y -= rhs.y;
-        self.z -= rhs.z;
+        *self = *self - rhs;
     }
 }
 
 impl ops::Neg for &Vec3 {
+    #[inline]
     fn neg(self) -> Self::Output {
-        Vec3 {x: -self.x, y: -self.y, z: -self.z}
+        self.map(|a| -a)
     }
 
     type Output = Vec3;
@@ -162,44 +191,50 @@ impl ops::Neg for &Vec3 {
 impl ops::Mul<Vec3> for Vec3 {
     type Output = Vec3;
 
+    #[inline]
     fn mul(self, rhs: Vec3) -> Self::Output {
-        Vec3 {x: self.x * rhs.x, y: self.y * rhs.y, z: self.z * rhs.z}
+        self.zip(rhs, |a, b| a * b)
     }
 }
 
 impl ops::Mul<f32> for Vec3 {
     type Output = Vec3;
 
+    #[inline]
     fn mul(self, rhs: f32) -> Self::Output {
-        Vec3 {x: self.x * rhs, y: self.y * rhs, z: self.z * rhs}
+        self.map(|a| a * rhs)
     }
 }
 
 impl ops::Mul<Vec3> for f32 {
     type Output = Vec3;
 
+    #[inline]
     fn mul(self, rhs: Vec3) -> Self::Output {
-        Vec3 {x: self * rhs.x, y: self * rhs.y, z: self * rhs.z}
+        rhs.map(|a| self * a)
     }
 }
 
 impl ops::Mul<f32> for &Vec3 {
     type Output = Vec3;
 
+    #[inline]
     fn mul(self, rhs: f32) -> Self::Output {
-        Vec3 {x: self.x * rhs, y: self.y * rhs, z: self.z * rhs}
+        self.map(|a| a * rhs)
     }
 }
 
 impl ops::Mul<&Vec3> for f32 {
     type Output = Vec3;
 
+    #[inline]
     fn mul(self, rhs: &Vec3) -> Self::Output {
-        Vec3 {x: self * rhs.x, y: self * rhs.y, z: self * rhs.z}
+        rhs.map(|a| self * a)
     }
 }
 
 impl ops::MulAssign<f32> for Vec3 {
+    #[inline]
     fn mul_assign(&mut self, rhs: f32) {
         self.x *= rhs;
         self.y *= rhs;
@@ -210,12 +245,14 @@ impl ops::MulAssign<f32> for Vec3 {
 impl ops::Div<f32> for Vec3 {
     type Output = Vec3;
 
+    #[inline]
     fn div(self, rhs: f32) -> Self::Output {
-        Vec3 {x: 1.0 / rhs * self.x, y: 1.0 / rhs * self.y, z: 1.0 / rhs * self.z}
+        self.map(|a| 1.0 / rhs * a)
     }
 }
 
 impl ops::DivAssign<f32> for Vec3 {
+    #[inline]
     fn div_assign(&mut self, rhs: f32) {
         self.x /= rhs;
         self.y /= rhs;
@@ -239,6 +276,7 @@ mod tests {
     use crate::Vec3;
 
     #[test]
+    #[allow(clippy::op_ref)]
     fn test_vec3_add() {
         let v1: Vec3 = Vec3::new(2.0, 2.0, 2.0);
         let v2: Vec3 = Vec3::new(1.0, 2.0, 3.0);
@@ -308,4 +346,4 @@ mod tests {
         v1 /= 2.0;
         assert_eq!(v1, Vec3::new(1.0, 1.0, 1.0));
     }
-}
\ No newline at end of file
+}
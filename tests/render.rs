@@ -0,0 +1,241 @@
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use ray_tracing_rust::cancel::CancellationToken;
+use ray_tracing_rust::color_utils::OutputTransform;
+use ray_tracing_rust::filter::{Filter, FilterKind};
+use ray_tracing_rust::framebuffer::{Framebuffer, Region};
+use ray_tracing_rust::image_io;
+use ray_tracing_rust::progress::{Progress, Quiet};
+use ray_tracing_rust::material::{Dielectric, Lambertian, Metal};
+use ray_tracing_rust::sphere::Sphere;
+use ray_tracing_rust::{render, Camera, Color, HittableList, Point3, RenderSettings, Vec3};
+
+fn small_scene() -> HittableList {
+    let mut world = HittableList::new();
+    world.add(Rc::new(Sphere { center: Point3::new(0.0, -100.5, -1.0), radius: 100.0,
+        material: Rc::new(Lambertian { albedo: Color::new(0.8, 0.8, 0.0) }) }));
+    world.add(Rc::new(Sphere { center: Point3::new(0.0, 0.0, -1.0), radius: 0.5,
+        material: Rc::new(Lambertian { albedo: Color::new(0.1, 0.2, 0.5) }) }));
+    world.add(Rc::new(Sphere { center: Point3::new(-1.0, 0.0, -1.0), radius: 0.5,
+        material: Rc::new(Dielectric { ir: 1.5, dispersion: None }) }));
+    world.add(Rc::new(Sphere { center: Point3::new(1.0, 0.0, -1.0), radius: 0.5,
+        material: Rc::new(Metal { albedo: Color::new(0.8, 0.6, 0.2), fuzz: 0.0 }) }));
+    world
+}
+
+fn small_camera() -> Camera {
+    Camera::new(Point3::new(0.0, 0.0, 1.0), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0),
+        60.0, 2.0, 0.0, 2.0)
+}
+
+fn settings(collect_aovs: bool) -> RenderSettings {
+    RenderSettings {
+        image_width: 20,
+        image_height: 10,
+        samples_per_pixel: 4,
+        max_depth: 10,
+        collect_aovs,
+        ..Default::default()
+    }
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ray-tracing-rust-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+#[test]
+fn test_render_fills_framebuffer() {
+    let output = render(&small_camera(), &small_scene(), &settings(false));
+
+    assert_eq!(output.beauty.width, 20);
+    assert_eq!(output.beauty.height, 10);
+    assert_eq!(output.beauty.pixels.len(), 200);
+    assert!(output.aovs.is_none());
+    for pixel in output.beauty.pixels.iter() {
+        assert!(pixel.x.is_finite() && pixel.y.is_finite() && pixel.z.is_finite());
+        assert!(pixel.x >= 0.0 && pixel.y >= 0.0 && pixel.z >= 0.0);
+    }
+    // the top row only sees the sky, which is brighter in blue
+    let sky = output.beauty.get(10, 0);
+    assert!(sky.z > sky.x);
+}
+
+#[test]
+fn test_camera_without_the_trait() {
+    // `CameraModel` isn't imported here, the camera's own methods still work
+    let camera = small_camera();
+    let ray = camera.get_ray(0.5, 0.5);
+    assert!((ray.direction.unit_vector() - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-5);
+    assert!((camera.depth(&Point3::new(0.3, 0.2, -1.0)) - 2.0).abs() < 1e-5);
+}
+
+#[test]
+fn test_render_collects_aovs() {
+    let output = render(&small_camera(), &small_scene(), &settings(true));
+    let aovs = output.aovs.expect("aovs were requested");
+
+    // the blue diffuse sphere, object 1 in the list, is in the middle of the image
+    // with an even width that's the corner of two pixels, for (9, 5) u goes from 9 / 19 to 10 / 19 around its center
+    let (x, y) = (9, 5);
+    assert_eq!(aovs.object_id.get(x, y).x, 2.0);
+    assert!((aovs.albedo.get(x, y) - Color::new(0.1, 0.2, 0.5)).length() < 1e-3);
+    assert!((aovs.depth.get(x, y).x - 1.5).abs() < 0.1);
+    // every sample hits it close to its center, so the normal points right back
+    assert!(aovs.normal.get(x, y).z > 0.95, "{:?}", aovs.normal.get(x, y));
+    // the top row is background
+    assert_eq!(aovs.object_id.get(10, 0).x, 0.0);
+    assert_eq!(aovs.depth.get(10, 0).x, f32::INFINITY);
+}
+
+#[test]
+fn test_negative_lobe_filters_stay_in_range() {
+    // one sample per pixel leaves some pixels, and the edges of the region, with little weight after the negative lobes
+    for kind in [FilterKind::Mitchell, FilterKind::Lanczos] {
+        let region = Region { x: 3, y: 2, width: 9, height: 5 };
+        let settings = RenderSettings { samples_per_pixel: 1, filter: Filter::new(kind), region: Some(region), ..settings(false) };
+        for seed in 0..8 {
+            let output = render(&small_camera(), &small_scene(), &RenderSettings { seed: Some(seed), ..settings });
+            for pixel in output.beauty.pixels.iter() {
+                assert!([pixel.x, pixel.y, pixel.z].iter().all(|c| c.is_finite() && (0.0..8.0).contains(c)), "{:?} {:?}", kind, pixel);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_write_images() {
+    let output = render(&small_camera(), &small_scene(), &settings(true));
+    let aovs = output.aovs.unwrap();
+
+    let ppm = temp_path("image.ppm");
+    image_io::write_ppm(ppm.to_str().unwrap(), &output.beauty, &OutputTransform::default()).unwrap();
+    let text = fs::read_to_string(&ppm).unwrap();
+    assert!(text.starts_with("P3\n20 10\n255\n"));
+    assert_eq!(text.lines().count(), 3 + 200);
+
+    // reading the ppm back gives the encoded (not the linear) values
+    let read_back = image_io::read_pnm(ppm.to_str().unwrap()).unwrap();
+    assert_eq!((read_back.width, read_back.height), (20, 10));
+    let encoded = OutputTransform::default().apply(&output.beauty.get(3, 7));
+    assert!((read_back.get(3, 7) - encoded).length() < 0.01);
+
+    let pfm = temp_path("image.pfm");
+    image_io::write_pfm(pfm.to_str().unwrap(), &output.beauty).unwrap();
+    let bytes = fs::read(&pfm).unwrap();
+    assert!(bytes.starts_with(b"PF\n20 10\n-1.0\n"));
+    assert_eq!(bytes.len(), "PF\n20 10\n-1.0\n".len() + 200 * 3 * 4);
+    // pfm is lossless
+    assert_eq!(image_io::read_pfm(pfm.to_str().unwrap()).unwrap().pixels, output.beauty.pixels);
+
+    let png = temp_path("image.png");
+    image_io::write_png(png.to_str().unwrap(), &output.beauty, &OutputTransform::default()).unwrap();
+    let bytes = fs::read(&png).unwrap();
+    assert!(bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]));
+    assert_eq!(&bytes[12..16], b"IHDR");
+    assert_eq!(&bytes[16..24], &[0, 0, 0, 20, 0, 0, 0, 10]);
+    assert!(bytes.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+
+    let exr = temp_path("image.exr");
+    aovs.write_exr(exr.to_str().unwrap(), &output.beauty).unwrap();
+    let bytes = fs::read(&exr).unwrap();
+    assert_eq!(&bytes[0..4], &[0x76, 0x2f, 0x31, 0x01]);
+
+    aovs.write_files(temp_path("image").to_str().unwrap()).unwrap();
+    for pass in ["albedo", "normal", "depth", "object_id", "direct", "indirect"] {
+        assert!(temp_path(&format!("image.{}.pfm", pass)).exists(), "{} pass is missing", pass);
+    }
+}
+
+#[test]
+fn test_tiles_stitch_into_full_render() {
+    // a wide filter, so the tiles need the samples of the pixels around them
+    let settings = RenderSettings { seed: Some(42), filter: Filter::new(FilterKind::Gaussian), ..settings(true) };
+    let full = render(&small_camera(), &small_scene(), &settings);
+
+    let (columns, rows) = (3, 2);
+    let tiles: Vec<Framebuffer> = (0..columns * rows).map(|index| {
+        let region = Region::tile(20, 10, columns, rows, index);
+        let output = render(&small_camera(), &small_scene(), &RenderSettings { region: Some(region), ..settings });
+        // only the pixels of the region are kept
+        assert_eq!((output.beauty.width, output.beauty.height), (region.width, region.height));
+        assert_eq!(output.aovs.unwrap().albedo.pixels, full.aovs.as_ref().unwrap().albedo.crop(&region).pixels);
+        // and put back in place, black around them
+        let padded = output.beauty.pad(&region, 20, 10);
+        assert!((0..200).all(|i| region.contains(i % 20, i / 20) || padded.pixels[i] == Color::new_empty()));
+        assert_eq!(padded.crop(&region).pixels, output.beauty.pixels);
+        output.beauty
+    }).collect();
+    assert_eq!((tiles[0].width, tiles[0].height), (6, 5));
+    assert_eq!((tiles[5].width, tiles[5].height), (7, 5));
+
+    let stitched = Framebuffer::stitch(&tiles, columns, rows).unwrap();
+    assert_eq!(stitched.pixels, full.beauty.pixels);
+    assert!(Framebuffer::stitch(&tiles[1..], columns, rows).is_err());
+}
+
+// cancels the render after `rows` scanlines
+struct CancelAfter {
+    rows: u64,
+    done: AtomicU64,
+    cancel: CancellationToken
+}
+
+impl Progress for CancelAfter {
+    fn start(&self, _task: &str, _total: u64) {}
+    fn advance(&self, units: u64) {
+        if self.done.fetch_add(units, Ordering::Relaxed) + units >= self.rows {
+            self.cancel.cancel();
+        }
+    }
+    fn finish(&self) {}
+}
+
+#[test]
+fn test_cancel_and_time_budget() {
+    let settings = settings(true);
+
+    // nothing rendered
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let output = render::render_with_progress(&small_camera(), &small_scene(), &settings, &Quiet, &cancel);
+    assert_eq!(output.samples_per_pixel, 0);
+    assert!(output.beauty.pixels.iter().all(|pixel| *pixel == Color::new_empty()));
+
+    // the first pass of 2 samples and the top half of the second one
+    let progress = CancelAfter { rows: 15, done: AtomicU64::new(0), cancel: CancellationToken::new() };
+    let mut passes = 0;
+    let output = render::render_progressive(&small_camera(), &small_scene(), &settings, 2, &progress, &progress.cancel, |_| {
+        passes += 1;
+        true
+    });
+    assert_eq!(passes, 2);
+    assert_eq!(output.samples_per_pixel, 2);
+    assert_eq!(output.counters.camera_rays, 2 * 200 + 2 * 100);
+    // the passes are averaged by the samples of each row, the top rows have 4 and the bottom ones 2
+    let aovs = output.aovs.unwrap();
+    for y in [4, 5] {
+        assert!((aovs.albedo.get(10, y) - Color::new(0.1, 0.2, 0.5)).length() < 1e-3, "{:?}", aovs.albedo.get(10, y));
+    }
+
+    // as many samples as fit in the budget
+    let budget = RenderSettings { samples_per_pixel: 1 << 20, time_budget: Some(Duration::from_millis(300)), ..settings };
+    let start = Instant::now();
+    let output = render::render_progressive(&small_camera(), &small_scene(), &budget, 1, &Quiet, &CancellationToken::new(), |_| true);
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert!(output.samples_per_pixel > 0 && output.samples_per_pixel < 1 << 20);
+    assert_eq!(render::progress_units(&budget, 1), 300);
+
+    // a budget past the end of the clock renders every sample instead of overflowing the deadline
+    let endless = RenderSettings { time_budget: Some(Duration::MAX), ..settings };
+    let output = render::render_progressive(&small_camera(), &small_scene(), &endless, 2, &Quiet, &CancellationToken::new(), |_| true);
+    assert_eq!(output.samples_per_pixel, settings.samples_per_pixel);
+    assert_eq!(render::time_budget(1e19), Err("the time budget is limited to 604800 seconds".to_string()));
+    assert_eq!(render::time_budget(1e30), Err("the time budget is limited to 604800 seconds".to_string()));
+    assert_eq!(render::time_budget(2.5), Ok(Duration::from_millis(2500)));
+}